@@ -1,16 +1,38 @@
+use crate::camera_control::CameraControlData;
+use crate::capabilities::Topology;
+use crate::downstream_keyer::DownstreamKeyer;
+use crate::fairlight::{
+    FairlightCompressor, FairlightEqualizerBand, FairlightExpander, FairlightInputProperties,
+    FairlightLimiter, FairlightMasterLevels, FairlightMasterProperties, FairlightMonitor,
+    FairlightSolo, FairlightSourceLevels, FairlightSourceProperties,
+};
+use crate::input::InputProperties;
+use crate::macros::{Macro, MacroPlayer};
+use crate::media_pool::{MediaPlayerSource, MediaPoolStill, MEDIA_POOL_STILL_BANK};
+use crate::multiview;
 use crate::payload::Payload;
+use crate::protocol_version::ProtocolVersion;
+use crate::recording::{RecordingDisk, RecordingSettings, RecordingStatus};
+use crate::streaming::{StreamingService, StreamingStatus};
+use crate::supersource::{SuperSourceArt, SuperSourceBox};
+use crate::tally;
+use crate::timecode::Timecode;
+use crate::video_mode::VideoMode;
 
 // hello
 // [16, 20, 0, 0, 0, 0, 0, 0, 0, 58, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]
 // response
 // [16, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 110, 0, 0, 0, 0]
-const SIZE_OF_HEADER: usize = 0x0c;
+pub(crate) const SIZE_OF_HEADER: usize = 0x0c;
 
-const COMMAND_MASK_ACK_REQUEST: u8 = 0x01;
-const COMMAND_MASK_HELLO: u8 = 0x02;
-const COMMAND_MASK_RESEND: u8 = 0x04;
-const COMMAND_MASK_REQUEST_NEXT: u8 = 0x08;
-const COMMAND_MASK_ACK: u8 = 0x10;
+pub(crate) const COMMAND_MASK_ACK_REQUEST: u8 = 0x01;
+pub(crate) const COMMAND_MASK_HELLO: u8 = 0x02;
+pub(crate) const COMMAND_MASK_RESEND: u8 = 0x04;
+pub(crate) const COMMAND_MASK_REQUEST_NEXT: u8 = 0x08;
+pub(crate) const COMMAND_MASK_ACK: u8 = 0x10;
+
+// package ids count in 15 bits
+pub(crate) const PACKAGE_ID_MASK: u16 = 0x7fff;
 
 #[derive(Debug, Default, PartialEq)]
 pub struct AtemCommandHeader {
@@ -154,12 +176,6 @@ impl Default for AtemCommandPayload {
 
 const IGNORED_CHUNKS: &'static [&str] = &[
     "Time",
-    "CCdP", // camera
-    "FTDC", // finished transfer data
-    "_MeC", // mix effects
-    "_mpl", // media pool
-    "_MvC", // multi view (count?)
-    "_SSC",
     "_FAC",
     "_FEC",
     "_FMH",
@@ -169,11 +185,7 @@ const IGNORED_CHUNKS: &'static [&str] = &[
     "AiVM",
     "TcLK",
     "TCCc",
-    "MvVM",
-    "MvPr", // multi view "program"?
-    "MvIn", // multi view input?
     "VuMC",
-    "SaMw",
     "VuMo",
     "TrSS", // transition
     "TrPr",
@@ -197,53 +209,36 @@ const IGNORED_CHUNKS: &'static [&str] = &[
     "DskP",
     "FtbP",
     "FtbS",
-    "MPfe", // media player
-    "MPCE",
     "CapA",
+    // HyperDeck settings, player, storage and clips: the disk recorders a switcher controls
+    // over the network, not the Mini Pro's own recorder, which is RTMS/RTMR/RMSu/RMRD
     "RXMS",
     "RXCP",
     "RXSS",
     "RXCC",
-    "SSrc",
-    "SSBP",
-    "FMPP",
-    "FAIP",
     "FIEP",
-    "AEBP",
-    "AIXP",
-    "AICP",
-    "AILP",
-    "FASP",
     "FMTl",
-    "FAMP",
-    "AMBP",
-    "MOCP",
-    "AMLP",
-    "FMHP",
-    "FAMS",
-    "TlSr", // tally state? !
     "TlFc",
     //	"MRPr",	// macro run?
     "MRcS", // macro recording?
     "CCst",
-    "RMSu",
-    "RTMS",
-    "RTMR",
-    "RMRD",
-    "SRSU",
-    "STAB",
-    "StRS",
-    "SRST",
-    "SRSD",
+    "SRSD", // layout unknown, status, duration and disks work without it
     "SRRS",
     "SAth",
-    "SLow",
     "NIfT",
     "LKST",
-    "SRSS",
 ];
 
-fn word_at(buffer: &[u8], index: usize) -> u16 {
+pub(crate) fn byte_at(buffer: &[u8], index: usize) -> u8 {
+    if index >= buffer.len() {
+        println!("Tried to read past end of buffer");
+        0
+    } else {
+        buffer[index]
+    }
+}
+
+pub(crate) fn word_at(buffer: &[u8], index: usize) -> u16 {
     if index + 1 >= buffer.len() {
         println!("Tried to read past end of buffer");
         0
@@ -252,7 +247,33 @@ fn word_at(buffer: &[u8], index: usize) -> u16 {
     }
 }
 
-fn string_at(buffer: &[u8], index: usize, len: Option<usize>) -> String {
+pub(crate) fn signed_word_at(buffer: &[u8], index: usize) -> i16 {
+    word_at(buffer, index) as i16
+}
+
+pub(crate) fn long_at(buffer: &[u8], index: usize) -> u32 {
+    if index + 3 >= buffer.len() {
+        println!("Tried to read past end of buffer");
+        0
+    } else {
+        u32::from_be_bytes([
+            buffer[index],
+            buffer[index + 1],
+            buffer[index + 2],
+            buffer[index + 3],
+        ])
+    }
+}
+
+pub(crate) fn signed_long_at(buffer: &[u8], index: usize) -> i32 {
+    long_at(buffer, index) as i32
+}
+
+pub(crate) fn signed_long_long_at(buffer: &[u8], index: usize) -> i64 {
+    (((long_at(buffer, index) as u64) << 32) | (long_at(buffer, index + 4) as u64)) as i64
+}
+
+pub(crate) fn string_at(buffer: &[u8], index: usize, len: Option<usize>) -> String {
     let b = if let Some(len) = len {
         &buffer[index..index + len]
     } else {
@@ -262,8 +283,22 @@ fn string_at(buffer: &[u8], index: usize, len: Option<usize>) -> String {
     String::from_utf8_lossy(b).to_string()
 }
 
+/// Reads a zero padded string of `len` bytes, empty if the chunk is too short.
+pub(crate) fn fixed_string_at(buffer: &[u8], index: usize, len: usize) -> String {
+    if buffer.len() < index + len {
+        return String::new();
+    }
+    let s = &buffer[index..index + len];
+    let end = s.iter().position(|&b| b == 0).unwrap_or(len);
+    string_at(s, 0, Some(end))
+}
+
 impl AtemCommandPayload {
-    pub fn from_buffer(buffer: &[u8]) -> Option<AtemCommandPayload> {
+    /// `protocol_version` selects the chunk layouts, it is updated when the buffer contains _ver.
+    pub fn from_buffer(
+        buffer: &[u8],
+        protocol_version: &mut Option<ProtocolVersion>,
+    ) -> Option<AtemCommandPayload> {
         let mut p = AtemCommandPayload::default();
         p.buffer = buffer.into();
 
@@ -277,14 +312,15 @@ impl AtemCommandPayload {
 
             //			println!("{:?}", &buffer[ o+0 .. l ] );
             //		    let size = ( ( buffer[ o+0 ] as u16 ) << 8 ) | ( buffer[ o+1 ] as u16 );
-            let size = word_at(&buffer, o);
-            if size == 0 {
+            let size = word_at(&buffer, o) as usize;
+            // a truncated or garbled packet, the rest can't be trusted
+            if size < 8 || o + size > buffer.len() {
                 break;
             }
             //		    println!("Chunk Size: {:#04x} from {:#02x} {:#02x} {} {}", size, buffer[ o+1 ], buffer[ o+0 ], buffer[ o+1 ], buffer[ o+0 ]);
 
             let s = o + 2;
-            let e = s + (size as usize) - 2;
+            let e = s + size - 2;
             let chunk = &buffer[o + 2..e];
             //		    println!("Chunk: {:?}", &chunk );
             let mut name = [0; 4];
@@ -302,83 +338,101 @@ impl AtemCommandPayload {
                     println!("InCm: {:?}", &chunk);
                 }
                 "_ver" => {
-                    let maj = word_at(&buffer, 6);
-                    let min = word_at(&buffer, 8);
-                    println!("Got version {}.{}", maj, min);
+                    let version = ProtocolVersion {
+                        major: word_at(chunk, 6),
+                        minor: word_at(chunk, 8),
+                    };
+                    println!("Got version {}", version);
+                    if !version.is_supported() {
+                        println!(
+                            "Protocol version {} is older than {}, can't decode its state",
+                            version,
+                            ProtocolVersion::OLDEST
+                        );
+                    } else if !version.is_known() {
+                        println!(
+                            "Unknown protocol version {}, decoding it like {}",
+                            version,
+                            ProtocolVersion::NEWEST
+                        );
+                    }
+                    *protocol_version = Some(version);
+                    p.payloads.push(Payload::ProtocolVersion(version));
                 }
                 "_pin" => {
                     let pin = string_at(&chunk, 6, None);
                     println!("Got pin >{}<", pin);
                 }
                 "_top" => {
-                    let me_count = chunk[6];
-                    let source_count = chunk[7];
-                    let colgen_count = chunk[8];
-                    let auxbus_count = chunk[9];
-                    // 10?
-                    let dsk_count = chunk[11];
-                    // 12?
-                    let usk_count = chunk[13];
-                    let stinger_count = chunk[14];
-                    let dve_count = chunk[15];
-                    let ss_count = chunk[16];
-                    let sd = chunk[17];
                     println!("Got Topology");
+                    p.payloads.push(Payload::Topology(Topology::from_data(
+                        &chunk[6..],
+                        *protocol_version,
+                    )));
+                }
+                "_MeC" => {
+                    p.payloads.push(Payload::MixEffectConfig {
+                        me: byte_at(chunk, 6),
+                        keyer_count: byte_at(chunk, 7),
+                    });
                 }
                 "_TlC" => {
-                    let c = word_at(&chunk, 6);
+                    let c = word_at(chunk, 6);
                     println!("Tally Channel Count: {}", c);
+                    p.payloads.push(Payload::TallyChannelCount(c));
                 }
                 "AuxS" => {
                     println!("Got Auxiliary Source");
-                    let i = chunk[6];
+                    let i = byte_at(chunk, 6);
                     let v = word_at(&buffer, 8);
                     println!("{} -> {}", i, v);
                 }
                 "DskS" => {
-                    println!("Got Downstream Keyer");
-                    let i = chunk[6];
-                    let on = chunk[7];
-                    let trans = chunk[8];
-                    let auto_trans = chunk[9];
-                    let frame = chunk[9];
-                    let v = word_at(&buffer, 8);
-                    println!("{} -> {} ({}/{}/{})", i, on, trans, auto_trans, v);
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::DownstreamKeyer {
+                        index: data[0],
+                        keyer: DownstreamKeyer::from_data(data),
+                    });
                 }
                 "TlIn" => {
-                    println!("Got Tally Info");
-                    let count = (((chunk[6] as u16) << 8) | (chunk[7] as u16)) as usize;
-                    println!("Count: {}", count);
-                    for i in 0..count {
-                        let t = chunk[8 + i];
-                        println!("{} -> {}", i, t);
-                    }
+                    p.payloads
+                        .push(Payload::TallyByIndex(tally::tally_by_index_from_data(
+                            &chunk[6..],
+                        )));
+                }
+                "TlSr" => {
+                    p.payloads
+                        .push(Payload::TallyBySource(tally::tally_by_source_from_data(
+                            &chunk[6..],
+                        )));
                 }
                 "InPr" => {
-                    let i = word_at(&chunk, 6);
-                    let lt = string_at(&chunk, 8, Some(20));
-                    let st = string_at(&chunk, 28, Some(4));
-                    let et = chunk[37];
-                    let it = chunk[38];
-                    let avail = chunk[40];
-                    let mea = chunk[41];
-
-                    println!("Input: {:>8} {:<4} | {:<20}", i, st, lt);
+                    let data = &chunk[6..];
+                    let input = word_at(data, 0);
+                    let properties = InputProperties::from_data(data, *protocol_version);
+                    println!(
+                        "Input: {:>8} {:<4} | {:<20}",
+                        input, properties.short_name, properties.long_name
+                    );
+                    p.payloads
+                        .push(Payload::InputProperties { input, properties });
                 }
                 "PrgI" => {
-                    let me = chunk[6];
-                    let input = word_at(&chunk, 8);
+                    let me = byte_at(chunk, 6);
+                    let input = word_at(chunk, 8);
                     println!("Program Input: {} -> {}", me, input);
+                    p.payloads.push(Payload::ProgramInput { me, input });
                 }
                 "PrvI" => {
-                    let me = chunk[6];
-                    let input = word_at(&chunk, 8);
+                    let me = byte_at(chunk, 6);
+                    let input = word_at(chunk, 8);
                     println!("Preview Input: {} -> {}", me, input);
+                    p.payloads.push(Payload::PreviewInput { me, input });
                 }
                 "KeOn" => {
-                    let w = chunk[6];
-                    let i = chunk[7];
-                    let s = chunk[8];
+                    let w = byte_at(chunk, 6);
+                    let i = byte_at(chunk, 7);
+                    let s = byte_at(chunk, 8);
 
                     println!("KeOn {} {} {}", w, i, s);
                     p.payloads.push(Payload::KeOn {
@@ -388,42 +442,33 @@ impl AtemCommandPayload {
                     });
                 }
                 "_MAC" => {
-                    let c = chunk[6];
+                    let c = byte_at(chunk, 6);
                     println!("Got Macro Count: {}", c);
+                    p.payloads.push(Payload::MacroCount(c));
                 }
                 "MPrp" => {
-                    // macro?
-                    let i = chunk[7];
-                    let u = chunk[8];
-                    let name_len = (((chunk[10] as u16) << 8) | (chunk[11] as u16)) as usize;
-                    let body_len = (((chunk[12] as u16) << 8) | (chunk[13] as u16)) as usize;
-
-                    let name = if name_len > 0 {
-                        let n = &chunk[14..14 + name_len];
-                        String::from_utf8_lossy(&n)
-                    } else {
-                        String::from_utf8_lossy(&[])
-                    };
-                    let body = if body_len > 0 {
-                        let b = &chunk[14 + name_len..14 + name_len + body_len];
-                        String::from_utf8_lossy(&b)
-                    } else {
-                        String::from_utf8_lossy(&[])
-                    };
-                    if u > 0 {
-                        println!("Macro: {:>4} {}\n{}", i, name, body);
+                    let data = &chunk[6..];
+                    let index = word_at(data, 0);
+                    let m = Macro::from_data(data);
+                    if m.is_used {
+                        println!("Macro: {:>4} {}\n{}", index, m.name, m.description);
                     }
+                    p.payloads.push(Payload::MacroProperties {
+                        index,
+                        properties: m,
+                    });
                 }
                 "VidM" => {
-                    let m = chunk[6];
-                    let n = match m {
-                        27 => "1080p60".to_string(),
-                        o => format!("unknown {}", o),
+                    let m = byte_at(chunk, 6);
+                    let n = match VideoMode(m).name() {
+                        Some(n) => n.to_string(),
+                        None => format!("unknown {}", m),
                     };
                     println!("Video Mode: {} -> {}", m, n);
+                    p.payloads.push(Payload::VideoMode(VideoMode(m)));
                 }
                 "ColV" => {
-                    let i = chunk[6];
+                    let i = byte_at(chunk, 6);
                     let h = word_at(&chunk, 8);
                     let s = word_at(&chunk, 10);
                     let l = word_at(&chunk, 12);
@@ -435,10 +480,300 @@ impl AtemCommandPayload {
                     println!("Col Gen: {} -> {}/{}/{}", i, h, s, l);
                 }
                 "MRPr" => {
-                    let s = chunk[6];
-                    let r = chunk[7];
-                    let m = chunk[9];
-                    println!("Macro Running: {} -> {}/{}", m, s, r);
+                    let player = MacroPlayer::from_data(&chunk[6..]);
+                    println!("Macro Running: {:?}", player);
+                    p.payloads.push(Payload::MacroPlayer(player));
+                }
+                "FAIP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightInput {
+                        input: word_at(data, 0),
+                        properties: FairlightInputProperties::from_data(data),
+                    });
+                }
+                "FASP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightSource {
+                        input: word_at(data, 0),
+                        source: signed_long_long_at(data, 8),
+                        properties: FairlightSourceProperties::from_data(data),
+                    });
+                }
+                "FAMP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightMaster(
+                        FairlightMasterProperties::from_data(data),
+                    ));
+                }
+                "AEBP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightSourceEqualizerBand {
+                        input: word_at(data, 0),
+                        source: signed_long_long_at(data, 8),
+                        band: byte_at(data, 16),
+                        properties: FairlightEqualizerBand::from_data(
+                            data.get(17..).unwrap_or(&[]),
+                        ),
+                    });
+                }
+                "AICP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightSourceCompressor {
+                        input: word_at(data, 0),
+                        source: signed_long_long_at(data, 8),
+                        compressor: FairlightCompressor::from_data(data.get(16..).unwrap_or(&[])),
+                    });
+                }
+                "AILP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightSourceLimiter {
+                        input: word_at(data, 0),
+                        source: signed_long_long_at(data, 8),
+                        limiter: FairlightLimiter::from_data(data.get(16..).unwrap_or(&[])),
+                    });
+                }
+                "AIXP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightSourceExpander {
+                        input: word_at(data, 0),
+                        source: signed_long_long_at(data, 8),
+                        expander: FairlightExpander::from_data(data.get(16..).unwrap_or(&[])),
+                    });
+                }
+                "AMBP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightMasterEqualizerBand {
+                        band: byte_at(data, 0),
+                        properties: FairlightEqualizerBand::from_data(data.get(1..).unwrap_or(&[])),
+                    });
+                }
+                "MOCP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightMasterCompressor(
+                        FairlightCompressor::from_data(data),
+                    ));
+                }
+                "AMLP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightMasterLimiter(
+                        FairlightLimiter::from_data(data),
+                    ));
+                }
+                "FMLv" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightSourceLevels {
+                        input: word_at(data, 0),
+                        source: signed_long_long_at(data, 8),
+                        levels: FairlightSourceLevels::from_data(data),
+                    });
+                }
+                "FDLv" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::FairlightMasterLevels(
+                        FairlightMasterLevels::from_data(data),
+                    ));
+                }
+                "FMHP" => {
+                    let data = &chunk[6..];
+                    p.payloads
+                        .push(Payload::FairlightMonitor(FairlightMonitor::from_data(data)));
+                }
+                "FAMS" => {
+                    let data = &chunk[6..];
+                    p.payloads
+                        .push(Payload::FairlightSolo(FairlightSolo::from_data(data)));
+                }
+                "_mpl" => {
+                    p.payloads.push(Payload::MediaPoolConfig {
+                        still_count: byte_at(chunk, 6),
+                        clip_count: byte_at(chunk, 7),
+                    });
+                }
+                "MPfe" => {
+                    let data = &chunk[6..];
+                    if byte_at(data, 0) == MEDIA_POOL_STILL_BANK {
+                        p.payloads.push(Payload::MediaPoolStill {
+                            index: word_at(data, 2),
+                            still: MediaPoolStill::from_data(data),
+                        });
+                    }
+                }
+                "MPCE" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::MediaPlayerSource {
+                        player: byte_at(data, 0),
+                        source: MediaPlayerSource::from_data(data),
+                    });
+                }
+                "LKOB" => {
+                    p.payloads.push(Payload::LockObtained {
+                        store_id: word_at(chunk, 6),
+                    });
+                }
+                "FTCD" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::DataTransferContinue {
+                        transfer_id: word_at(data, 0),
+                        chunk_size: word_at(data, 6),
+                        chunk_count: word_at(data, 8),
+                    });
+                }
+                "FTDa" => {
+                    let data = &chunk[6..];
+                    let size = (word_at(data, 2) as usize).min(data.len().saturating_sub(4));
+                    p.payloads.push(Payload::DataTransferData {
+                        transfer_id: word_at(data, 0),
+                        data: data[4..4 + size].to_vec(),
+                    });
+                }
+                "FTDC" => {
+                    p.payloads.push(Payload::DataTransferComplete {
+                        transfer_id: word_at(chunk, 6),
+                    });
+                }
+                "FTDE" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::DataTransferError {
+                        transfer_id: word_at(data, 0),
+                        error_code: byte_at(data, 2),
+                    });
+                }
+                "StRS" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::StreamingStatus {
+                        status: StreamingStatus::from_u16(word_at(data, 0)),
+                        error: word_at(data, 2),
+                    });
+                }
+                "SRST" => {
+                    p.payloads
+                        .push(Payload::StreamingDuration(Timecode::from_data(&chunk[6..])));
+                }
+                "SRSS" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::StreamingStats {
+                        encoding_bitrate: long_at(data, 0),
+                        cache_used: word_at(data, 4),
+                    });
+                }
+                "SRSU" => {
+                    p.payloads
+                        .push(Payload::StreamingService(StreamingService::from_data(
+                            &chunk[6..],
+                        )));
+                }
+                "STAB" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::StreamingAudioBitrates {
+                        low: long_at(data, 0),
+                        high: long_at(data, 4),
+                    });
+                }
+                "SLow" => {
+                    p.payloads
+                        .push(Payload::StreamingLowBandwidth(byte_at(chunk, 6) > 0));
+                }
+                "RTMS" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::RecordingStatus {
+                        status: RecordingStatus::from_u16(word_at(data, 0)),
+                        error: word_at(data, 2),
+                        recording_time_available: long_at(data, 4),
+                    });
+                }
+                "RTMR" => {
+                    p.payloads
+                        .push(Payload::RecordingDuration(Timecode::from_data(&chunk[6..])));
+                }
+                "RMSu" => {
+                    p.payloads
+                        .push(Payload::RecordingSettings(RecordingSettings::from_data(
+                            &chunk[6..],
+                        )));
+                }
+                "RMRD" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::RecordingDisk {
+                        disk_id: long_at(data, 0),
+                        disk: RecordingDisk::from_data(data),
+                    });
+                }
+                "CCdP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::CameraControl {
+                        input: byte_at(data, 0),
+                        category: byte_at(data, 1),
+                        parameter: byte_at(data, 2),
+                        data: CameraControlData::from_data(data, 3),
+                    });
+                }
+                "_MvC" => {
+                    p.payloads.push(Payload::MultiviewConfig {
+                        multiview_count: byte_at(chunk, 6),
+                        window_count: byte_at(chunk, 7),
+                    });
+                }
+                "MvPr" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::MultiviewProperties {
+                        multiview: byte_at(data, 0),
+                        layout: byte_at(data, 1),
+                        program_preview_swapped: byte_at(data, 2) > 0,
+                    });
+                }
+                "MvIn" => {
+                    let data = &chunk[6..];
+                    let (source, supports_vu_meter, supports_safe_area) =
+                        multiview::window_source_from_data(data);
+                    p.payloads.push(Payload::MultiviewWindowSource {
+                        multiview: byte_at(data, 0),
+                        window: byte_at(data, 1),
+                        source,
+                        supports_vu_meter,
+                        supports_safe_area,
+                    });
+                }
+                "MvVM" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::MultiviewVuMeter {
+                        multiview: byte_at(data, 0),
+                        window: byte_at(data, 1),
+                        enabled: byte_at(data, 2) > 0,
+                    });
+                }
+                "SaMw" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::MultiviewSafeArea {
+                        multiview: byte_at(data, 0),
+                        window: byte_at(data, 1),
+                        enabled: byte_at(data, 2) > 0,
+                    });
+                }
+                "_SSC" => {
+                    p.payloads.push(Payload::SuperSourceConfig {
+                        supersource: byte_at(chunk, 6),
+                        box_count: byte_at(chunk, 8),
+                    });
+                }
+                "SSrc" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::SuperSourceArt {
+                        supersource: byte_at(data, 0),
+                        art: SuperSourceArt::from_data(data),
+                    });
+                }
+                "SSBP" => {
+                    let data = &chunk[6..];
+                    p.payloads.push(Payload::SuperSourceBox {
+                        supersource: byte_at(data, 0),
+                        index: byte_at(data, 1),
+                        properties: SuperSourceBox::from_data(data),
+                    });
+                }
+                "FMPP" => {
+                    let crossfade = byte_at(chunk, 6) > 0;
+                    p.payloads
+                        .push(Payload::FairlightMasterCrossfade(crossfade));
                 }
                 o => {
                     if IGNORED_CHUNKS.contains(&o) {
@@ -448,7 +783,7 @@ impl AtemCommandPayload {
                     }
                 }
             }
-            o += size as usize;
+            o += size;
         }
 
         Some(p)
@@ -464,6 +799,35 @@ impl AtemCommandPayload {
         self.dirty = true
     }
 
+    pub fn set_bytes(&mut self, index: usize, values: &[u8]) {
+        self.buffer[index..index + values.len()].copy_from_slice(values);
+        self.dirty = true
+    }
+
+    pub fn set_word(&mut self, index: usize, value: u16) {
+        self.set_bytes(index, &value.to_be_bytes());
+    }
+
+    pub fn set_signed_word(&mut self, index: usize, value: i16) {
+        self.set_bytes(index, &value.to_be_bytes());
+    }
+
+    pub fn set_long(&mut self, index: usize, value: u32) {
+        self.set_bytes(index, &value.to_be_bytes());
+    }
+
+    pub fn set_signed_long(&mut self, index: usize, value: i32) {
+        self.set_bytes(index, &value.to_be_bytes());
+    }
+
+    pub fn set_signed_long_long(&mut self, index: usize, value: i64) {
+        self.set_bytes(index, &value.to_be_bytes());
+    }
+
+    pub fn payloads(&self) -> &[Payload] {
+        &self.payloads
+    }
+
     pub fn update_buffer(&mut self) {
         self.dirty = false;
     }
@@ -500,7 +864,10 @@ impl Default for AtemCommand {
 }
 
 impl AtemCommand {
-    pub fn from_buffer(buffer: &[u8]) -> Option<AtemCommand> {
+    pub fn from_buffer(
+        buffer: &[u8],
+        protocol_version: &mut Option<ProtocolVersion>,
+    ) -> Option<AtemCommand> {
         if buffer.len() < 12 {
             None
         } else {
@@ -511,7 +878,7 @@ impl AtemCommand {
             let p = if is_hello {
                 None
             } else {
-                AtemCommandPayload::from_buffer(&bp)
+                AtemCommandPayload::from_buffer(&bp, protocol_version)
             };
 
             if let Some(h) = h {
@@ -580,6 +947,20 @@ impl AtemCommand {
         &mut self.payload
     }
 
+    pub fn payloads(&self) -> &[Payload] {
+        self.payload.payloads()
+    }
+
+    pub fn set_package_id(&mut self, package_id: u16) {
+        self.header.set_package_id(package_id);
+        self.dirty = true;
+    }
+
+    pub fn set_session_id(&mut self, session_id: u16) {
+        self.header.set_session_id(session_id);
+        self.dirty = true;
+    }
+
     fn set_payload_len(&mut self, len: u16) {
         self.header.set_len(len);
         self.payload.set_len(len);
@@ -680,3 +1061,61 @@ pub enum Command {
     RunMacro(u8),
     Shutdown,
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::server::RawChunk;
+
+    /// A packet from the switcher carrying `chunks`.
+    pub(crate) fn packet(chunks: &[RawChunk]) -> Vec<u8> {
+        let mut buffer = vec![0; SIZE_OF_HEADER];
+        for chunk in chunks {
+            chunk.write(&mut buffer);
+        }
+        let len = buffer.len() as u16;
+        buffer[0] = (COMMAND_MASK_ACK_REQUEST << 3) | (len >> 8) as u8;
+        buffer[1] = len as u8;
+        buffer
+    }
+
+    /// Decodes `data` as if the switcher sent it in a chunk named `name`.
+    pub(crate) fn decode(name: &[u8; 4], data: &[u8]) -> Vec<Payload> {
+        let buffer = packet(&[RawChunk::new(name, data)]);
+        let mut version = Some(ProtocolVersion::NEWEST);
+        AtemCommand::from_buffer(&buffer, &mut version)
+            .unwrap()
+            .payloads()
+            .to_vec()
+    }
+
+    /// A command as it goes to the switcher.
+    pub(crate) fn command_chunk(mut c: AtemCommand) -> RawChunk {
+        c.update_buffer();
+        let mut chunks = RawChunk::split(&c.buffer()[SIZE_OF_HEADER..]);
+        assert_eq!(chunks.len(), 1);
+        chunks.remove(0)
+    }
+
+    /// The data of a command as it goes to the switcher, after its length and name.
+    pub(crate) fn command_data(c: AtemCommand) -> Vec<u8> {
+        command_chunk(c).data
+    }
+
+    // chunks whose decoders must cope with whatever length the switcher sends
+    const CHECKED_CHUNKS: &[&[u8; 4]] = &[
+        b"FAIP", b"FASP", b"FAMP", b"FMPP", b"AEBP", b"AICP", b"AILP", b"AIXP", b"AMBP", b"MOCP",
+        b"AMLP", b"FMLv", b"FDLv", b"FMHP", b"FAMS", b"_mpl", b"MPfe", b"MPCE", b"StRS", b"SRST",
+        b"SRSS", b"SRSU", b"STAB", b"SLow", b"RTMS", b"RTMR", b"RMSu", b"RMRD", b"CCdP", b"_MvC",
+        b"MvPr", b"MvIn", b"MvVM", b"SaMw", b"_SSC", b"SSrc", b"SSBP", b"_MeC",
+    ];
+
+    #[test]
+    fn truncated_chunks_decode() {
+        for name in CHECKED_CHUNKS {
+            for len in 0..24 {
+                decode(name, &vec![0xff; len]);
+            }
+        }
+    }
+}
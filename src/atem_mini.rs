@@ -7,14 +7,60 @@ use crate::atem_command::{
     //	AtemResponse,
     Command,
     //	CommandId,
+    PACKAGE_ID_MASK,
 };
 
+use crate::atem_state::AtemState;
+use crate::camera_control::{self, CameraControlData, Rgby};
+use crate::capabilities::{self, Capabilities};
+use crate::capture::{CaptureWriter, CapturedPacket, Direction};
+use crate::data_transfer::{self, DataTransfer, PendingTransfer, RunningTransfer};
+use crate::downstream_keyer;
+use crate::fairlight::{
+    self, AudioLevels, FairlightCompressor, FairlightEqualizerBand, FairlightExpander,
+    FairlightLimiter, FairlightMixOption, FairlightMonitor,
+};
+use crate::media_pool;
+use crate::mix_effect;
+
+use crate::multiview::{self, MultiviewLayout};
+use crate::payload::Payload;
+use crate::protocol_version::ProtocolVersion;
+use crate::recording;
+use crate::server::RawChunk;
+use crate::still_image;
+use crate::streaming;
+use crate::supersource::{self, SuperSourceArt, SuperSourceBox};
+use crate::tally::TallyChange;
+
+use image::imageops::FilterType;
+
 use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, watch};
 
 const REMOTE_ADDR: &str = "192.168.186.101:9910";
 
+const ATEM_PORT: u16 = 9910;
+
 const PACKET_BUFFER_SIZE: usize = 96;
 
+const TALLY_CHANGES_CAPACITY: usize = 64;
+
+// the initial state alone is several hundred chunks
+const RAW_CHUNKS_CAPACITY: usize = 4096;
+
+const TRANSFER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Writes the packet to the capture, a failing capture is dropped so the connection goes on.
+fn record(capture: &mut Option<CaptureWriter>, direction: Direction, data: &[u8]) {
+    if let Some(writer) = capture {
+        if writer.write(CapturedPacket::now(direction, data)).is_err() {
+            // the writer thread already told why
+            *capture = None;
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 struct Connection {
     sock: Option<UdpSocket>,
@@ -22,6 +68,7 @@ struct Connection {
     localId: u16,
     remoteId: u16,
     package_id: u16,
+    protocol_version: Option<ProtocolVersion>,
 }
 
 impl Connection {
@@ -41,20 +88,59 @@ impl Connection {
     pub fn remoteId(&self) -> u16 {
         self.remoteId
     }
+    /// Ids wrap after 15 bits, the top bit isn't part of them on the wire.
+    fn next_package_id(&mut self) -> u16 {
+        self.package_id = (self.package_id + 1) & PACKAGE_ID_MASK;
+        self.package_id
+    }
 }
 
 pub struct AtemMini {
+    remote_addr: String,
     request_tx: Option<mpsc::Sender<Command>>,
-    response_rx: Option<mpsc::Receiver<AtemCommand>>,
+    /// carries the handler's error last when it gives up
+    response_rx: Option<mpsc::Receiver<anyhow::Result<AtemCommand>>>,
     initial_payload_received: bool,
+    state: AtemState,
+    audio_levels: AudioLevels,
+    audio_levels_tx: watch::Sender<AudioLevels>,
+    audio_levels_rx: watch::Receiver<AudioLevels>,
+    tally_tx: broadcast::Sender<TallyChange>,
+    raw_chunks_tx: broadcast::Sender<RawChunk>,
+    capture: Option<CaptureWriter>,
+    transfer: Option<RunningTransfer>,
+    next_transfer_id: u16,
 }
 
 impl AtemMini {
     pub fn new() -> Self {
+        Self::with_address(REMOTE_ADDR)
+    }
+
+    /// `addr` is the switcher's host, with or without port.
+    pub fn with_address(addr: &str) -> Self {
+        let remote_addr = if addr.contains(':') {
+            addr.to_string()
+        } else {
+            format!("{}:{}", addr, ATEM_PORT)
+        };
+        let (audio_levels_tx, audio_levels_rx) = watch::channel(AudioLevels::default());
+        let (tally_tx, _) = broadcast::channel(TALLY_CHANGES_CAPACITY);
+        let (raw_chunks_tx, _) = broadcast::channel(RAW_CHUNKS_CAPACITY);
         Self {
+            remote_addr,
             request_tx: None,
             response_rx: None,
             initial_payload_received: false,
+            state: AtemState::default(),
+            audio_levels: AudioLevels::default(),
+            audio_levels_tx,
+            audio_levels_rx,
+            tally_tx,
+            raw_chunks_tx,
+            capture: None,
+            transfer: None,
+            next_transfer_id: 1,
         }
     }
 
@@ -65,126 +151,173 @@ impl AtemMini {
         self.request_tx = Some(request_tx);
         self.response_rx = Some(response_rx);
 
-        let handle: tokio::task::JoinHandle<anyhow::Result<()>> = tokio::spawn(async move {
-            let mut connection = Connection::default();
+        let remote_addr = self.remote_addr.clone();
+        let mut capture = self.capture.take();
+        tokio::spawn(async move {
+            let error_tx = response_tx.clone();
+            let result: anyhow::Result<()> = async move {
+                let mut connection = Connection::default();
 
-            let local_addr = {
-                let mut rng = rand::thread_rng();
-                let r = rng.gen_range(0..100);
-                format!("0.0.0.0:{}", 55555 + r)
-            };
+                let local_addr = {
+                    let mut rng = rand::thread_rng();
+                    let r = rng.gen_range(0..100);
+                    format!("0.0.0.0:{}", 55555 + r)
+                };
 
-            let socket = UdpSocket::bind(&local_addr).await?;
-            let remote_addr = REMOTE_ADDR;
-            match socket.connect(remote_addr).await {
-                Ok(_) => {
-                    println!("Connected!");
-                }
-                Err(e) => {
-                    println!("Error connecting: {:?}", &e);
-                }
-            };
+                let socket = UdpSocket::bind(&local_addr).await?;
+                match socket.connect(&remote_addr).await {
+                    Ok(_) => {
+                        println!("Connected!");
+                    }
+                    Err(e) => {
+                        println!("Error connecting: {:?}", &e);
+                    }
+                };
 
-            loop {
-                // send outgoing requests
-                let r = request_rx.try_recv();
-                match r {
-                    Ok(cmd) => {
-                        match cmd {
-                            Command::Hello => {
-                                println!("Sending Hello");
-                                let c = AtemCommand::create_hello();
-                                let buf = c.buffer();
-                                println!("{:?}", &buf);
-                                let len = socket.send(&buf[..20]).await?;
-                                //								println!("{:?} bytes sent", len);
-                            }
-                            Command::Ack(session_id, remote_id) => {
-                                /*
-                                let package_id = connection.package_id;
-                                connection.package_id += 1;
-                                */
-                                let package_id = 0;
-                                // :HACK
-                                connection.session_id = session_id;
-                                //								println!("Sending Ack for session {}, remote {}", session_id, remote_id);
-                                let c = AtemCommand::create_ack(package_id, session_id, remote_id);
-                                let buf = c.buffer();
-                                //								println!("{:?}", &buf[..12]);
-                                let len = socket.send(&buf[..12]).await?;
-                                //								println!("{:?} bytes sent", len);
-                            }
-                            Command::Shutdown => {
-                                return Ok(());
+                loop {
+                    // send outgoing requests
+                    loop {
+                        let r = request_rx.try_recv();
+                        match r {
+                            Ok(cmd) => {
+                                match cmd {
+                                    Command::Hello => {
+                                        println!("Sending Hello");
+                                        let c = AtemCommand::create_hello();
+                                        let buf = c.buffer();
+                                        println!("{:?}", &buf);
+                                        let len = socket.send(&buf[..20]).await?;
+                                        record(&mut capture, Direction::Sent, &buf[..20]);
+                                        //								println!("{:?} bytes sent", len);
+                                    }
+                                    Command::Ack(session_id, remote_id) => {
+                                        /*
+                                        let package_id = connection.package_id;
+                                        connection.package_id += 1;
+                                        */
+                                        let package_id = 0;
+                                        // :HACK
+                                        connection.session_id = session_id;
+                                        //								println!("Sending Ack for session {}, remote {}", session_id, remote_id);
+                                        let c = AtemCommand::create_ack(
+                                            package_id, session_id, remote_id,
+                                        );
+                                        let buf = c.buffer();
+                                        //								println!("{:?}", &buf[..12]);
+                                        let len = socket.send(&buf[..12]).await?;
+                                        record(&mut capture, Direction::Sent, &buf[..12]);
+                                        //								println!("{:?} bytes sent", len);
+                                    }
+                                    Command::Shutdown => {
+                                        return Ok(());
+                                    }
+                                    Command::AtemCommand(mut ac) => {
+                                        ac.set_package_id(connection.next_package_id());
+                                        ac.set_session_id(connection.session_id);
+                                        ac.update_buffer();
+                                        socket.send(ac.buffer()).await?;
+                                        record(&mut capture, Direction::Sent, ac.buffer());
+                                    }
+                                    Command::RunMacro(index) => {
+                                        println!(
+                                            "Running Macro {} - {} / {}",
+                                            index, connection.session_id, connection.package_id
+                                        );
+                                        let package_id = connection.next_package_id();
+                                        let session_id = connection.session_id;
+                                        let mut c = AtemCommand::create_command(
+                                            package_id, session_id, b"MAct", 4,
+                                        );
+                                        c.payload().set(1, index);
+                                        c.update_buffer();
+                                        println!("{:?}", &c.buffer());
+                                        let len = socket.send(&c.buffer()).await?;
+                                        record(&mut capture, Direction::Sent, c.buffer());
+                                        /*
+                                            QByteArray cmd("MAct");
+                                            QByteArray payload(4, 0x0);
+
+                                            payload[1] = static_cast<char>(macroIndex);
+
+                                            sendCommand(cmd, payload);
+                                        */
+                                    }
+                                }
                             }
-                            Command::AtemCommand(ac) => {
-                                println!("Sending AtemCommand");
+                            Err(mpsc::TryRecvError::Empty) => {
+                                //						println!("Empty");
+                                break;
                             }
-                            Command::RunMacro(index) => {
-                                println!(
-                                    "Running Macro {} - {} / {}",
-                                    index, connection.session_id, connection.package_id
-                                );
-                                connection.package_id += 1;
-                                let package_id = connection.package_id;
-                                let session_id = connection.session_id;
-                                let mut c =
-                                    AtemCommand::create_command(package_id, session_id, b"MAct", 4);
-                                c.payload().set(1, index);
-                                c.update_buffer();
-                                println!("{:?}", &c.buffer());
-                                let len = socket.send(&c.buffer()).await?;
-                                /*
-                                    QByteArray cmd("MAct");
-                                    QByteArray payload(4, 0x0);
-
-                                    payload[1] = static_cast<char>(macroIndex);
-
-                                    sendCommand(cmd, payload);
-                                */
+                            Err(e) => {
+                                println!("{:?}", &e);
+                                break;
                             }
                         }
                     }
-                    Err(mpsc::TryRecvError::Empty) => {
-                        //						println!("Empty");
-                    }
-                    Err(e) => {
-                        println!("{:?}", &e);
-                    }
-                }
 
-                // handle incomming responses
-                let mut buf = [0; 65535]; //[0;1024];
-                match socket.try_recv(&mut buf) {
-                    Ok(n) => {
-                        if let Some(cmd) = AtemCommand::from_buffer(&buf[..n]) {
-                            //							println!("Response: {:?}", &cmd);
-                            response_tx.send(cmd);
-                        } else {
-                            println!("Unhandled {:?}", &buf[..n]);
-                            panic!("Unhandled Response");
+                    // handle incomming responses, data transfers bring in a lot of them
+                    loop {
+                        let mut buf = [0; 65535]; //[0;1024];
+                        match socket.try_recv(&mut buf) {
+                            Ok(n) => {
+                                record(&mut capture, Direction::Received, &buf[..n]);
+                                if let Some(cmd) = AtemCommand::from_buffer(
+                                    &buf[..n],
+                                    &mut connection.protocol_version,
+                                ) {
+                                    if let Some(v) = connection.protocol_version {
+                                        if !v.is_supported() {
+                                            anyhow::bail!("Refusing protocol version {}", v);
+                                        }
+                                    }
+                                    //							println!("Response: {:?}", &cmd);
+                                    if response_tx.send(Ok(cmd)).is_err() {
+                                        // the AtemMini is gone, nobody is left to listen
+                                        return Ok(());
+                                    }
+                                } else {
+                                    println!("Unhandled {:?}", &buf[..n]);
+                                    panic!("Unhandled Response");
+                                }
+                            }
+                            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                                // no messages
+                                //						println!("?");
+                                break;
+                            }
+                            Err(e) => {
+                                println!("{:?}", &e);
+                                break;
+                            }
                         }
                     }
-                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                        // no messages
-                        //						println!("?");
-                    }
-                    Err(e) => {
-                        println!("{:?}", &e);
-                    }
+                    //				println!("!");
+                    //				tokio::task::yield_now().await;
+                    // sleeping the thread would starve the other tasks on small machines
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
                 }
-                //				println!("!");
-                //				tokio::task::yield_now().await;
-                std::thread::sleep(std::time::Duration::from_millis(10));
+                println!("???");
+                Ok(())
+            }
+            .await;
+            if let Err(e) = result {
+                // fails only when the AtemMini is gone
+                let _ = error_tx.send(Err(e));
             }
-            println!("???");
-            Ok(())
         });
 
         Ok(())
     }
+    /// Logs every datagram of the connection to `path`, call it before `connect()`.
+    ///
+    /// Read it back with `read_capture` and feed it to a `Replay`.
+    pub fn record_to(&mut self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        self.capture = Some(CaptureWriter::create(path)?);
+        Ok(())
+    }
+
     pub fn connect(&mut self) -> anyhow::Result<()> {
-        self.run_handler();
+        self.run_handler()?;
         if let Some(tx) = &mut self.request_tx {
             let cmd = Command::Hello;
             tx.send(cmd)?;
@@ -197,22 +330,781 @@ impl AtemMini {
         true
     }
 
-    pub fn run_macro(&mut self, index: u8) {
+    pub fn state(&self) -> &AtemState {
+        &self.state
+    }
+
+    /// What the switcher reported about itself, known once the initial state has arrived.
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.state.capabilities
+    }
+
+    fn check_fairlight(&self) -> anyhow::Result<()> {
+        if self.state.fairlight.inputs.is_empty() {
+            Err(capabilities::unsupported(
+                "this switcher has no Fairlight audio".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_fairlight_input(&self, input: u16) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        if self.state.fairlight.inputs.contains_key(&input) {
+            Ok(())
+        } else {
+            Err(capabilities::unsupported(format!(
+                "this switcher has no Fairlight input {}",
+                input
+            )))
+        }
+    }
+
+    fn check_media_player(&self, player: u8) -> anyhow::Result<()> {
+        let count = self.state.media_pool.players.len();
+        capabilities::check_index("media player", count, player as usize)
+    }
+
+    fn check_streaming(&self) -> anyhow::Result<()> {
+        if self.state.streaming.status.is_none() {
+            Err(capabilities::unsupported(
+                "this switcher has no streaming encoder".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_recording(&self) -> anyhow::Result<()> {
+        if self.state.recording.status.is_none() {
+            Err(capabilities::unsupported(
+                "this switcher has no recorder".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_multiview(&self, multiview: u8) -> anyhow::Result<()> {
+        let count = self.state.multiview.multiview_count as usize;
+        capabilities::check_index("multiview", count, multiview as usize)
+    }
+
+    fn send_command(&mut self, ac: AtemCommand) -> anyhow::Result<()> {
         if let Some(tx) = &mut self.request_tx {
-            let cmd = Command::RunMacro(index);
-            match tx.send(cmd) {
-                Ok(_) => {}
-                Err(_) => {}
+            tx.send(Command::AtemCommand(ac))?;
+            Ok(())
+        } else {
+            anyhow::bail!("Not connected")
+        }
+    }
+
+    pub fn set_program_input(&mut self, me: u8, input: u16) -> anyhow::Result<()> {
+        self.state.capabilities.check_me(me)?;
+        self.send_command(mix_effect::create_set_program(me, input))
+    }
+
+    pub fn set_preview_input(&mut self, me: u8, input: u16) -> anyhow::Result<()> {
+        self.state.capabilities.check_me(me)?;
+        self.send_command(mix_effect::create_set_preview(me, input))
+    }
+
+    pub fn cut(&mut self, me: u8) -> anyhow::Result<()> {
+        self.state.capabilities.check_me(me)?;
+        self.send_command(mix_effect::create_cut(me))
+    }
+
+    /// Runs the transition selected on `me`.
+    pub fn auto_transition(&mut self, me: u8) -> anyhow::Result<()> {
+        self.state.capabilities.check_me(me)?;
+        self.send_command(mix_effect::create_auto(me))
+    }
+
+    /// Takes upstream keyer `keyer` of `me` on or off air.
+    pub fn set_keyer_on_air(&mut self, me: u8, keyer: u8, on_air: bool) -> anyhow::Result<()> {
+        self.state.capabilities.check_keyer(me, keyer)?;
+        self.send_command(mix_effect::create_set_keyer_on_air(me, keyer, on_air))
+    }
+
+    pub fn set_dsk_on_air(&mut self, index: u8, on_air: bool) -> anyhow::Result<()> {
+        self.state.capabilities.check_dsk(index)?;
+        self.send_command(downstream_keyer::create_set_on_air(index, on_air))
+    }
+
+    /// Runs the mix of downstream keyer `index` on or off air.
+    pub fn dsk_auto(&mut self, index: u8) -> anyhow::Result<()> {
+        self.state.capabilities.check_dsk(index)?;
+        self.send_command(downstream_keyer::create_auto(index))
+    }
+
+    /// Switches the meter stream on or off, the values arrive in `audio_levels()`.
+    pub fn set_audio_levels_enabled(&mut self, enabled: bool) -> anyhow::Result<()> {
+        for c in fairlight::create_send_levels(enabled) {
+            self.send_command(c)?;
+        }
+        Ok(())
+    }
+
+    /// Receiver for the meter values, updated by `update()` whenever level chunks arrive.
+    pub fn audio_levels(&self) -> watch::Receiver<AudioLevels> {
+        self.audio_levels_rx.clone()
+    }
+
+    /// Every change of a source's tally, the full map is in `state().tally`.
+    pub fn tally_changes(&self) -> broadcast::Receiver<TallyChange> {
+        self.tally_tx.subscribe()
+    }
+
+    /// Every chunk the switcher sends, as is and in order, e.g. to pass it on to other clients.
+    ///
+    /// Subscribe before the first `update()` to see the initial state.
+    pub fn raw_chunks(&self) -> broadcast::Receiver<RawChunk> {
+        self.raw_chunks_tx.subscribe()
+    }
+
+    /// Sends a command that was built elsewhere, e.g. by a client of a proxy.
+    pub fn send_raw_chunk(&mut self, chunk: &RawChunk) -> anyhow::Result<()> {
+        let mut c = AtemCommand::create_command(0, 0, &chunk.name, chunk.data.len() as u16);
+        c.payload().set_bytes(0, &chunk.data);
+        self.send_command(c)
+    }
+
+    pub fn set_fairlight_source_gain(
+        &mut self,
+        input: u16,
+        source: i64,
+        gain: f32,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_gain(input, source, gain))
+    }
+
+    pub fn set_fairlight_source_fader_gain(
+        &mut self,
+        input: u16,
+        source: i64,
+        gain: f32,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_fader_gain(input, source, gain))
+    }
+
+    pub fn set_fairlight_source_balance(
+        &mut self,
+        input: u16,
+        source: i64,
+        balance: f32,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_balance(input, source, balance))
+    }
+
+    pub fn set_fairlight_source_mix_option(
+        &mut self,
+        input: u16,
+        source: i64,
+        mix_option: FairlightMixOption,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_mix_option(
+            input, source, mix_option,
+        ))
+    }
+
+    pub fn set_fairlight_source_frames_delay(
+        &mut self,
+        input: u16,
+        source: i64,
+        frames: u8,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_frames_delay(
+            input, source, frames,
+        ))
+    }
+
+    pub fn set_fairlight_source_stereo_simulation(
+        &mut self,
+        input: u16,
+        source: i64,
+        stereo_simulation: f32,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_stereo_simulation(
+            input,
+            source,
+            stereo_simulation,
+        ))
+    }
+
+    pub fn set_fairlight_source_equalizer_enabled(
+        &mut self,
+        input: u16,
+        source: i64,
+        enabled: bool,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_equalizer_enabled(
+            input, source, enabled,
+        ))
+    }
+
+    pub fn set_fairlight_source_equalizer_gain(
+        &mut self,
+        input: u16,
+        source: i64,
+        gain: f32,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_equalizer_gain(
+            input, source, gain,
+        ))
+    }
+
+    pub fn set_fairlight_source_make_up_gain(
+        &mut self,
+        input: u16,
+        source: i64,
+        gain: f32,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_make_up_gain(
+            input, source, gain,
+        ))
+    }
+
+    /// Sends all parameters of `properties`, take the band from `state()` and change what you need.
+    pub fn set_fairlight_source_equalizer_band(
+        &mut self,
+        input: u16,
+        source: i64,
+        band: u8,
+        properties: &FairlightEqualizerBand,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_equalizer_band(
+            input, source, band, properties,
+        ))
+    }
+
+    pub fn set_fairlight_source_compressor(
+        &mut self,
+        input: u16,
+        source: i64,
+        compressor: &FairlightCompressor,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_compressor(
+            input, source, compressor,
+        ))
+    }
+
+    pub fn set_fairlight_source_limiter(
+        &mut self,
+        input: u16,
+        source: i64,
+        limiter: &FairlightLimiter,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_limiter(input, source, limiter))
+    }
+
+    pub fn set_fairlight_source_expander(
+        &mut self,
+        input: u16,
+        source: i64,
+        expander: &FairlightExpander,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_source_expander(
+            input, source, expander,
+        ))
+    }
+
+    pub fn set_fairlight_master_equalizer_enabled(&mut self, enabled: bool) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_master_equalizer_enabled(enabled))
+    }
+
+    pub fn set_fairlight_master_equalizer_gain(&mut self, gain: f32) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_master_equalizer_gain(gain))
+    }
+
+    pub fn set_fairlight_master_make_up_gain(&mut self, gain: f32) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_master_make_up_gain(gain))
+    }
+
+    pub fn set_fairlight_master_equalizer_band(
+        &mut self,
+        band: u8,
+        properties: &FairlightEqualizerBand,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_master_equalizer_band(
+            band, properties,
+        ))
+    }
+
+    pub fn set_fairlight_master_compressor(
+        &mut self,
+        compressor: &FairlightCompressor,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_master_compressor(compressor))
+    }
+
+    pub fn set_fairlight_master_limiter(
+        &mut self,
+        limiter: &FairlightLimiter,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_master_limiter(limiter))
+    }
+
+    pub fn set_fairlight_audio_follow_video_crossfade_transition(
+        &mut self,
+        enabled: bool,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_audio_follow_video_crossfade_transition(enabled))
+    }
+
+    /// Sends all gains of `monitor`, take it from `state()` and change what you need.
+    pub fn set_fairlight_monitor(&mut self, monitor: &FairlightMonitor) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_monitor(monitor))
+    }
+
+    /// Solos `source` of `input` on the headphone/monitor output.
+    pub fn set_fairlight_solo(&mut self, input: u16, source: i64) -> anyhow::Result<()> {
+        self.check_fairlight_input(input)?;
+        self.send_command(fairlight::create_set_solo(true, input, source))
+    }
+
+    pub fn clear_fairlight_solo(&mut self) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_solo(false, 0, 0))
+    }
+
+    pub fn set_fairlight_master_fader_gain(&mut self, gain: f32) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_master_fader_gain(gain))
+    }
+
+    pub fn set_fairlight_master_follow_fade_to_black(
+        &mut self,
+        follow: bool,
+    ) -> anyhow::Result<()> {
+        self.check_fairlight()?;
+        self.send_command(fairlight::create_set_master_follow_fade_to_black(follow))
+    }
+
+    /// Loads media pool still `still` into media player `player`.
+    pub fn set_media_player_source(&mut self, player: u8, still: u8) -> anyhow::Result<()> {
+        self.check_media_player(player)?;
+        let count = self.state.media_pool.still_count as usize;
+        capabilities::check_index("still", count, still as usize)?;
+        self.send_command(media_pool::create_set_media_player_still(player, still))
+    }
+
+    pub fn set_media_player_clip(&mut self, player: u8, clip: u8) -> anyhow::Result<()> {
+        self.check_media_player(player)?;
+        let count = self.state.media_pool.clip_count as usize;
+        capabilities::check_index("clip", count, clip as usize)?;
+        self.send_command(media_pool::create_set_media_player_clip(player, clip))
+    }
+
+    /// Uploads an image file into media pool still `slot`.
+    ///
+    /// The image is scaled to the current video mode and converted to the switcher's format.
+    /// The transfer runs with `update()`, wait for the switcher to confirm it with the result.
+    pub fn upload_still(
+        &mut self,
+        slot: u16,
+        path: impl AsRef<std::path::Path>,
+        name: &str,
+    ) -> anyhow::Result<PendingTransfer<()>> {
+        let (width, height) = self
+            .state
+            .video_mode
+            .and_then(|m| m.resolution())
+            .ok_or_else(|| anyhow::anyhow!("Unknown video mode, can't scale still"))?;
+
+        let image = image::open(path)?
+            .resize_exact(width, height, FilterType::Triangle)
+            .to_rgba8();
+        let data = still_image::rgba_to_yuv422(&image);
+
+        let transfer_id = self.next_transfer_id;
+        self.next_transfer_id = self.next_transfer_id.wrapping_add(1);
+        let transfer = DataTransfer::upload(
+            transfer_id,
+            data_transfer::STILL_STORE_ID,
+            slot,
+            name,
+            "",
+            &data,
+        );
+        Ok(self.start_transfer(transfer)?.map(|_| Ok(())))
+    }
+
+    /// Downloads media pool still `slot`, the result is converted to RGBA.
+    ///
+    /// The transfer runs with `update()`, wait for the image with the result.
+    pub fn download_still(
+        &mut self,
+        slot: u16,
+    ) -> anyhow::Result<PendingTransfer<image::RgbaImage>> {
+        let pool = &self.state.media_pool;
+        if slot >= pool.still_count as u16 {
+            anyhow::bail!(
+                "Still slot {} doesn't exist, the media pool has {} stills",
+                slot,
+                pool.still_count
+            );
+        }
+        match pool.stills.get(&slot) {
+            Some(still) if still.is_used => {}
+            _ => anyhow::bail!("Still slot {} is empty", slot),
+        }
+
+        let (width, height) = self
+            .state
+            .video_mode
+            .and_then(|m| m.resolution())
+            .ok_or_else(|| anyhow::anyhow!("Unknown video mode, can't decode still"))?;
+
+        let transfer_id = self.next_transfer_id;
+        self.next_transfer_id = self.next_transfer_id.wrapping_add(1);
+        let transfer = DataTransfer::download(transfer_id, data_transfer::STILL_STORE_ID, slot);
+        Ok(self.start_transfer(transfer)?.map(move |data| {
+            let data = still_image::rle_decode(&data, (width * height * 4) as usize)?;
+            still_image::yuv422_to_rgba(&data, width, height)
+        }))
+    }
+
+    /// Like `download_still()`, also writes the still to `path` as PNG.
+    pub fn download_still_png(
+        &mut self,
+        slot: u16,
+        path: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<PendingTransfer<image::RgbaImage>> {
+        let path = path.as_ref().to_path_buf();
+        Ok(self.download_still(slot)?.map(move |image| {
+            image.save_with_format(path, image::ImageFormat::Png)?;
+            Ok(image)
+        }))
+    }
+
+    fn start_transfer(
+        &mut self,
+        mut transfer: DataTransfer,
+    ) -> anyhow::Result<PendingTransfer<Vec<u8>>> {
+        if self.transfer.is_some() {
+            anyhow::bail!("Another data transfer is running");
+        }
+        for c in transfer.start() {
+            self.send_command(c)?;
+        }
+        let (running, pending) = RunningTransfer::new(transfer);
+        self.transfer = Some(running);
+        Ok(pending)
+    }
+
+    /// Gives up on a transfer the switcher stopped answering and hands out finished ones.
+    fn check_transfer(&mut self) -> Vec<AtemCommand> {
+        let mut commands = Vec::new();
+        if let Some(running) = &mut self.transfer {
+            if !running.transfer.is_finished() && running.started.elapsed() > TRANSFER_TIMEOUT {
+                commands = running.transfer.abort("Data transfer timed out");
+            }
+            if running.transfer.is_finished() {
+                if let Some(running) = self.transfer.take() {
+                    running.finish();
+                }
             }
         }
+        commands
+    }
+
+    pub fn start_streaming(&mut self) -> anyhow::Result<()> {
+        self.check_streaming()?;
+        self.send_command(streaming::create_set_streaming(true))
     }
-    pub fn update(&mut self) {
+
+    pub fn stop_streaming(&mut self) -> anyhow::Result<()> {
+        self.check_streaming()?;
+        self.send_command(streaming::create_set_streaming(false))
+    }
+
+    /// `name` is the service name shown in the UI, `url` the RTMP server and `key` the stream key.
+    pub fn set_stream_service(&mut self, name: &str, url: &str, key: &str) -> anyhow::Result<()> {
+        self.check_streaming()?;
+        self.send_command(streaming::create_set_service(name, url, key))
+    }
+
+    /// Low and high video bitrate in bit/s, the switcher picks one based on the video mode.
+    pub fn set_stream_bitrates(&mut self, low: u32, high: u32) -> anyhow::Result<()> {
+        self.check_streaming()?;
+        self.send_command(streaming::create_set_bitrates(low, high))
+    }
+
+    pub fn start_recording(&mut self) -> anyhow::Result<()> {
+        self.check_recording()?;
+        self.send_command(recording::create_set_recording(true))
+    }
+
+    pub fn stop_recording(&mut self) -> anyhow::Result<()> {
+        self.check_recording()?;
+        self.send_command(recording::create_set_recording(false))
+    }
+
+    pub fn set_recording_filename(&mut self, filename: &str) -> anyhow::Result<()> {
+        self.check_recording()?;
+        self.send_command(recording::create_set_filename(filename))
+    }
+
+    /// Continues the running recording on the next disk of the working set.
+    pub fn switch_disk(&mut self) -> anyhow::Result<()> {
+        self.check_recording()?;
+        self.send_command(recording::create_switch_disk())
+    }
+
+    /// Normalised focus of the camera on `input`, 0.0 is near and 1.0 is infinity.
+    pub fn set_camera_focus(&mut self, input: u8, focus: f32) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_focus(input, focus))
+    }
+
+    pub fn trigger_camera_auto_focus(&mut self, input: u8) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_auto_focus(input))
+    }
+
+    /// Normalised iris, 0.0 is closed and 1.0 is fully open.
+    pub fn set_camera_iris(&mut self, input: u8, iris: f32) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_iris(input, iris))
+    }
+
+    pub fn trigger_camera_auto_iris(&mut self, input: u8) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_auto_iris(input))
+    }
+
+    /// Normalised zoom, 0.0 is wide and 1.0 is tele.
+    pub fn set_camera_zoom(&mut self, input: u8, zoom: f32) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_zoom(input, zoom))
+    }
+
+    /// Continuous zoom from -1.0 (wide) to 1.0 (tele), 0.0 stops.
+    pub fn set_camera_zoom_speed(&mut self, input: u8, speed: f32) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_zoom_speed(input, speed))
+    }
+
+    pub fn set_camera_white_balance(
+        &mut self,
+        input: u8,
+        kelvin: i16,
+        tint: i16,
+    ) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_white_balance(
+            input, kelvin, tint,
+        ))
+    }
+
+    pub fn trigger_camera_auto_white_balance(&mut self, input: u8) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_auto_white_balance(input))
+    }
+
+    /// Sensor gain in dB.
+    pub fn set_camera_gain(&mut self, input: u8, gain: i8) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_gain(input, gain))
+    }
+
+    pub fn set_camera_iso(&mut self, input: u8, iso: i32) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_iso(input, iso))
+    }
+
+    /// Shutter speed as 1/x seconds, e.g. 50 for 1/50.
+    pub fn set_camera_shutter_speed(&mut self, input: u8, speed: i32) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_shutter_speed(input, speed))
+    }
+
+    pub fn set_camera_lift(&mut self, input: u8, lift: Rgby) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_lift(input, lift))
+    }
+
+    pub fn set_camera_gamma(&mut self, input: u8, gamma: Rgby) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_gamma(input, gamma))
+    }
+
+    pub fn set_camera_color_gain(&mut self, input: u8, gain: Rgby) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_color_gain(input, gain))
+    }
+
+    pub fn set_camera_offset(&mut self, input: u8, offset: Rgby) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_offset(input, offset))
+    }
+
+    /// Contrast around `pivot` (0.0 to 1.0), `adjust` goes from 0.0 to 2.0 with 1.0 unchanged.
+    pub fn set_camera_contrast(
+        &mut self,
+        input: u8,
+        pivot: f32,
+        adjust: f32,
+    ) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_set_contrast(input, pivot, adjust))
+    }
+
+    /// Sends any camera control parameter, for everything not covered by the setters above.
+    pub fn send_camera_control(
+        &mut self,
+        input: u8,
+        category: u8,
+        parameter: u8,
+        relative: bool,
+        data: &CameraControlData,
+    ) -> anyhow::Result<()> {
+        self.state.capabilities.check_input(input as u16)?;
+        self.send_command(camera_control::create_camera_command(
+            input, category, parameter, relative, data,
+        ))
+    }
+
+    pub fn set_multiview_properties(
+        &mut self,
+        multiview: u8,
+        layout: MultiviewLayout,
+        program_preview_swapped: bool,
+    ) -> anyhow::Result<()> {
+        self.check_multiview(multiview)?;
+        self.send_command(multiview::create_set_properties(
+            multiview,
+            layout,
+            program_preview_swapped,
+        ))
+    }
+
+    /// Routes `source` into `window` of `multiview`, windows count from the top left.
+    pub fn set_multiview_window_source(
+        &mut self,
+        multiview: u8,
+        window: u8,
+        source: u16,
+    ) -> anyhow::Result<()> {
+        self.check_multiview(multiview)?;
+        let count = self.state.multiview.window_count as usize;
+        capabilities::check_index("multiview window", count, window as usize)?;
+        self.send_command(multiview::create_set_window_source(
+            multiview, window, source,
+        ))
+    }
+
+    pub fn set_supersource_art(
+        &mut self,
+        supersource: u8,
+        art: &SuperSourceArt,
+    ) -> anyhow::Result<()> {
+        self.state.capabilities.check_supersource(supersource)?;
+        self.send_command(supersource::create_set_art(supersource, art))
+    }
+
+    pub fn set_supersource_box(
+        &mut self,
+        supersource: u8,
+        index: u8,
+        properties: &SuperSourceBox,
+    ) -> anyhow::Result<()> {
+        self.state.capabilities.check_supersource(supersource)?;
+        if let Some(ss) = self
+            .state
+            .supersource
+            .as_ref()
+            .and_then(|ss| ss.supersources.get(&supersource))
+        {
+            capabilities::check_index("SuperSource box", ss.box_count as usize, index as usize)?;
+        }
+        self.send_command(supersource::create_set_box(supersource, index, properties))
+    }
+
+    pub fn run_macro(&mut self, index: u8) -> anyhow::Result<()> {
+        self.state.capabilities.check_macro(index)?;
+        if let Some(tx) = &mut self.request_tx {
+            tx.send(Command::RunMacro(index))?;
+            Ok(())
+        } else {
+            anyhow::bail!("Not connected")
+        }
+    }
+    /// Applies what the switcher sent, fails once the connection is lost or refused.
+    pub fn update(&mut self) -> anyhow::Result<()> {
         let max_responses = 10;
+        let mut transfer_commands = Vec::new();
         if let Some(response_rx) = &self.response_rx {
             for _i in 0..max_responses {
                 let r = response_rx.try_recv();
                 match r {
-                    Ok(c) => {
+                    Ok(Ok(mut c)) => {
+                        let mut levels_changed = false;
+                        for payload in c.payloads() {
+                            match payload {
+                                Payload::FairlightSourceLevels {
+                                    input,
+                                    source,
+                                    levels,
+                                } => {
+                                    self.audio_levels
+                                        .sources
+                                        .insert((*input, *source), levels.clone());
+                                    levels_changed = true;
+                                }
+                                Payload::FairlightMasterLevels(levels) => {
+                                    self.audio_levels.master = levels.clone();
+                                    levels_changed = true;
+                                }
+                                _ => {}
+                            }
+                            if let Some(running) = &mut self.transfer {
+                                transfer_commands.extend(running.transfer.handle(payload));
+                            }
+                            let old_tally = matches!(
+                                payload,
+                                Payload::TallyByIndex(_) | Payload::TallyBySource(_)
+                            )
+                            .then(|| self.state.tally.clone());
+                            self.state.apply(payload);
+                            if let Some(old_tally) = old_tally {
+                                for change in old_tally.changes(&self.state.tally) {
+                                    // fails when nobody subscribed, which is fine
+                                    let _ = self.tally_tx.send(change);
+                                }
+                            }
+                        }
+                        if !c.header().is_hello() && self.raw_chunks_tx.receiver_count() > 0 {
+                            for chunk in RawChunk::split(c.payload().buffer()) {
+                                let _ = self.raw_chunks_tx.send(chunk);
+                            }
+                        }
+                        if levels_changed {
+                            // only fails without receivers, and we keep one ourselves
+                            let _ = self.audio_levels_tx.send(self.audio_levels.clone());
+                        }
                         if !self.initial_payload_received {
                             if c.header().len() == 0 {
                                 println!("Initial payload transfered");
@@ -262,16 +1154,68 @@ impl AtemMini {
                     Err(mpsc::TryRecvError::Empty) => {
                         break;
                     }
-                    Err(mpsc::TryRecvError::Disconnected) => {
-                        panic!("Connection lost");
-                        break;
+                    Ok(Err(e)) => {
+                        self.request_tx = None;
+                        self.response_rx = None;
+                        return Err(e);
                     }
-                    Err(e) => {
-                        println!("{:?}", &e);
-                        break;
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        self.request_tx = None;
+                        self.response_rx = None;
+                        anyhow::bail!("Connection lost");
                     }
                 }
             }
         }
+        transfer_commands.extend(self.check_transfer());
+        for c in transfer_commands {
+            let _ = self.send_command(c);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_ids_wrap() {
+        let mut connection = Connection::default();
+        // a still upload sends thousands of FTDa packets, more than fit in 15 bits
+        for expected in 1..=PACKAGE_ID_MASK {
+            assert_eq!(connection.next_package_id(), expected);
+        }
+        assert_eq!(connection.next_package_id(), 0);
+        assert_eq!(connection.next_package_id(), 1);
+    }
+
+    #[test]
+    fn tally_changes_are_broadcast() -> anyhow::Result<()> {
+        let mut atem = AtemMini::new();
+        let (tx, rx) = channel();
+        atem.response_rx = Some(rx);
+        let mut changes = atem.tally_changes();
+        let received = |chunk: RawChunk| {
+            let data = crate::atem_command::tests::packet(&[chunk]);
+            AtemCommand::from_buffer(&data, &mut None).unwrap()
+        };
+
+        // a switcher that only sends TlIn
+        tx.send(Ok(received(RawChunk::new(b"TlIn", &[0, 2, 0, 1]))))?;
+        atem.update()?;
+        let change = changes.try_recv()?;
+        assert_eq!((change.source, change.new.program), (2, true));
+        assert!(changes.try_recv().is_err());
+
+        tx.send(Ok(received(RawChunk::new(b"TlSr", &[0, 1, 0, 5, 2]))))?;
+        atem.update()?;
+        let mut sources = Vec::new();
+        while let Ok(change) = changes.try_recv() {
+            sources.push((change.source, change.new.preview));
+        }
+        // input 2 went dark when the source list took over
+        assert_eq!(sources, [(5, true), (2, false)]);
+        Ok(())
     }
 }
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+
+use crate::camera_control::CameraState;
+use crate::capabilities::Capabilities;
+use crate::downstream_keyer::DownstreamKeyerState;
+use crate::fairlight::FairlightState;
+use crate::input::InputProperties;
+use crate::macros::MacroState;
+use crate::media_pool::MediaPoolState;
+use crate::mix_effect::MixEffectState;
+use crate::multiview::MultiviewState;
+use crate::payload::Payload;
+use crate::recording::RecordingState;
+use crate::streaming::StreamingState;
+use crate::supersource::SuperSourceState;
+use crate::tally::Tally;
+use crate::video_mode::VideoMode;
+
+/// Everything we know about the switcher, built from the chunks it sends us.
+#[derive(Debug, Clone, Default)]
+pub struct AtemState {
+    pub capabilities: Capabilities,
+    pub mix_effects: MixEffectState,
+    pub downstream_keyers: DownstreamKeyerState,
+    pub fairlight: FairlightState,
+    pub media_pool: MediaPoolState,
+    pub video_mode: Option<VideoMode>,
+    pub inputs: BTreeMap<u16, InputProperties>,
+    pub tally: Tally,
+    pub macros: MacroState,
+    pub streaming: StreamingState,
+    pub recording: RecordingState,
+    /// Blackmagic cameras by input, only those we have seen camera control data for.
+    pub cameras: BTreeMap<u8, CameraState>,
+    pub multiview: MultiviewState,
+    /// `None` on switchers without SuperSource.
+    pub supersource: Option<SuperSourceState>,
+}
+
+impl AtemState {
+    pub fn apply(&mut self, payload: &Payload) {
+        match payload {
+            Payload::KeOn { who, index, state } => {
+                self.mix_effects
+                    .mix_effects
+                    .entry(*who)
+                    .or_default()
+                    .keyers_on_air
+                    .insert(*index, *state > 0);
+            }
+            Payload::FairlightInput { input, properties } => {
+                self.fairlight.inputs.entry(*input).or_default().properties =
+                    Some(properties.clone());
+            }
+            Payload::FairlightSource {
+                input,
+                source,
+                properties,
+            } => {
+                self.fairlight.source_mut(*input, *source).properties = Some(properties.clone());
+            }
+            Payload::FairlightSourceEqualizerBand {
+                input,
+                source,
+                band,
+                properties,
+            } => {
+                self.fairlight
+                    .source_mut(*input, *source)
+                    .equalizer_bands
+                    .insert(*band, properties.clone());
+            }
+            Payload::FairlightSourceCompressor {
+                input,
+                source,
+                compressor,
+            } => {
+                self.fairlight.source_mut(*input, *source).compressor = Some(compressor.clone());
+            }
+            Payload::FairlightSourceLimiter {
+                input,
+                source,
+                limiter,
+            } => {
+                self.fairlight.source_mut(*input, *source).limiter = Some(limiter.clone());
+            }
+            Payload::FairlightSourceExpander {
+                input,
+                source,
+                expander,
+            } => {
+                self.fairlight.source_mut(*input, *source).expander = Some(expander.clone());
+            }
+            Payload::FairlightMaster(properties) => {
+                self.fairlight.master.properties = properties.clone();
+            }
+            Payload::FairlightMasterEqualizerBand { band, properties } => {
+                self.fairlight
+                    .master
+                    .equalizer_bands
+                    .insert(*band, properties.clone());
+            }
+            Payload::FairlightMasterCompressor(compressor) => {
+                self.fairlight.master.compressor = Some(compressor.clone());
+            }
+            Payload::FairlightMasterLimiter(limiter) => {
+                self.fairlight.master.limiter = Some(limiter.clone());
+            }
+            Payload::FairlightSourceLevels { .. } | Payload::FairlightMasterLevels(_) => {
+                // published through the levels channel, not part of the state
+            }
+            Payload::FairlightMonitor(monitor) => {
+                self.fairlight.monitor = Some(monitor.clone());
+            }
+            Payload::FairlightSolo(solo) => {
+                self.fairlight.solo = Some(solo.clone());
+            }
+            Payload::FairlightMasterCrossfade(crossfade) => {
+                self.fairlight.audio_follow_video_crossfade_transition = *crossfade;
+            }
+            Payload::MediaPoolConfig {
+                still_count,
+                clip_count,
+            } => {
+                self.media_pool.still_count = *still_count;
+                self.media_pool.clip_count = *clip_count;
+            }
+            Payload::MediaPoolStill { index, still } => {
+                self.media_pool.stills.insert(*index, still.clone());
+            }
+            Payload::MediaPlayerSource { player, source } => {
+                self.media_pool.players.insert(*player, source.clone());
+            }
+            Payload::VideoMode(mode) => {
+                self.video_mode = Some(*mode);
+            }
+            Payload::LockObtained { .. }
+            | Payload::DataTransferContinue { .. }
+            | Payload::DataTransferData { .. }
+            | Payload::DataTransferComplete { .. }
+            | Payload::DataTransferError { .. } => {
+                // handled by the running data transfer
+            }
+            Payload::StreamingStatus { status, error } => {
+                self.streaming.status = Some(*status);
+                self.streaming.error = *error;
+            }
+            Payload::StreamingDuration(duration) => {
+                self.streaming.duration = Some(*duration);
+            }
+            Payload::StreamingStats {
+                encoding_bitrate,
+                cache_used,
+            } => {
+                self.streaming.encoding_bitrate = *encoding_bitrate;
+                self.streaming.cache_used = *cache_used;
+            }
+            Payload::StreamingService(service) => {
+                self.streaming.service = Some(service.clone());
+            }
+            Payload::StreamingAudioBitrates { low, high } => {
+                self.streaming.audio_bitrates = Some((*low, *high));
+            }
+            Payload::StreamingLowBandwidth(low_bandwidth) => {
+                self.streaming.low_bandwidth = *low_bandwidth;
+            }
+            Payload::RecordingStatus {
+                status,
+                error,
+                recording_time_available,
+            } => {
+                self.recording.status = Some(*status);
+                self.recording.error = *error;
+                self.recording.recording_time_available = *recording_time_available;
+            }
+            Payload::RecordingDuration(duration) => {
+                self.recording.duration = Some(*duration);
+            }
+            Payload::RecordingSettings(settings) => {
+                self.recording.settings = Some(settings.clone());
+            }
+            Payload::RecordingDisk { disk_id, disk } => {
+                if disk.is_removed() {
+                    self.recording.disks.remove(disk_id);
+                } else {
+                    self.recording.disks.insert(*disk_id, disk.clone());
+                }
+            }
+            Payload::CameraControl {
+                input,
+                category,
+                parameter,
+                data,
+            } => {
+                self.cameras
+                    .entry(*input)
+                    .or_default()
+                    .apply(*category, *parameter, data);
+            }
+            Payload::MultiviewConfig {
+                multiview_count,
+                window_count,
+            } => {
+                self.multiview.multiview_count = *multiview_count;
+                self.multiview.window_count = *window_count;
+            }
+            Payload::MultiviewProperties {
+                multiview,
+                layout,
+                program_preview_swapped,
+            } => {
+                let m = self.multiview.multiviews.entry(*multiview).or_default();
+                m.layout = *layout;
+                m.program_preview_swapped = *program_preview_swapped;
+            }
+            Payload::MultiviewWindowSource {
+                multiview,
+                window,
+                source,
+                supports_vu_meter,
+                supports_safe_area,
+            } => {
+                let w = self.multiview.window_mut(*multiview, *window);
+                w.source = *source;
+                w.supports_vu_meter = *supports_vu_meter;
+                w.supports_safe_area = *supports_safe_area;
+            }
+            Payload::MultiviewVuMeter {
+                multiview,
+                window,
+                enabled,
+            } => {
+                self.multiview
+                    .window_mut(*multiview, *window)
+                    .vu_meter_enabled = *enabled;
+            }
+            Payload::MultiviewSafeArea {
+                multiview,
+                window,
+                enabled,
+            } => {
+                self.multiview
+                    .window_mut(*multiview, *window)
+                    .safe_area_enabled = *enabled;
+            }
+            Payload::Topology(topology) => {
+                if topology.supersource_count > 0 {
+                    self.supersource.get_or_insert_with(Default::default).count =
+                        topology.supersource_count;
+                } else {
+                    self.supersource = None;
+                }
+                self.capabilities.topology = Some(topology.clone());
+            }
+            Payload::MixEffectConfig { me, keyer_count } => {
+                self.capabilities.keyer_counts.insert(*me, *keyer_count);
+            }
+            Payload::SuperSourceConfig {
+                supersource,
+                box_count,
+            } => {
+                if let Some(ss) = &mut self.supersource {
+                    ss.supersources.entry(*supersource).or_default().box_count = *box_count;
+                }
+            }
+            Payload::SuperSourceArt { supersource, art } => {
+                if let Some(ss) = &mut self.supersource {
+                    ss.supersources.entry(*supersource).or_default().art = Some(art.clone());
+                }
+            }
+            Payload::SuperSourceBox {
+                supersource,
+                index,
+                properties,
+            } => {
+                if let Some(ss) = &mut self.supersource {
+                    ss.supersources
+                        .entry(*supersource)
+                        .or_default()
+                        .boxes
+                        .insert(*index, properties.clone());
+                }
+            }
+            Payload::ProtocolVersion(version) => {
+                self.capabilities.protocol_version = Some(*version);
+            }
+            Payload::MacroCount(count) => {
+                self.capabilities.macro_count = Some(*count);
+            }
+            Payload::TallyChannelCount(count) => {
+                self.capabilities.tally_channel_count = Some(*count);
+            }
+            Payload::InputProperties { input, properties } => {
+                self.inputs.insert(*input, properties.clone());
+            }
+            Payload::TallyByIndex(tally) => {
+                self.tally.by_index = tally.clone();
+            }
+            Payload::TallyBySource(tally) => {
+                self.tally.by_source = tally.clone();
+            }
+            Payload::ProgramInput { me, input } => {
+                self.mix_effects.mix_effects.entry(*me).or_default().program = Some(*input);
+            }
+            Payload::PreviewInput { me, input } => {
+                self.mix_effects.mix_effects.entry(*me).or_default().preview = Some(*input);
+            }
+            Payload::MacroProperties { index, properties } => {
+                self.macros.macros.insert(*index, properties.clone());
+            }
+            Payload::MacroPlayer(player) => {
+                self.macros.player = *player;
+            }
+            Payload::DownstreamKeyer { index, keyer } => {
+                self.downstream_keyers.keyers.insert(*index, keyer.clone());
+            }
+        }
+    }
+}
@@ -1,28 +1,117 @@
-use bmda_bridge::AtemMini;
+use std::sync::{Arc, Mutex};
+
+use bmda_bridge::{is_pcap, parse_capture, parse_pcap, AtemMini, Replay};
+
+mod bridge;
+
+use bridge::config::Config;
+use bridge::events::EventHub;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let mut am = AtemMini::new();
+    let config = Config::from_args(std::env::args().skip(1))?;
+
+    if let Some(path) = &config.replay_path {
+        // pcap files are told apart by their magic, whatever they are called
+        let data = std::fs::read(path)?;
+        let packets = if is_pcap(&data) {
+            parse_pcap(&data)?
+        } else {
+            parse_capture(std::str::from_utf8(&data)?)?
+        };
+        let mut replay = Replay::new();
+        replay.feed_all(&packets);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&bridge::json::state_json(replay.state()))?
+        );
+        return Ok(());
+    }
+
+    let mut am = match &config.atem_address {
+        Some(addr) => AtemMini::with_address(addr),
+        None => AtemMini::new(),
+    };
+
+    if let Some(path) = &config.record_path {
+        am.record_to(path)?;
+    }
+
+    // before the first update, the proxy needs the initial state
+    let raw_chunks = config.proxy_address.as_ref().map(|_| am.raw_chunks());
 
     am.connect()?;
 
-    let mut wait = 0;
-    while am.is_connected() {
-        std::thread::sleep(std::time::Duration::from_millis(1));
-        //		println!(".");
-        am.update(); // :HACK:
-        wait += 1;
-
-        if wait == 5000 {
-            println!("Run Macro Test 0");
-            am.run_macro(0);
-        } else if wait == 7000 {
-            println!("Run Macro Test 1");
-            am.run_macro(1);
-        } else if wait == 10000 {
-            println!("Run Macro Test 0");
-            am.run_macro(0);
-        }
+    let atem = Arc::new(Mutex::new(am));
+
+    let events = EventHub::new();
+    tokio::spawn(events.clone().run(atem.clone()));
+
+    if !config.tsl_destinations.is_empty() {
+        let atem = atem.clone();
+        let destinations = config.tsl_destinations.clone();
+        let labels = config.tsl_labels.clone();
+        let screen = config.tsl_screen;
+        tokio::spawn(async move {
+            if let Err(e) = bridge::tsl::run(atem, destinations, labels, screen).await {
+                println!("TSL output stopped: {}", e);
+            }
+        });
+    }
+
+    if let Some(addr) = config.http_address.clone() {
+        let atem = atem.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = bridge::http::run(atem, events, addr).await {
+                println!("HTTP API stopped: {}", e);
+            }
+        });
+    }
+
+    if let Some(addr) = config.osc_address.clone() {
+        let atem = atem.clone();
+        let events = events.clone();
+        let targets = config.osc_targets.clone();
+        tokio::spawn(async move {
+            if let Err(e) = bridge::osc::run(atem, events, addr, targets).await {
+                println!("OSC stopped: {}", e);
+            }
+        });
+    }
+
+    if let Some(mqtt) = config.mqtt.clone() {
+        let atem = atem.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = bridge::mqtt::run(atem, events, mqtt).await {
+                println!("MQTT stopped: {}", e);
+            }
+        });
+    }
+
+    if let Some(addr) = config.vmix_address.clone() {
+        let atem = atem.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = bridge::vmix::run(atem, events, addr).await {
+                println!("vMix API stopped: {}", e);
+            }
+        });
+    }
+
+    if let (Some(addr), Some(raw_chunks)) = (config.proxy_address.clone(), raw_chunks) {
+        let atem = atem.clone();
+        tokio::spawn(async move {
+            if let Err(e) = bridge::proxy::run(atem, raw_chunks, addr).await {
+                println!("Proxy stopped: {}", e);
+            }
+        });
+    }
+
+    while atem.lock().unwrap().is_connected() {
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        atem.lock().unwrap().update()?;
     }
 
     Ok(())
@@ -0,0 +1,41 @@
+use bmda_bridge::{Simulator, SimulatorSetup};
+
+const USAGE: &str = "Usage: bmda-simulator [options]
+
+  --listen <addr:port>    where to accept clients (default 0.0.0.0:9910)
+  --mes <count>           number of MEs (default 1)
+  --inputs <count>        number of external inputs (default 4)
+  --dsks <count>          number of downstream keyers (default 1)
+  --recording             has a recorder
+";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut listen = "0.0.0.0:9910".to_string();
+    let mut setup = SimulatorSetup::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--listen" => listen = value()?,
+            "--mes" => setup.topology.me_count = value()?.parse()?,
+            "--inputs" => {
+                let count: u8 = value()?.parse()?;
+                setup.topology.source_count = count;
+                setup.inputs = (1..=count).map(|i| format!("Camera {}", i)).collect();
+            }
+            "--dsks" => setup.topology.dsk_count = value()?.parse()?,
+            "--recording" => setup.has_recording = true,
+            "-h" | "--help" => anyhow::bail!("{}", USAGE),
+            o => anyhow::bail!("Unknown option {}\n\n{}", o, USAGE),
+        }
+    }
+
+    let simulator = Simulator::bind(&listen, setup).await?;
+    println!("Simulating a switcher on {}", simulator.local_addr()?);
+    simulator.run().await
+}
@@ -0,0 +1,98 @@
+use serde_json::Value;
+
+use bmda_bridge::AtemMini;
+
+/// Something a client of the bridge asked the switcher to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Program { me: u8, input: u16 },
+    Preview { me: u8, input: u16 },
+    Cut { me: u8 },
+    Auto { me: u8 },
+    KeyerOnAir { me: u8, keyer: u8, on_air: bool },
+    DskOnAir { index: u8, on_air: bool },
+    DskAuto { index: u8 },
+    RunMacro(u8),
+    StartStreaming,
+    StopStreaming,
+    StartRecording,
+    StopRecording,
+}
+
+/// Fails for numbers that don't fit `T` instead of wrapping them around.
+fn number<T: TryFrom<u64>>(value: &Value, key: &str) -> anyhow::Result<T> {
+    let n = value
+        .get(key)
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| anyhow::anyhow!("Expected a number in \"{}\"", key))?;
+    T::try_from(n).map_err(|_| anyhow::anyhow!("{} is out of range for \"{}\"", n, key))
+}
+
+fn boolean(value: &Value, key: &str) -> anyhow::Result<bool> {
+    match value.get(key) {
+        Some(Value::Bool(b)) => Ok(*b),
+        Some(Value::Number(n)) => Ok(n.as_u64() != Some(0)),
+        _ => anyhow::bail!("Expected a boolean in \"{}\"", key),
+    }
+}
+
+fn me(value: &Value) -> anyhow::Result<u8> {
+    match value.get("me") {
+        None => Ok(0),
+        Some(_) => number(value, "me"),
+    }
+}
+
+impl Action {
+    /// `name` is what the client called, `args` holds its arguments, e.g. {"me": 0, "input": 3}.
+    pub fn from_json(name: &str, args: &Value) -> anyhow::Result<Self> {
+        let action = match name {
+            "program" => Action::Program {
+                me: me(args)?,
+                input: number(args, "input")?,
+            },
+            "preview" => Action::Preview {
+                me: me(args)?,
+                input: number(args, "input")?,
+            },
+            "cut" => Action::Cut { me: me(args)? },
+            "auto" => Action::Auto { me: me(args)? },
+            "keyer_on_air" => Action::KeyerOnAir {
+                me: me(args)?,
+                keyer: number(args, "keyer")?,
+                on_air: boolean(args, "on_air")?,
+            },
+            "dsk_on_air" => Action::DskOnAir {
+                index: number(args, "index")?,
+                on_air: boolean(args, "on_air")?,
+            },
+            "dsk_auto" => Action::DskAuto {
+                index: number(args, "index")?,
+            },
+            "macro" => Action::RunMacro(number(args, "index")?),
+            "start_streaming" => Action::StartStreaming,
+            "stop_streaming" => Action::StopStreaming,
+            "start_recording" => Action::StartRecording,
+            "stop_recording" => Action::StopRecording,
+            o => anyhow::bail!("Unknown action {}", o),
+        };
+        Ok(action)
+    }
+
+    pub fn apply(&self, atem: &mut AtemMini) -> anyhow::Result<()> {
+        match *self {
+            Action::Program { me, input } => atem.set_program_input(me, input),
+            Action::Preview { me, input } => atem.set_preview_input(me, input),
+            Action::Cut { me } => atem.cut(me),
+            Action::Auto { me } => atem.auto_transition(me),
+            Action::KeyerOnAir { me, keyer, on_air } => atem.set_keyer_on_air(me, keyer, on_air),
+            Action::DskOnAir { index, on_air } => atem.set_dsk_on_air(index, on_air),
+            Action::DskAuto { index } => atem.dsk_auto(index),
+            Action::RunMacro(index) => atem.run_macro(index),
+            Action::StartStreaming => atem.start_streaming(),
+            Action::StopStreaming => atem.stop_streaming(),
+            Action::StartRecording => atem.start_recording(),
+            Action::StopRecording => atem.stop_recording(),
+        }
+    }
+}
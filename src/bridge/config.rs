@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use crate::bridge::mqtt::MqttConfig;
+use crate::bridge::tsl::{TslDestination, TslProtocol, TslTransport};
+
+const USAGE: &str = "Usage: bmda-bridge [options]
+
+  --atem <host[:port]>          switcher address
+  --record <file>               log every packet to and from the switcher to a capture file
+  --replay <file>               decode a capture and print the resulting state instead of connecting,
+                                pcap and pcapng files (tcpdump/Wireshark) are recognized by content
+  --http <addr:port>            serve the REST API and /events WebSocket, e.g. 0.0.0.0:8080
+  --osc <addr:port>             receive OSC commands, e.g. 0.0.0.0:9000
+  --osc-target <host:port>      send OSC feedback of state changes there
+  --mqtt <host:port>            publish state to an MQTT broker and take commands from it
+  --mqtt-name <name>            topics go under atem/<name>/ (default atem)
+  --mqtt-user <user>            MQTT username
+  --mqtt-password <password>    MQTT password
+  --proxy <addr:port>           let other clients share our switcher session, e.g. 0.0.0.0:9910
+  --vmix <addr:port>            emulate the vMix TCP API, e.g. 0.0.0.0:8099
+  --tsl-udp <host:port>         send TSL UMD v3.1 over UDP
+  --tsl-tcp <host:port>         send TSL UMD v3.1 over TCP
+  --tsl5-udp <host:port>        send TSL UMD v5 over UDP
+  --tsl5-tcp <host:port>        send TSL UMD v5 over TCP
+  --tsl-label <input>=<text>    display text for an input instead of its InPr name
+  --tsl-screen <index>          TSL v5 screen index (default 0)
+";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub atem_address: Option<String>,
+    pub record_path: Option<String>,
+    pub replay_path: Option<String>,
+    pub http_address: Option<String>,
+    pub osc_address: Option<String>,
+    pub osc_targets: Vec<String>,
+    pub mqtt: Option<MqttConfig>,
+    pub vmix_address: Option<String>,
+    pub proxy_address: Option<String>,
+    pub tsl_destinations: Vec<TslDestination>,
+    pub tsl_labels: BTreeMap<u16, String>,
+    pub tsl_screen: u16,
+}
+
+impl Config {
+    /// `args` without the program name.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut config = Config {
+            atem_address: None,
+            record_path: None,
+            replay_path: None,
+            http_address: None,
+            osc_address: None,
+            osc_targets: Vec::new(),
+            mqtt: None,
+            vmix_address: None,
+            proxy_address: None,
+            tsl_destinations: Vec::new(),
+            tsl_labels: BTreeMap::new(),
+            tsl_screen: 0,
+        };
+
+        let mut mqtt_broker = None;
+        let mut mqtt_name = "atem".to_string();
+        let mut mqtt_username = None;
+        let mut mqtt_password = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--atem" => config.atem_address = Some(value()?),
+                "--record" => config.record_path = Some(value()?),
+                "--replay" => config.replay_path = Some(value()?),
+                "--http" => config.http_address = Some(value()?),
+                "--osc" => config.osc_address = Some(value()?),
+                "--osc-target" => config.osc_targets.push(value()?),
+                "--mqtt" => mqtt_broker = Some(value()?),
+                "--mqtt-name" => mqtt_name = value()?,
+                "--mqtt-user" => mqtt_username = Some(value()?),
+                "--mqtt-password" => mqtt_password = Some(value()?),
+                "--proxy" => config.proxy_address = Some(value()?),
+                "--vmix" => config.vmix_address = Some(value()?),
+                "--tsl-udp" => config.tsl_destinations.push(TslDestination {
+                    protocol: TslProtocol::V31,
+                    transport: TslTransport::Udp,
+                    address: value()?,
+                }),
+                "--tsl-tcp" => config.tsl_destinations.push(TslDestination {
+                    protocol: TslProtocol::V31,
+                    transport: TslTransport::Tcp,
+                    address: value()?,
+                }),
+                "--tsl5-udp" => config.tsl_destinations.push(TslDestination {
+                    protocol: TslProtocol::V5,
+                    transport: TslTransport::Udp,
+                    address: value()?,
+                }),
+                "--tsl5-tcp" => config.tsl_destinations.push(TslDestination {
+                    protocol: TslProtocol::V5,
+                    transport: TslTransport::Tcp,
+                    address: value()?,
+                }),
+                "--tsl-label" => {
+                    let v = value()?;
+                    let (input, label) = v
+                        .split_once('=')
+                        .ok_or_else(|| anyhow::anyhow!("Expected <input>=<text>, got {}", v))?;
+                    config
+                        .tsl_labels
+                        .insert(input.trim().parse()?, label.to_string());
+                }
+                "--tsl-screen" => config.tsl_screen = value()?.parse()?,
+                "-h" | "--help" => anyhow::bail!("{}", USAGE),
+                o => anyhow::bail!("Unknown option {}\n\n{}", o, USAGE),
+            }
+        }
+
+        if mqtt_password.is_some() && mqtt_username.is_none() {
+            anyhow::bail!("--mqtt-password needs --mqtt-user");
+        }
+        config.mqtt = mqtt_broker.map(|broker| MqttConfig {
+            broker,
+            name: mqtt_name,
+            username: mqtt_username,
+            password: mqtt_password,
+        });
+
+        Ok(config)
+    }
+}
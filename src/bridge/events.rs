@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+use bmda_bridge::AtemState;
+
+use crate::bridge::SharedAtem;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const EVENTS_CAPACITY: usize = 256;
+
+/// Finds what changed between two states, as JSON events like {"event": "program", "me": 0, "input": 3}.
+pub fn diff(old: &AtemState, new: &AtemState) -> Vec<Value> {
+    let mut events = Vec::new();
+
+    for (me, m) in &new.mix_effects.mix_effects {
+        let old_m = old.mix_effects.mix_effects.get(me);
+        if m.program.is_some() && old_m.and_then(|o| o.program) != m.program {
+            events.push(json!({ "event": "program", "me": me, "input": m.program }));
+        }
+        if m.preview.is_some() && old_m.and_then(|o| o.preview) != m.preview {
+            events.push(json!({ "event": "preview", "me": me, "input": m.preview }));
+        }
+        for (keyer, on_air) in &m.keyers_on_air {
+            if old_m.and_then(|o| o.keyers_on_air.get(keyer)) != Some(on_air) {
+                events
+                    .push(json!({ "event": "keyer", "me": me, "keyer": keyer, "on_air": on_air }));
+            }
+        }
+    }
+
+    for (index, dsk) in &new.downstream_keyers.keyers {
+        let old_dsk = old.downstream_keyers.keyers.get(index);
+        if old_dsk.map(|d| d.on_air) != Some(dsk.on_air) {
+            events.push(json!({ "event": "dsk", "index": index, "on_air": dsk.on_air }));
+        }
+    }
+
+    for change in old.tally.changes(&new.tally) {
+        events.push(json!({
+            "event": "tally",
+            "source": change.source,
+            "program": change.new.program,
+            "preview": change.new.preview,
+        }));
+    }
+
+    if old.macros.player != new.macros.player {
+        let p = &new.macros.player;
+        events.push(json!({
+            "event": "macro",
+            "running": p.running,
+            "waiting": p.waiting,
+            "looping": p.looping,
+        }));
+    }
+
+    if old.streaming.status != new.streaming.status {
+        events.push(json!({
+            "event": "streaming",
+            "status": new.streaming.status.map(|s| format!("{:?}", s)),
+        }));
+    }
+
+    if old.recording.status != new.recording.status {
+        events.push(json!({
+            "event": "recording",
+            "status": new.recording.status.map(|s| format!("{:?}", s)),
+        }));
+    }
+
+    events
+}
+
+/// A copy of the parts of `state` that `diff()` looks at, cheap enough to take under the lock.
+fn watched(state: &AtemState) -> AtemState {
+    let mut w = AtemState {
+        mix_effects: state.mix_effects.clone(),
+        downstream_keyers: state.downstream_keyers.clone(),
+        tally: state.tally.clone(),
+        ..Default::default()
+    };
+    w.macros.player = state.macros.player;
+    w.streaming.status = state.streaming.status;
+    w.recording.status = state.recording.status;
+    w
+}
+
+/// Publishes the changes of the switcher state to every service that subscribed.
+#[derive(Clone)]
+pub struct EventHub {
+    tx: broadcast::Sender<Value>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self { tx }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Value> {
+        self.tx.subscribe()
+    }
+
+    /// Polls the state and sends an event for everything that changed.
+    pub async fn run(self, atem: SharedAtem) {
+        let mut last = AtemState::default();
+        loop {
+            let current = watched(atem.lock().unwrap().state());
+            for event in diff(&last, &current) {
+                // fails when nobody listens, which is fine
+                let _ = self.tx.send(event);
+            }
+            last = current;
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bmda_bridge::{DownstreamKeyer, MixEffect, StreamingStatus, TallyState};
+
+    fn state() -> AtemState {
+        let mut state = AtemState::default();
+        state.mix_effects.mix_effects.insert(
+            0,
+            MixEffect {
+                program: Some(1),
+                preview: Some(2),
+                keyers_on_air: [(0, true)].into(),
+            },
+        );
+        state.downstream_keyers.keyers.insert(
+            1,
+            DownstreamKeyer {
+                on_air: true,
+                ..Default::default()
+            },
+        );
+        state.tally.by_source.insert(
+            1,
+            TallyState {
+                program: true,
+                preview: false,
+            },
+        );
+        state.macros.player.running = Some(3);
+        state.streaming.status = Some(StreamingStatus::Streaming);
+        state
+    }
+
+    #[test]
+    fn diff_reports_changes() {
+        let events = diff(&AtemState::default(), &state());
+        let names: Vec<&str> = events.iter().filter_map(|e| e["event"].as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "program",
+                "preview",
+                "keyer",
+                "dsk",
+                "tally",
+                "macro",
+                "streaming"
+            ]
+        );
+        assert_eq!(
+            events[0],
+            json!({ "event": "program", "me": 0, "input": 1 })
+        );
+        assert_eq!(
+            events[6],
+            json!({ "event": "streaming", "status": "Streaming" })
+        );
+        assert!(diff(&state(), &state()).is_empty());
+
+        // the light going out on the way back
+        let events = diff(&state(), &AtemState::default());
+        assert!(events.contains(&json!({
+            "event": "tally",
+            "source": 1,
+            "program": false,
+            "preview": false,
+        })));
+    }
+
+    #[test]
+    fn watched_keeps_what_diff_needs() {
+        let mut full = state();
+        full.streaming.error = 5;
+        full.macros.macros.insert(0, Default::default());
+        let w = watched(&full);
+        assert_eq!(w.streaming.error, 0);
+        assert!(w.macros.macros.is_empty());
+        assert_eq!(
+            diff(&AtemState::default(), &w),
+            diff(&AtemState::default(), &full)
+        );
+        assert!(diff(&w, &full).is_empty());
+    }
+}
@@ -0,0 +1,362 @@
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use bmda_bridge::Unsupported;
+
+use crate::bridge::actions::Action;
+use crate::bridge::events::EventHub;
+use crate::bridge::json;
+use crate::bridge::websocket;
+use crate::bridge::SharedAtem;
+
+/// Requests bigger than this are refused, we only expect small JSON bodies.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Answered with 413 instead of 400.
+#[derive(Debug)]
+struct BodyTooLarge(usize);
+
+impl std::fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Body of {} bytes is too large", self.0)
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.to_string() }),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    }
+}
+
+async fn read_request(reader: &mut (impl AsyncBufRead + Unpin)) -> anyhow::Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse()?;
+            }
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(BodyTooLarge(content_length).into());
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    }))
+}
+
+/// Maps a route to the action it triggers, `None` if the route isn't an action.
+fn route_action(path: &[&str], args: &Value) -> Option<anyhow::Result<Action>> {
+    let action = match path {
+        ["program"] => Action::from_json("program", args),
+        ["preview"] => Action::from_json("preview", args),
+        ["cut"] => Action::from_json("cut", args),
+        ["auto"] => Action::from_json("auto", args),
+        ["macros", index, "run"] => index
+            .parse()
+            .map(Action::RunMacro)
+            .map_err(|_| anyhow::anyhow!("Invalid macro index {}", index)),
+        ["streaming", "start"] => Ok(Action::StartStreaming),
+        ["streaming", "stop"] => Ok(Action::StopStreaming),
+        ["recording", "start"] => Ok(Action::StartRecording),
+        ["recording", "stop"] => Ok(Action::StopRecording),
+        _ => return None,
+    };
+    Some(action)
+}
+
+fn handle_request(atem: &SharedAtem, request: &Request) -> Response {
+    let path = request.path.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match request.method.as_str() {
+        "GET" => {
+            let atem = atem.lock().unwrap();
+            let state = atem.state();
+            match segments.as_slice() {
+                ["state"] => Response::ok(json::state_json(state)),
+                ["inputs"] => Response::ok(json::inputs_json(state)),
+                ["tally"] => Response::ok(json::tally_json_map(state)),
+                ["mix_effects"] => Response::ok(json::mix_effects_json(state)),
+                ["streaming"] => Response::ok(json::streaming_json(state)),
+                ["recording"] => Response::ok(json::recording_json(state)),
+                _ => Response::error(404, format!("No such resource {}", path)),
+            }
+        }
+        "POST" => {
+            let args = if request.body.is_empty() {
+                json!({})
+            } else {
+                match serde_json::from_slice(&request.body) {
+                    Ok(v) => v,
+                    Err(e) => return Response::error(400, e),
+                }
+            };
+            let action = match route_action(&segments, &args) {
+                Some(Ok(action)) => action,
+                Some(Err(e)) => return Response::error(400, e),
+                None => return Response::error(404, format!("No such action {}", path)),
+            };
+            match action.apply(&mut atem.lock().unwrap()) {
+                Ok(()) => Response::ok(json!({ "ok": true })),
+                Err(e) if e.is::<Unsupported>() => Response::error(422, e),
+                Err(e) => Response::error(500, e),
+            }
+        }
+        _ => Response::error(405, format!("Method {} not allowed", request.method)),
+    }
+}
+
+async fn handle_connection(
+    atem: SharedAtem,
+    events: EventHub,
+    stream: TcpStream,
+) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let response = match read_request(&mut reader).await {
+        Ok(Some(request)) if request.path == "/events" => {
+            let is_upgrade = request
+                .header("upgrade")
+                .is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+            match request.header("sec-websocket-key") {
+                Some(key) if is_upgrade => {
+                    // nothing is buffered, clients wait for our answer before talking
+                    let key = key.to_string();
+                    return websocket::serve(atem, events, reader.into_inner(), &key).await;
+                }
+                _ => Response::error(400, "/events is a WebSocket"),
+            }
+        }
+        Ok(Some(request)) => handle_request(&atem, &request),
+        Ok(None) => return Ok(()),
+        Err(e) if e.is::<BodyTooLarge>() => Response::error(413, e),
+        Err(e) => Response::error(400, e),
+    };
+
+    let body = response.body.to_string();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        body.len()
+    );
+    let stream = reader.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Serves the REST API and the /events WebSocket on `addr` until the listener fails.
+pub async fn run(atem: SharedAtem, events: EventHub, addr: String) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&addr).await?;
+    println!("HTTP API on {}", addr);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let atem = atem.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(atem, events, stream).await {
+                println!("HTTP connection failed: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::*;
+    use bmda_bridge::AtemMini;
+
+    async fn parse(raw: &str) -> anyhow::Result<Option<Request>> {
+        read_request(&mut raw.as_bytes()).await
+    }
+
+    #[tokio::test]
+    async fn parses_requests() -> anyhow::Result<()> {
+        let raw =
+            "POST /program HTTP/1.1\r\nHost: atem\r\nContent-Length: 12\r\n\r\n{\"input\": 2}";
+        let request = parse(raw).await?.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/program");
+        assert_eq!(request.header("content-length"), Some("12"));
+        assert_eq!(request.header("HOST"), Some("atem"));
+        assert_eq!(request.body, b"{\"input\": 2}");
+
+        assert!(parse("").await?.is_none());
+        // the connection closed in the middle of the headers
+        assert!(parse("GET /state HTTP/1.1\r\nHost: atem\r\n")
+            .await?
+            .is_none());
+        assert!(parse("POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_large_bodies() {
+        let raw = format!(
+            "POST /cut HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        let e = parse(&raw).await.err().unwrap();
+        assert!(e.is::<BodyTooLarge>());
+    }
+
+    #[test]
+    fn routes() {
+        let args = json!({});
+        assert!(matches!(
+            route_action(&["macros", "3", "run"], &args),
+            Some(Ok(Action::RunMacro(3)))
+        ));
+        assert!(matches!(
+            route_action(&["macros", "x", "run"], &args),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            route_action(&["streaming", "start"], &args),
+            Some(Ok(Action::StartStreaming))
+        ));
+        assert!(route_action(&["state"], &args).is_none());
+    }
+
+    #[test]
+    fn out_of_range_arguments() {
+        let args = json!({ "me": 256, "input": 1 });
+        assert!(matches!(route_action(&["program"], &args), Some(Err(_))));
+        let args = json!({ "input": 70000 });
+        let e = route_action(&["preview"], &args).unwrap().unwrap_err();
+        assert!(e.to_string().contains("out of range"), "{}", e);
+        assert!(Action::from_json("macro", &json!({ "index": 256 })).is_err());
+        assert_eq!(
+            Action::from_json("macro", &json!({ "index": 255 })).unwrap(),
+            Action::RunMacro(255)
+        );
+    }
+
+    async fn next_json(
+        ws: &mut tokio_tungstenite::WebSocketStream<TcpStream>,
+    ) -> anyhow::Result<Value> {
+        use futures_util::StreamExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        match ws.next().await {
+            Some(Ok(Message::Text(text))) => Ok(serde_json::from_str(&text)?),
+            o => anyhow::bail!("Expected a text message, got {:?}", o),
+        }
+    }
+
+    #[tokio::test]
+    async fn events_handshake() -> anyhow::Result<()> {
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let atem: SharedAtem = Arc::new(Mutex::new(AtemMini::new()));
+        tokio::spawn(async move {
+            let events = EventHub::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(atem.clone(), events.clone(), stream));
+            }
+        });
+
+        tokio::time::timeout(Duration::from_secs(5), async move {
+            // a plain request for /events isn't served
+            let mut plain = TcpStream::connect(addr).await?;
+            plain
+                .write_all(b"GET /events HTTP/1.1\r\nHost: atem\r\n\r\n")
+                .await?;
+            let mut response = String::new();
+            plain.read_to_string(&mut response).await?;
+            assert!(response.starts_with("HTTP/1.1 400 "), "{}", response);
+
+            let stream = TcpStream::connect(addr).await?;
+            let (mut ws, response) =
+                tokio_tungstenite::client_async(format!("ws://{}/events", addr), stream).await?;
+            assert_eq!(response.status().as_u16(), 101);
+            assert_eq!(next_json(&mut ws).await?["event"], "state");
+
+            ws.send(Message::Text(json!({ "action": "cut" }).to_string()))
+                .await?;
+            let reply = next_json(&mut ws).await?;
+            assert_eq!(reply["event"], "result");
+            assert_eq!(reply["ok"], false);
+            Ok::<(), anyhow::Error>(())
+        })
+        .await?
+    }
+}
@@ -0,0 +1,132 @@
+use serde_json::{json, Value};
+
+use bmda_bridge::{AtemState, TallyState};
+
+fn tally_json(tally: &TallyState) -> Value {
+    json!({ "program": tally.program, "preview": tally.preview })
+}
+
+pub fn mix_effects_json(state: &AtemState) -> Value {
+    state
+        .mix_effects
+        .mix_effects
+        .iter()
+        .map(|(me, m)| {
+            json!({
+                "me": me,
+                "program": m.program,
+                "preview": m.preview,
+                "keyers_on_air": m.keyers_on_air,
+            })
+        })
+        .collect()
+}
+
+pub fn downstream_keyers_json(state: &AtemState) -> Value {
+    state
+        .downstream_keyers
+        .keyers
+        .iter()
+        .map(|(index, d)| {
+            json!({
+                "index": index,
+                "on_air": d.on_air,
+                "in_transition": d.in_transition,
+                "frames_remaining": d.frames_remaining,
+            })
+        })
+        .collect()
+}
+
+pub fn inputs_json(state: &AtemState) -> Value {
+    state
+        .inputs
+        .iter()
+        .map(|(id, p)| {
+            let tally = state.tally.by_source.get(id).copied().unwrap_or_default();
+            json!({
+                "id": id,
+                "long_name": p.long_name,
+                "short_name": p.short_name,
+                "port_type": format!("{:?}", p.internal_port_type),
+                "tally": tally_json(&tally),
+            })
+        })
+        .collect()
+}
+
+/// Tally by source id, the keys are strings as JSON wants them.
+pub fn tally_json_map(state: &AtemState) -> Value {
+    state
+        .tally
+        .by_source
+        .iter()
+        .map(|(source, tally)| (source.to_string(), tally_json(tally)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+pub fn streaming_json(state: &AtemState) -> Value {
+    let s = &state.streaming;
+    json!({
+        "status": s.status.map(|s| format!("{:?}", s)),
+        "duration": s.duration.map(|d| d.to_string()),
+        "encoding_bitrate": s.encoding_bitrate,
+        "cache_used": s.cache_used,
+    })
+}
+
+pub fn recording_json(state: &AtemState) -> Value {
+    let r = &state.recording;
+    json!({
+        "status": r.status.map(|s| format!("{:?}", s)),
+        "duration": r.duration.map(|d| d.to_string()),
+        "recording_time_available": r.recording_time_available,
+    })
+}
+
+pub fn state_json(state: &AtemState) -> Value {
+    let c = &state.capabilities;
+    json!({
+        "protocol_version": c.protocol_version.map(|v| v.to_string()),
+        "topology": c.topology.as_ref().map(|t| json!({
+            "me_count": t.me_count,
+            "source_count": t.source_count,
+            "aux_count": t.aux_count,
+            "dsk_count": t.dsk_count,
+            "media_player_count": t.media_player_count,
+            "supersource_count": t.supersource_count,
+        })),
+        "macro_count": c.macro_count,
+        "video_mode": state.video_mode.and_then(|m| m.name()),
+        "mix_effects": mix_effects_json(state),
+        "downstream_keyers": downstream_keyers_json(state),
+        "inputs": inputs_json(state),
+        "tally": tally_json_map(state),
+        "streaming": streaming_json(state),
+        "recording": recording_json(state),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bmda_bridge::MixEffect;
+
+    #[test]
+    fn keyers_keep_their_index() {
+        let mut state = AtemState::default();
+        state.mix_effects.mix_effects.insert(
+            0,
+            MixEffect {
+                program: Some(1),
+                preview: Some(2),
+                keyers_on_air: [(1, true), (3, false)].into(),
+            },
+        );
+        assert_eq!(
+            mix_effects_json(&state)[0]["keyers_on_air"],
+            json!({ "1": true, "3": false })
+        );
+    }
+}
@@ -0,0 +1,18 @@
+pub mod actions;
+pub mod config;
+pub mod events;
+pub mod http;
+pub mod json;
+pub mod mqtt;
+pub mod osc;
+pub mod proxy;
+pub mod tsl;
+pub mod vmix;
+pub mod websocket;
+
+use std::sync::{Arc, Mutex};
+
+use bmda_bridge::AtemMini;
+
+/// The switcher connection shared by all bridge services, `main` keeps calling `update()` on it.
+pub type SharedAtem = Arc<Mutex<AtemMini>>;
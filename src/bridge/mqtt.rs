@@ -0,0 +1,496 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+
+use bmda_bridge::AtemState;
+
+use crate::bridge::actions::Action;
+use crate::bridge::events::{self, EventHub};
+use crate::bridge::SharedAtem;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+// MQTT 3.1.1 packet types, shifted into the upper nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xc0;
+
+const RETAIN: u8 = 0x01;
+
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub broker: String,
+    /// Topics live under atem/<name>/.
+    pub name: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+fn put_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![header];
+    // remaining length, 7 bits per byte
+    let mut len = body.len();
+    loop {
+        let mut b = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            b |= 0x80;
+        }
+        out.push(b);
+        if len == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+fn connect_packet(config: &MqttConfig, client_id: &str, will_topic: &str) -> Vec<u8> {
+    let mut flags = 0x02 | 0x04 | 0x20; // clean session, will, retained will
+                                        // 3.1.1 only allows a password along with a username
+    let password = config
+        .password
+        .as_ref()
+        .filter(|_| config.username.is_some());
+    if config.username.is_some() {
+        flags |= 0x80;
+    }
+    if password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = Vec::new();
+    put_string(&mut body, "MQTT");
+    body.push(4); // 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    put_string(&mut body, client_id);
+    put_string(&mut body, will_topic);
+    put_string(&mut body, "false");
+    if let Some(u) = &config.username {
+        put_string(&mut body, u);
+    }
+    if let Some(p) = password {
+        put_string(&mut body, p);
+    }
+    packet(CONNECT, &body)
+}
+
+fn publish_packet(topic: &str, payload: &str, retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, topic);
+    body.extend_from_slice(payload.as_bytes());
+    packet(PUBLISH | if retain { RETAIN } else { 0 }, &body)
+}
+
+fn subscribe_packet(packet_id: u16, filter: &str) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    put_string(&mut body, filter);
+    body.push(0); // QoS 0
+    packet(SUBSCRIBE, &body)
+}
+
+/// Reads the rest of a packet once its first byte arrived.
+async fn read_body(stream: &mut (impl AsyncRead + Unpin)) -> anyhow::Result<Vec<u8>> {
+    let mut len = 0usize;
+    let mut shift = 0;
+    loop {
+        let b = stream.read_u8().await?;
+        len |= ((b & 0x7f) as usize) << shift;
+        if b & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 21 {
+            anyhow::bail!("Malformed remaining length");
+        }
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+/// Splits an incoming PUBLISH into topic and payload.
+fn parse_publish(header: u8, body: &[u8]) -> Option<(String, Vec<u8>)> {
+    let len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    let topic = String::from_utf8_lossy(body.get(2..2 + len)?).to_string();
+    let mut offset = 2 + len;
+    if (header >> 1) & 0x03 > 0 {
+        // packet id, only present with QoS 1 and 2
+        offset += 2;
+    }
+    Some((topic, body.get(offset..)?.to_vec()))
+}
+
+/// The retained topics and values for a bridge event, relative to atem/<name>/.
+fn topics(event: &Value) -> Vec<(String, String)> {
+    let name = event
+        .get("event")
+        .and_then(|e| e.as_str())
+        .unwrap_or_default();
+    // strings go out bare, everything else as JSON
+    let value = |key: &str| match event.get(key) {
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+        None => "null".to_string(),
+    };
+    let me = event.get("me").and_then(|m| m.as_u64()).unwrap_or(0);
+    let prefix = if me == 0 {
+        String::new()
+    } else {
+        format!("me/{}/", me + 1)
+    };
+    match name {
+        "program" | "preview" => vec![(format!("{}{}", prefix, name), value("input"))],
+        "keyer" => vec![(
+            format!(
+                "{}usk/{}/onair",
+                prefix,
+                event["keyer"].as_u64().unwrap_or(0) + 1
+            ),
+            value("on_air"),
+        )],
+        "dsk" => vec![(
+            format!("dsk/{}/onair", event["index"].as_u64().unwrap_or(0) + 1),
+            value("on_air"),
+        )],
+        "tally" => vec![(
+            format!("tally/{}", value("source")),
+            json!({ "program": event["program"], "preview": event["preview"] }).to_string(),
+        )],
+        "macro" => vec![("macro/running".to_string(), value("running"))],
+        "streaming" => vec![("streaming/status".to_string(), value("status"))],
+        "recording" => vec![("recording/status".to_string(), value("status"))],
+        _ => Vec::new(),
+    }
+}
+
+/// Handles a message on atem/<name>/command/<action>.
+///
+/// The payload is a JSON object with the arguments, or just a number for the input or index.
+fn command(atem: &SharedAtem, action: &str, payload: &[u8]) -> anyhow::Result<()> {
+    let args = match serde_json::from_slice::<Value>(payload) {
+        Ok(Value::Number(n)) => json!({ "input": n, "index": n }),
+        Ok(v @ Value::Object(_)) => v,
+        _ if payload.is_empty() => json!({}),
+        _ => anyhow::bail!("Expected a JSON object or number"),
+    };
+    Action::from_json(action, &args)?.apply(&mut atem.lock().unwrap())
+}
+
+/// Runs one connection to the broker, `delay` goes back to the minimum once it accepted us.
+async fn session(
+    atem: &SharedAtem,
+    rx: &mut broadcast::Receiver<Value>,
+    config: &MqttConfig,
+    delay: &mut Duration,
+) -> anyhow::Result<()> {
+    let base = format!("atem/{}", config.name);
+    let online = format!("{}/online", base);
+    let command_prefix = format!("{}/command/", base);
+
+    let mut stream = TcpStream::connect(&config.broker).await?;
+    let client_id = format!("bmda-bridge-{}", config.name);
+    stream
+        .write_all(&connect_packet(config, &client_id, &online))
+        .await?;
+    let header = stream.read_u8().await?;
+    let body = read_body(&mut stream).await?;
+    if header != CONNACK || body.get(1) != Some(&0) {
+        anyhow::bail!("Broker refused the connection ({:?})", body.get(1));
+    }
+    println!("MQTT connected to {}", config.broker);
+    *delay = MIN_RECONNECT_DELAY;
+
+    stream
+        .write_all(&subscribe_packet(1, &format!("{}#", command_prefix)))
+        .await?;
+    stream
+        .write_all(&publish_packet(&online, "true", true))
+        .await?;
+
+    // everything we know so far, the broker keeps it for late subscribers
+    let state = atem.lock().unwrap().state().clone();
+    let mut initial: Vec<Value> = events::diff(&AtemState::default(), &state)
+        .into_iter()
+        .filter(|e| e["event"] != "tally")
+        .collect();
+    // diff only reports sources that are lit, publish the dark ones too
+    for (source, tally) in &state.tally.by_source {
+        initial.push(json!({
+            "event": "tally",
+            "source": source,
+            "program": tally.program,
+            "preview": tally.preview,
+        }));
+    }
+    for event in initial {
+        for (topic, value) in topics(&event) {
+            let topic = format!("{}/{}", base, topic);
+            stream
+                .write_all(&publish_packet(&topic, &value, true))
+                .await?;
+        }
+    }
+
+    let (mut reader, mut writer) = stream.into_split();
+    let mut ping = tokio::time::interval(KEEP_ALIVE / 2);
+    loop {
+        tokio::select! {
+            _ = ping.tick() => writer.write_all(&[PINGREQ, 0]).await?,
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    for (topic, value) in topics(&event) {
+                        let topic = format!("{}/{}", base, topic);
+                        writer.write_all(&publish_packet(&topic, &value, true)).await?;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    println!("MQTT missed {} events", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            b = reader.read_u8() => {
+                let header = b?;
+                let body = read_body(&mut reader).await?;
+                if header & 0xf0 == PUBLISH {
+                    if let Some((topic, payload)) = parse_publish(header, &body) {
+                        if let Some(action) = topic.strip_prefix(&command_prefix) {
+                            if let Err(e) = command(atem, action, &payload) {
+                                println!("MQTT {}: {}", topic, e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Keeps a session with the broker, reconnecting with a growing delay when it drops.
+pub async fn run(atem: SharedAtem, events: EventHub, config: MqttConfig) -> anyhow::Result<()> {
+    let mut rx = events.subscribe();
+    let mut delay = MIN_RECONNECT_DELAY;
+    loop {
+        match session(&atem, &mut rx, &config, &mut delay).await {
+            Ok(()) => return Ok(()),
+            Err(e) => println!("MQTT connection to {} lost: {}", config.broker, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        // changes that happened while we were away are covered by the full publish on connect
+        rx = rx.resubscribe();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use bmda_bridge::AtemMini;
+
+    #[tokio::test]
+    async fn remaining_length() -> anyhow::Result<()> {
+        for (len, encoded) in [
+            (0, vec![0x00]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (16383, vec![0xff, 0x7f]),
+            (16384, vec![0x80, 0x80, 0x01]),
+        ] {
+            let body = vec![7; len];
+            let p = packet(PUBLISH, &body);
+            assert_eq!(p[0], PUBLISH);
+            assert_eq!(&p[1..1 + encoded.len()], &encoded[..], "{}", len);
+            assert_eq!(read_body(&mut &p[1..]).await?, body);
+        }
+
+        // more than four length bytes
+        assert!(read_body(&mut &[0x80, 0x80, 0x80, 0x80, 0x01][..])
+            .await
+            .is_err());
+        // body shorter than announced
+        assert!(read_body(&mut &[0x03, 1, 2][..]).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn publish_round_trip() {
+        let p = publish_packet("atem/mini/program", "3", true);
+        assert_eq!(p[0], PUBLISH | RETAIN);
+        assert_eq!(
+            parse_publish(p[0], &p[2..]),
+            Some(("atem/mini/program".to_string(), b"3".to_vec()))
+        );
+    }
+
+    #[test]
+    fn publish_with_packet_id() {
+        let mut body = Vec::new();
+        put_string(&mut body, "a/b");
+        body.extend_from_slice(&[0x12, 0x34]);
+        body.extend_from_slice(b"{}");
+        // QoS 1 carries a packet id between topic and payload
+        assert_eq!(
+            parse_publish(PUBLISH | 0x02, &body),
+            Some(("a/b".to_string(), b"{}".to_vec()))
+        );
+        // QoS 0 doesn't
+        assert_eq!(
+            parse_publish(PUBLISH, &body),
+            Some(("a/b".to_string(), b"\x12\x34{}".to_vec()))
+        );
+        assert_eq!(parse_publish(PUBLISH, &[0, 5, b'a']), None);
+        assert_eq!(parse_publish(PUBLISH | 0x04, &[0, 1, b'a', 0]), None);
+    }
+
+    #[test]
+    fn connect_flags() {
+        let mut config = MqttConfig {
+            broker: "localhost:1883".to_string(),
+            name: "mini".to_string(),
+            username: None,
+            password: None,
+        };
+        let p = connect_packet(&config, "id", "atem/mini/online");
+        assert_eq!(p[0], CONNECT);
+        assert_eq!(p[1] as usize, p.len() - 2);
+        assert_eq!(&p[2..9], b"\0\x04MQTT\x04");
+        assert_eq!(p[9], 0x26);
+        assert_eq!(&p[10..12], &30u16.to_be_bytes());
+        assert_eq!(&p[12..16], b"\0\x02id");
+        assert!(p.ends_with(b"\0\x05false"));
+
+        config.username = Some("user".to_string());
+        config.password = Some("secret".to_string());
+        let p = connect_packet(&config, "id", "atem/mini/online");
+        assert_eq!(p[9], 0xe6);
+        assert!(p.ends_with(b"\0\x04user\0\x06secret"));
+    }
+
+    #[test]
+    fn event_topics() {
+        assert_eq!(
+            topics(&json!({ "event": "keyer", "me": 1, "keyer": 0, "on_air": true })),
+            [("me/2/usk/1/onair".to_string(), "true".to_string())]
+        );
+        assert_eq!(
+            topics(&json!({ "event": "streaming", "status": "Streaming" })),
+            [("streaming/status".to_string(), "Streaming".to_string())]
+        );
+        assert!(topics(&json!({ "event": "state" })).is_empty());
+    }
+
+    fn config(broker: String) -> MqttConfig {
+        MqttConfig {
+            broker,
+            name: "test".to_string(),
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+        }
+    }
+
+    #[test]
+    fn password_needs_username() {
+        let mut c = config(String::new());
+        let p = connect_packet(&c, "id", "will");
+        // flags follow the protocol name and level
+        assert_eq!(p[9], 0x80 | 0x40 | 0x20 | 0x04 | 0x02);
+        c.username = None;
+        let p = connect_packet(&c, "id", "will");
+        assert_eq!(p[9], 0x20 | 0x04 | 0x02);
+        assert!(!p.windows(6).any(|w| w == b"secret"));
+    }
+
+    /// The next packet from the bridge, skipping pings.
+    async fn next_packet(stream: &mut TcpStream) -> anyhow::Result<(u8, Vec<u8>)> {
+        loop {
+            let header = stream.read_u8().await?;
+            let body = read_body(stream).await?;
+            if header != PINGREQ {
+                return Ok((header, body));
+            }
+        }
+    }
+
+    async fn expect_publish(stream: &mut TcpStream, topic: &str, payload: &str) {
+        let (header, body) = next_packet(stream).await.unwrap();
+        assert_eq!(header, PUBLISH | RETAIN);
+        assert_eq!(
+            parse_publish(header, &body),
+            Some((topic.to_string(), payload.as_bytes().to_vec()))
+        );
+    }
+
+    #[tokio::test]
+    async fn session_with_broker() -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let config = config(listener.local_addr()?.to_string());
+        let atem: SharedAtem = Arc::new(Mutex::new(AtemMini::new()));
+        let (tx, mut rx) = broadcast::channel(16);
+        let mut delay = MAX_RECONNECT_DELAY;
+
+        let broker = async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (header, body) = next_packet(&mut stream).await.unwrap();
+            assert_eq!(header, CONNECT);
+            let mut expected = Vec::new();
+            put_string(&mut expected, "atem/test/online");
+            assert!(body.windows(expected.len()).any(|w| w == expected));
+            assert!(body.ends_with(b"\x00\x04user\x00\x06secret"));
+            stream.write_all(&[CONNACK, 2, 0, 0]).await.unwrap();
+
+            let (header, body) = next_packet(&mut stream).await.unwrap();
+            assert_eq!(header, SUBSCRIBE);
+            assert_eq!(&body[4..], b"atem/test/command/#\x00");
+            expect_publish(&mut stream, "atem/test/online", "true").await;
+
+            tx.send(json!({ "event": "program", "me": 0, "input": 3 }))
+                .unwrap();
+            expect_publish(&mut stream, "atem/test/program", "3").await;
+
+            // a command the switcher can't take yet doesn't end the session
+            stream
+                .write_all(&publish_packet("atem/test/command/cut", "", false))
+                .await
+                .unwrap();
+            tx.send(json!({ "event": "dsk", "index": 1, "on_air": true }))
+                .unwrap();
+            expect_publish(&mut stream, "atem/test/dsk/2/onair", "true").await;
+            // dropping tx ends the session, the connection stays open until then
+            stream
+        };
+
+        let (result, _stream) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(session(&atem, &mut rx, &config, &mut delay), broker)
+        })
+        .await?;
+        result?;
+        assert_eq!(delay, MIN_RECONNECT_DELAY);
+        Ok(())
+    }
+
+    #[test]
+    fn commands() {
+        let atem: SharedAtem = Arc::new(Mutex::new(AtemMini::new()));
+        let e = command(&atem, "program", b"70000").unwrap_err();
+        assert!(e.to_string().contains("out of range"), "{}", e);
+        assert!(command(&atem, "program", b"[1]").is_err());
+        // parsed, the switcher just hasn't sent its topology
+        let e = command(&atem, "program", br#"{"input": 2}"#).unwrap_err();
+        assert!(e.to_string().contains("topology"), "{}", e);
+    }
+}
@@ -0,0 +1,405 @@
+use serde_json::Value;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+
+use crate::bridge::actions::Action;
+use crate::bridge::events::EventHub;
+use crate::bridge::SharedAtem;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Bool(bool),
+}
+
+impl OscArg {
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            OscArg::Int(v) => Some(*v as i64),
+            OscArg::Float(v) => Some(*v as i64),
+            OscArg::Bool(v) => Some(*v as i64),
+            OscArg::String(s) => s.parse().ok(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+/// Strings are NUL terminated and padded to 4 bytes.
+fn read_string(data: &[u8], offset: &mut usize) -> Option<String> {
+    let rest = data.get(*offset..)?;
+    let end = rest.iter().position(|b| *b == 0)?;
+    let s = String::from_utf8_lossy(&rest[..end]).to_string();
+    *offset += (end + 4) / 4 * 4;
+    Some(s)
+}
+
+fn read_u32(data: &[u8], offset: &mut usize) -> Option<u32> {
+    let b = data.get(*offset..*offset + 4)?;
+    *offset += 4;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    out.extend(std::iter::repeat(0).take(padding));
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.into(),
+            args,
+        }
+    }
+
+    /// Decodes a packet, bundles are flattened into their messages.
+    pub fn decode(data: &[u8]) -> Vec<OscMessage> {
+        let mut messages = Vec::new();
+        Self::decode_into(data, &mut messages);
+        messages
+    }
+
+    fn decode_into(data: &[u8], messages: &mut Vec<OscMessage>) {
+        if data.starts_with(b"#bundle\0") {
+            // skip the time tag
+            let mut offset = 16;
+            while let Some(size) = read_u32(data, &mut offset) {
+                let end = offset + size as usize;
+                if let Some(element) = data.get(offset..end) {
+                    Self::decode_into(element, messages);
+                }
+                offset = end;
+            }
+        } else if let Some(m) = Self::decode_message(data) {
+            messages.push(m);
+        }
+    }
+
+    fn decode_message(data: &[u8]) -> Option<OscMessage> {
+        let mut offset = 0;
+        let address = read_string(data, &mut offset)?;
+        let tags = if offset < data.len() {
+            read_string(data, &mut offset)?
+        } else {
+            String::new()
+        };
+
+        let mut args = Vec::new();
+        for tag in tags.chars().skip_while(|c| *c == ',') {
+            let arg = match tag {
+                'i' => OscArg::Int(read_u32(data, &mut offset)? as i32),
+                'f' => OscArg::Float(f32::from_bits(read_u32(data, &mut offset)?)),
+                's' => OscArg::String(read_string(data, &mut offset)?),
+                'T' => OscArg::Bool(true),
+                'F' => OscArg::Bool(false),
+                // we can't skip types we don't know the size of
+                _ => break,
+            };
+            args.push(arg);
+        }
+
+        Some(OscMessage { address, args })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_string(&mut out, &self.address);
+        let mut tags = String::from(",");
+        for a in &self.args {
+            tags.push(match a {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+                OscArg::Bool(true) => 'T',
+                OscArg::Bool(false) => 'F',
+            });
+        }
+        write_string(&mut out, &tags);
+        for a in &self.args {
+            match a {
+                OscArg::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+                OscArg::Float(v) => out.extend_from_slice(&v.to_bits().to_be_bytes()),
+                OscArg::String(s) => write_string(&mut out, s),
+                OscArg::Bool(_) => {}
+            }
+        }
+        out
+    }
+}
+
+/// Argument `i` of `message`, failing for numbers that don't fit `T` instead of wrapping them.
+fn arg<T: TryFrom<i64>>(message: &OscMessage, i: usize) -> anyhow::Result<T> {
+    let n = message
+        .args
+        .get(i)
+        .and_then(|a| a.as_i64())
+        .ok_or_else(|| anyhow::anyhow!("{} needs a number argument", message.address))?;
+    T::try_from(n).map_err(|_| anyhow::anyhow!("{} is out of range for {}", n, message.address))
+}
+
+/// Maps an incoming message to an action, numbers in addresses count from 1 like on the panel.
+///
+/// /atem/program 3, /atem/preview 3, /atem/cut, /atem/auto, /atem/macro/run 0,
+/// /atem/dsk/1/onair 1, /atem/dsk/1/auto, /atem/usk/1/onair 1,
+/// /atem/streaming/start, /atem/streaming/stop, /atem/recording/start, /atem/recording/stop
+fn action(message: &OscMessage) -> anyhow::Result<Action> {
+    let index = |s: &str| -> anyhow::Result<u8> {
+        match s.parse::<u8>() {
+            Ok(n) if n > 0 => Ok(n - 1),
+            _ => anyhow::bail!("Invalid index {} in {}", s, message.address),
+        }
+    };
+
+    let segments: Vec<&str> = message
+        .address
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    let action = match segments.as_slice() {
+        ["atem", "program"] => Action::Program {
+            me: 0,
+            input: arg(message, 0)?,
+        },
+        ["atem", "preview"] => Action::Preview {
+            me: 0,
+            input: arg(message, 0)?,
+        },
+        ["atem", "cut"] => Action::Cut { me: 0 },
+        ["atem", "auto"] => Action::Auto { me: 0 },
+        ["atem", "macro", "run"] => Action::RunMacro(arg(message, 0)?),
+        ["atem", "dsk", n, "onair"] => Action::DskOnAir {
+            index: index(n)?,
+            on_air: arg::<i64>(message, 0)? != 0,
+        },
+        ["atem", "dsk", n, "auto"] => Action::DskAuto { index: index(n)? },
+        ["atem", "usk", n, "onair"] => Action::KeyerOnAir {
+            me: 0,
+            keyer: index(n)?,
+            on_air: arg::<i64>(message, 0)? != 0,
+        },
+        ["atem", "streaming", "start"] => Action::StartStreaming,
+        ["atem", "streaming", "stop"] => Action::StopStreaming,
+        ["atem", "recording", "start"] => Action::StartRecording,
+        ["atem", "recording", "stop"] => Action::StopRecording,
+        _ => anyhow::bail!("Unknown address {}", message.address),
+    };
+    Ok(action)
+}
+
+fn int(event: &Value, key: &str) -> i32 {
+    event.get(key).and_then(|v| v.as_i64()).unwrap_or(-1) as i32
+}
+
+fn flag(event: &Value, key: &str) -> OscArg {
+    OscArg::Int(event.get(key).and_then(|v| v.as_bool()).unwrap_or(false) as i32)
+}
+
+/// Translates a bridge event into the messages we send to the feedback targets.
+fn feedback(event: &Value) -> Vec<OscMessage> {
+    let name = event
+        .get("event")
+        .and_then(|e| e.as_str())
+        .unwrap_or_default();
+    let status = || {
+        OscArg::String(
+            event
+                .get("status")
+                .and_then(|s| s.as_str())
+                .unwrap_or("Unknown")
+                .to_string(),
+        )
+    };
+    match name {
+        "program" | "preview" if int(event, "me") == 0 => vec![OscMessage::new(
+            format!("/atem/{}", name),
+            vec![OscArg::Int(int(event, "input"))],
+        )],
+        "tally" => {
+            let source = int(event, "source");
+            vec![
+                OscMessage::new(
+                    format!("/atem/tally/{}/program", source),
+                    vec![flag(event, "program")],
+                ),
+                OscMessage::new(
+                    format!("/atem/tally/{}/preview", source),
+                    vec![flag(event, "preview")],
+                ),
+            ]
+        }
+        "dsk" => vec![OscMessage::new(
+            format!("/atem/dsk/{}/onair", int(event, "index") + 1),
+            vec![flag(event, "on_air")],
+        )],
+        "keyer" if int(event, "me") == 0 => vec![OscMessage::new(
+            format!("/atem/usk/{}/onair", int(event, "keyer") + 1),
+            vec![flag(event, "on_air")],
+        )],
+        "macro" => vec![OscMessage::new(
+            "/atem/macro/running",
+            vec![OscArg::Int(int(event, "running"))],
+        )],
+        "streaming" => vec![OscMessage::new("/atem/streaming/status", vec![status()])],
+        "recording" => vec![OscMessage::new("/atem/recording/status", vec![status()])],
+        _ => Vec::new(),
+    }
+}
+
+/// Receives OSC on `addr` and sends state changes to `targets`.
+pub async fn run(
+    atem: SharedAtem,
+    events: EventHub,
+    addr: String,
+    targets: Vec<String>,
+) -> anyhow::Result<()> {
+    let socket = UdpSocket::bind(&addr).await?;
+    println!("OSC on {}, feedback to {:?}", addr, targets);
+    let mut rx = events.subscribe();
+    let mut buf = [0; 65535];
+
+    loop {
+        tokio::select! {
+            r = socket.recv_from(&mut buf) => {
+                let (n, peer) = r?;
+                for message in OscMessage::decode(&buf[..n]) {
+                    let result = action(&message)
+                        .and_then(|a| a.apply(&mut atem.lock().unwrap()));
+                    if let Err(e) = result {
+                        println!("OSC {} from {}: {}", message.address, peer, e);
+                    }
+                }
+            }
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    for message in feedback(&event) {
+                        let packet = message.encode();
+                        for target in &targets {
+                            if let Err(e) = socket.send_to(&packet, target).await {
+                                println!("OSC feedback to {} failed: {}", target, e);
+                            }
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    println!("OSC feedback missed {} events", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn encode_pads_to_four_bytes() {
+        let m = OscMessage::new(
+            "/atem/program",
+            vec![
+                OscArg::Int(3),
+                OscArg::Bool(true),
+                OscArg::String("abcd".into()),
+            ],
+        );
+        let mut expected = b"/atem/program\0\0\0,iTs\0\0\0\0".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 3]);
+        expected.extend_from_slice(b"abcd\0\0\0\0");
+        assert_eq!(m.encode(), expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let m = OscMessage::new(
+            "/a",
+            vec![
+                OscArg::Int(-1),
+                OscArg::Float(0.5),
+                OscArg::String(String::new()),
+                OscArg::Bool(false),
+            ],
+        );
+        assert_eq!(OscMessage::decode(&m.encode()), [m]);
+    }
+
+    #[test]
+    fn decode_bundles_and_broken_packets() {
+        let a = OscMessage::new("/atem/cut", vec![]);
+        let b = OscMessage::new("/atem/preview", vec![OscArg::Int(2)]);
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        for m in [&a, &b] {
+            let data = m.encode();
+            bundle.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            bundle.extend_from_slice(&data);
+        }
+        assert_eq!(OscMessage::decode(&bundle), [a, b.clone()]);
+
+        // no type tags at all is an old style message without arguments
+        assert_eq!(
+            OscMessage::decode(b"/atem/cut\0\0\0"),
+            [OscMessage::new("/atem/cut", vec![])]
+        );
+        // the argument is missing
+        let data = b.encode();
+        assert!(OscMessage::decode(&data[..data.len() - 4]).is_empty());
+        assert!(OscMessage::decode(b"/no/terminator").is_empty());
+    }
+
+    #[test]
+    fn actions() {
+        let m = |address: &str, args: Vec<OscArg>| action(&OscMessage::new(address, args));
+        assert_eq!(
+            m("/atem/program", vec![OscArg::Float(4.0)]).unwrap(),
+            Action::Program { me: 0, input: 4 }
+        );
+        assert_eq!(
+            m("/atem/usk/2/onair", vec![OscArg::String("1".into())]).unwrap(),
+            Action::KeyerOnAir {
+                me: 0,
+                keyer: 1,
+                on_air: true
+            }
+        );
+        assert!(m("/atem/dsk/0/auto", vec![]).is_err());
+        assert!(m("/atem/preview", vec![]).is_err());
+        assert!(m("/atem/unknown", vec![]).is_err());
+
+        // out of range numbers fail instead of wrapping around
+        assert!(m("/atem/program", vec![OscArg::Int(70000)]).is_err());
+        assert!(m("/atem/preview", vec![OscArg::Int(-1)]).is_err());
+        assert!(m("/atem/macro/run", vec![OscArg::Int(256)]).is_err());
+        assert_eq!(
+            m("/atem/macro/run", vec![OscArg::Int(255)]).unwrap(),
+            Action::RunMacro(255)
+        );
+    }
+
+    #[test]
+    fn feedback_messages() {
+        let tally = feedback(&json!({ "event": "tally", "source": 2, "program": true }));
+        assert_eq!(
+            tally,
+            [
+                OscMessage::new("/atem/tally/2/program", vec![OscArg::Int(1)]),
+                OscMessage::new("/atem/tally/2/preview", vec![OscArg::Int(0)]),
+            ]
+        );
+        assert_eq!(
+            feedback(&json!({ "event": "dsk", "index": 0, "on_air": true })),
+            [OscMessage::new("/atem/dsk/1/onair", vec![OscArg::Int(1)])]
+        );
+        // only the first ME has addresses
+        assert!(feedback(&json!({ "event": "program", "me": 1, "input": 3 })).is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+use std::net::SocketAddr;
+
+use tokio::sync::broadcast;
+
+use bmda_bridge::{AtemServer, RawChunk, ServerEvent};
+
+use crate::bridge::SharedAtem;
+
+/// Passed on to clients but not worth replaying to new ones.
+const VOLATILE_CHUNKS: &[&str] = &[
+    "Time", "FMLv", "FDLv", "AMLv", "FTDa", "FTDC", "FTCD", "FTUA", "FTDE", "LKOB", "LKST",
+];
+
+/// How many leading data bytes tell chunks of one type apart, e.g. the ME of PrgI.
+///
+/// Others use 4 bytes, which holds the index of most chunks and maybe some of the value, that
+/// only costs a few extra entries since the cache is replayed in order. Chunks whose index
+/// reaches past that, like the 64 bit Fairlight source, have to be listed.
+fn key_len(name: &str) -> usize {
+    match name {
+        "_ver" | "_pin" | "_top" | "_mpl" | "_MvC" | "_TlC" | "_MAC" | "_FAC" | "_VMC" | "_MeM"
+        | "TlIn" | "TlSr" | "MRPr" | "StRS" | "RTMS" | "VidM" | "FMPP" | "FAMP" | "MOCP"
+        | "AMLP" | "FMHP" | "FAMS" => 0,
+        "_MeC" | "_SSC" | "PrgI" | "PrvI" | "DskS" | "DskP" | "DskB" | "TrSS" | "TrPr" | "TrPs"
+        | "FtbS" | "FtbP" | "AMBP" | "MPCE" | "SSrc" | "MvPr" => 1,
+        "KeOn" | "InPr" | "FAIP" | "SSBP" | "MvIn" | "MvVM" | "SaMw" => 2,
+        "CCdP" => 3,
+        // input, then the source at 8
+        "FASP" | "AICP" | "AILP" | "AIXP" => 16,
+        "AEBP" => 17,
+        _ => 4,
+    }
+}
+
+/// The switcher's state as the chunks that told us about it, in the order they last changed.
+#[derive(Default)]
+struct ChunkCache {
+    chunks: Vec<RawChunk>,
+    /// Seen InCm, the initial state is all there.
+    complete: bool,
+}
+
+impl ChunkCache {
+    /// Returns whether the chunk is passed on to clients.
+    fn apply(&mut self, chunk: &RawChunk) -> bool {
+        let name = chunk.name();
+        if name == "InCm" {
+            self.complete = true;
+            // clients got their own with our replay
+            return false;
+        }
+        if VOLATILE_CHUNKS.contains(&name.as_str()) {
+            return true;
+        }
+        let len = key_len(&name).min(chunk.data.len());
+        let key = &chunk.data[..len];
+        self.chunks
+            .retain(|c| c.name != chunk.name || c.data.get(..len) != Some(key));
+        self.chunks.push(chunk.clone());
+        true
+    }
+
+    /// What a new client gets, closed with InCm.
+    fn initial(&self) -> Vec<RawChunk> {
+        let mut chunks = self.chunks.clone();
+        chunks.push(RawChunk::new(b"InCm", &[1, 0, 0, 0]));
+        chunks
+    }
+}
+
+async fn send_initial(
+    server: &mut AtemServer,
+    cache: &ChunkCache,
+    addr: SocketAddr,
+) -> anyhow::Result<()> {
+    println!(
+        "Proxy client {} connected, sending {} chunks",
+        addr,
+        cache.chunks.len()
+    );
+    server.send(addr, &cache.initial()).await
+}
+
+/// Serves the switcher protocol on `addr`, so several clients share our one session.
+///
+/// `chunks` comes from `AtemMini::raw_chunks()` taken before the first `update()`.
+pub async fn run(
+    atem: SharedAtem,
+    mut chunks: broadcast::Receiver<RawChunk>,
+    addr: String,
+) -> anyhow::Result<()> {
+    let mut server = AtemServer::bind(&addr).await?;
+    println!("Proxy on {}", addr);
+    let mut cache = ChunkCache::default();
+    // clients that connected before we had the initial state
+    let mut pending = Vec::new();
+
+    loop {
+        tokio::select! {
+            event = server.next_event() => match event? {
+                ServerEvent::Connected(addr) => {
+                    if cache.complete {
+                        send_initial(&mut server, &cache, addr).await?;
+                    } else {
+                        pending.push(addr);
+                    }
+                }
+                ServerEvent::Commands(addr, commands) => {
+                    let mut atem = atem.lock().unwrap();
+                    for c in &commands {
+                        if let Err(e) = atem.send_raw_chunk(c) {
+                            println!("Proxy {} from {}: {}", c.name(), addr, e);
+                        }
+                    }
+                }
+                ServerEvent::Disconnected(addr) => {
+                    pending.retain(|a| *a != addr);
+                    println!("Proxy client {} disconnected", addr);
+                }
+            },
+            chunk = chunks.recv() => match chunk {
+                Ok(chunk) => {
+                    let was_complete = cache.complete;
+                    // take what else is queued, so clients get it in few packets too
+                    let mut updates = Vec::new();
+                    let mut next = Some(chunk);
+                    while let Some(chunk) = next {
+                        if cache.apply(&chunk) {
+                            updates.push(chunk);
+                        }
+                        next = chunks.try_recv().ok();
+                    }
+                    if was_complete {
+                        server.broadcast(&updates).await?;
+                    } else if cache.complete {
+                        for addr in std::mem::take(&mut pending) {
+                            send_initial(&mut server, &cache, addr).await?;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    // the cache missed them as well, but clients at least agree with it again
+                    println!("Proxy missed {} chunks from the switcher, resyncing clients", n);
+                    if cache.complete {
+                        for addr in server.clients() {
+                            send_initial(&mut server, &cache, addr).await?;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fasp(input: u16, source: i64, gain: u8) -> RawChunk {
+        let mut data = vec![0; 24];
+        data[0..2].copy_from_slice(&input.to_be_bytes());
+        data[8..16].copy_from_slice(&source.to_be_bytes());
+        data[16] = gain;
+        RawChunk::new(b"FASP", &data)
+    }
+
+    fn names(cache: &ChunkCache) -> Vec<(String, Vec<u8>)> {
+        cache
+            .initial()
+            .iter()
+            .map(|c| (c.name(), c.data.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn replaces_by_key_and_keeps_order() {
+        let mut cache = ChunkCache::default();
+        assert!(cache.apply(&RawChunk::new(b"PrgI", &[0, 0, 0, 1])));
+        assert!(cache.apply(&RawChunk::new(b"PrgI", &[1, 0, 0, 2])));
+        assert!(cache.apply(&fasp(1, -256, 1)));
+        assert!(cache.apply(&fasp(1, -255, 2)));
+        // replaces the first ME's program and moves it to the end
+        assert!(cache.apply(&RawChunk::new(b"PrgI", &[0, 0, 0, 3])));
+        assert!(cache.apply(&fasp(1, -256, 3)));
+
+        assert_eq!(
+            names(&cache),
+            [
+                ("PrgI".to_string(), vec![1, 0, 0, 2]),
+                ("FASP".to_string(), fasp(1, -255, 2).data),
+                ("PrgI".to_string(), vec![0, 0, 0, 3]),
+                ("FASP".to_string(), fasp(1, -256, 3).data),
+                ("InCm".to_string(), vec![1, 0, 0, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn equalizer_bands_stay_apart() {
+        let mut cache = ChunkCache::default();
+        for band in 0..6 {
+            let mut data = fasp(2, -65280, 0).data;
+            data[16] = band;
+            cache.apply(&RawChunk::new(b"AEBP", &data));
+        }
+        assert_eq!(cache.chunks.len(), 6);
+    }
+
+    #[test]
+    fn volatile_and_init_chunks() {
+        let mut cache = ChunkCache::default();
+        assert!(cache.apply(&RawChunk::new(b"Time", &[0; 8])));
+        assert!(cache.chunks.is_empty());
+        assert!(!cache.complete);
+        assert!(!cache.apply(&RawChunk::new(b"InCm", &[1, 0, 0, 0])));
+        assert!(cache.complete);
+        assert!(cache.chunks.is_empty());
+        // shorter than the key still works
+        assert!(cache.apply(&RawChunk::new(b"FASP", &[0, 1])));
+        assert!(cache.apply(&RawChunk::new(b"FASP", &[0, 1])));
+        assert_eq!(cache.chunks.len(), 1);
+    }
+}
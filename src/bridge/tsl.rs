@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+
+use bmda_bridge::{AtemState, TallyState};
+
+use crate::bridge::SharedAtem;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// UDP may drop packets, so everything is sent again now and then.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// v3.1 displays have 16 characters, addresses go up to 126.
+const V31_LABEL_LEN: usize = 16;
+const V31_MAX_ADDRESS: u16 = 126;
+
+const V5_DLE: u8 = 0xfe;
+const V5_STX: u8 = 0x02;
+
+// v5 tally colours
+const V5_OFF: u16 = 0;
+const V5_RED: u16 = 1;
+const V5_GREEN: u16 = 2;
+const V5_AMBER: u16 = 3;
+const V5_FULL_BRIGHTNESS: u16 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TslProtocol {
+    V31,
+    V5,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TslTransport {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone)]
+pub struct TslDestination {
+    pub protocol: TslProtocol,
+    pub transport: TslTransport,
+    pub address: String,
+}
+
+/// One UMD, indexed by input number (tally channel + 1).
+#[derive(Debug, Clone, PartialEq)]
+struct Display {
+    index: u16,
+    label: String,
+    tally: TallyState,
+}
+
+/// Builds the displays from TlIn tally and InPr names, `labels` override the names.
+fn displays(state: &AtemState, labels: &BTreeMap<u16, String>) -> Vec<Display> {
+    state
+        .tally
+        .by_index
+        .iter()
+        .enumerate()
+        .map(|(i, tally)| {
+            let index = i as u16 + 1;
+            let label = labels
+                .get(&index)
+                .cloned()
+                .or_else(|| state.inputs.get(&index).map(|p| p.long_name.clone()))
+                .unwrap_or_else(|| format!("Input {}", index));
+            Display {
+                index,
+                label,
+                tally: *tally,
+            }
+        })
+        .collect()
+}
+
+/// 18 bytes: address, control (tally 1 program, tally 2 preview, full brightness) and text.
+fn v31_packet(display: &Display) -> Option<Vec<u8>> {
+    if display.index > V31_MAX_ADDRESS {
+        return None;
+    }
+    let mut control = 0x30;
+    if display.tally.program {
+        control |= 0x01;
+    }
+    if display.tally.preview {
+        control |= 0x02;
+    }
+
+    let mut packet = vec![0x80 + display.index as u8, control];
+    let mut text: Vec<u8> = display
+        .label
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c as u8
+            } else {
+                b'?'
+            }
+        })
+        .take(V31_LABEL_LEN)
+        .collect();
+    text.resize(V31_LABEL_LEN, b' ');
+    packet.extend_from_slice(&text);
+    Some(packet)
+}
+
+/// One v5 packet with a DMSG per display, program is red, preview green, both amber.
+fn v5_packet(screen: u16, displays: &[Display]) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.push(0); // version
+    body.push(0); // flags, ASCII text
+    body.extend_from_slice(&screen.to_le_bytes());
+
+    for display in displays {
+        let colour = match (display.tally.program, display.tally.preview) {
+            (true, true) => V5_AMBER,
+            (true, false) => V5_RED,
+            (false, true) => V5_GREEN,
+            (false, false) => V5_OFF,
+        };
+        let control = colour | (colour << 2) | (colour << 4) | (V5_FULL_BRIGHTNESS << 6);
+        let text: Vec<u8> = display
+            .label
+            .chars()
+            .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+            .collect();
+
+        body.extend_from_slice(&display.index.to_le_bytes());
+        body.extend_from_slice(&control.to_le_bytes());
+        body.extend_from_slice(&(text.len() as u16).to_le_bytes());
+        body.extend_from_slice(&text);
+    }
+
+    let mut packet = (body.len() as u16).to_le_bytes().to_vec();
+    packet.extend_from_slice(&body);
+    packet
+}
+
+/// TCP wraps v5 packets in DLE/STX and doubles every DLE inside.
+fn v5_tcp_frame(packet: &[u8]) -> Vec<u8> {
+    let mut frame = vec![V5_DLE, V5_STX];
+    for b in packet {
+        frame.push(*b);
+        if *b == V5_DLE {
+            frame.push(V5_DLE);
+        }
+    }
+    frame
+}
+
+enum TcpState {
+    Idle { retry_at: Instant },
+    Connecting(JoinHandle<anyhow::Result<TcpStream>>),
+    Connected(TcpStream),
+}
+
+/// Connects in the background, so an unreachable display doesn't hold up the others.
+struct TcpConnection {
+    state: TcpState,
+    delay: Duration,
+}
+
+impl TcpConnection {
+    fn new() -> Self {
+        Self {
+            state: TcpState::Idle {
+                retry_at: Instant::now(),
+            },
+            delay: MIN_RECONNECT_DELAY,
+        }
+    }
+
+    /// The stream once connected, until then every call starts or checks the connect.
+    async fn stream(&mut self, address: &str) -> anyhow::Result<Option<&mut TcpStream>> {
+        match &mut self.state {
+            TcpState::Idle { retry_at } if Instant::now() >= *retry_at => {
+                let address = address.to_string();
+                self.state = TcpState::Connecting(tokio::spawn(async move {
+                    Ok(
+                        tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+                            .await??,
+                    )
+                }));
+            }
+            TcpState::Connecting(handle) if handle.is_finished() => match handle.await? {
+                Ok(stream) => {
+                    self.delay = MIN_RECONNECT_DELAY;
+                    self.state = TcpState::Connected(stream);
+                }
+                Err(e) => {
+                    self.disconnected();
+                    return Err(e);
+                }
+            },
+            _ => {}
+        }
+        match &mut self.state {
+            TcpState::Connected(stream) => Ok(Some(stream)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Waits before the next connect, twice as long after every failure.
+    fn disconnected(&mut self) {
+        self.state = TcpState::Idle {
+            retry_at: Instant::now() + self.delay,
+        };
+        self.delay = (self.delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+async fn write_packets(stream: &mut TcpStream, packets: &[Vec<u8>]) -> anyhow::Result<()> {
+    for p in packets {
+        tokio::time::timeout(WRITE_TIMEOUT, stream.write_all(p)).await??;
+    }
+    Ok(())
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpConnection),
+}
+
+struct Output {
+    destination: TslDestination,
+    connection: Connection,
+}
+
+impl Output {
+    async fn new(destination: TslDestination) -> anyhow::Result<Self> {
+        let connection = match destination.transport {
+            TslTransport::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                socket.connect(&destination.address).await?;
+                Connection::Udp(socket)
+            }
+            TslTransport::Tcp => Connection::Tcp(TcpConnection::new()),
+        };
+        Ok(Self {
+            destination,
+            connection,
+        })
+    }
+
+    fn packets(&self, screen: u16, displays: &[Display]) -> Vec<Vec<u8>> {
+        match (self.destination.protocol, self.destination.transport) {
+            (TslProtocol::V31, _) => displays.iter().filter_map(v31_packet).collect(),
+            (TslProtocol::V5, TslTransport::Udp) => vec![v5_packet(screen, displays)],
+            (TslProtocol::V5, TslTransport::Tcp) => {
+                vec![v5_tcp_frame(&v5_packet(screen, displays))]
+            }
+        }
+    }
+
+    async fn send(&mut self, screen: u16, displays: &[Display]) -> anyhow::Result<()> {
+        let packets = self.packets(screen, displays);
+        match &mut self.connection {
+            Connection::Udp(socket) => {
+                for p in packets {
+                    socket.send(&p).await?;
+                }
+            }
+            Connection::Tcp(tcp) => {
+                // packets sent while connecting are lost, the refresh makes up for them
+                let Some(stream) = tcp.stream(&self.destination.address).await? else {
+                    return Ok(());
+                };
+                if let Err(e) = write_packets(stream, &packets).await {
+                    tcp.disconnected();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends the switcher's tally to all `destinations` whenever it changes.
+pub async fn run(
+    atem: SharedAtem,
+    destinations: Vec<TslDestination>,
+    labels: BTreeMap<u16, String>,
+    screen: u16,
+) -> anyhow::Result<()> {
+    let mut outputs = Vec::new();
+    for d in destinations {
+        println!(
+            "TSL {:?} over {:?} to {}",
+            d.protocol, d.transport, d.address
+        );
+        outputs.push(Output::new(d).await?);
+    }
+
+    let mut last = Vec::new();
+    let mut last_sent = Instant::now();
+    loop {
+        let current = {
+            let atem = atem.lock().unwrap();
+            displays(atem.state(), &labels)
+        };
+
+        if current != last || last_sent.elapsed() >= REFRESH_INTERVAL {
+            for output in &mut outputs {
+                if let Err(e) = output.send(screen, &current).await {
+                    println!("TSL to {} failed: {}", output.destination.address, e);
+                }
+            }
+            last = current;
+            last_sent = Instant::now();
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn display(index: u16, label: &str, program: bool, preview: bool) -> Display {
+        Display {
+            index,
+            label: label.to_string(),
+            tally: TallyState { program, preview },
+        }
+    }
+
+    #[test]
+    fn v31_framing() {
+        let p = v31_packet(&display(3, "Cam é", true, false)).unwrap();
+        assert_eq!(p.len(), 18);
+        assert_eq!(&p[..2], &[0x83, 0x31]);
+        assert_eq!(&p[2..], b"Cam ?           ");
+
+        let p = v31_packet(&display(1, "A much longer camera name", true, true)).unwrap();
+        assert_eq!(p[1], 0x33);
+        assert_eq!(&p[2..], b"A much longer ca");
+
+        assert!(v31_packet(&display(127, "Off the end", false, false)).is_none());
+    }
+
+    #[test]
+    fn v5_framing() {
+        let p = v5_packet(
+            0x0102,
+            &[display(1, "A", false, true), display(2, "", true, true)],
+        );
+        let body = [
+            0, 0, 0x02, 0x01, // version, flags, screen
+            1, 0, 0xea, 0x00, 1, 0, b'A', // green
+            2, 0, 0xff, 0x00, 0, 0, // amber
+        ];
+        assert_eq!(p[..2], (body.len() as u16).to_le_bytes());
+        assert_eq!(&p[2..], &body);
+
+        let frame = v5_tcp_frame(&[1, V5_DLE, 2]);
+        assert_eq!(frame, [V5_DLE, V5_STX, 1, V5_DLE, V5_DLE, 2]);
+    }
+
+    #[tokio::test]
+    async fn tcp_connects_in_the_background() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut output = Output::new(TslDestination {
+            protocol: TslProtocol::V31,
+            transport: TslTransport::Tcp,
+            address: listener.local_addr()?.to_string(),
+        })
+        .await?;
+        let displays = [display(1, "Cam 1", true, false)];
+
+        // returns at once, the connect runs in its own task
+        output.send(0, &displays).await?;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            let (mut peer, _) = listener.accept().await?;
+            while !matches!(
+                output.connection,
+                Connection::Tcp(TcpConnection {
+                    state: TcpState::Connected(_),
+                    ..
+                })
+            ) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                output.send(0, &displays).await?;
+            }
+
+            let mut packet = [0; 18];
+            peer.read_exact(&mut packet).await?;
+            assert_eq!(packet, v31_packet(&displays[0]).unwrap()[..]);
+            Ok(())
+        })
+        .await?
+    }
+}
@@ -0,0 +1,365 @@
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+use bmda_bridge::{AtemState, RecordingStatus, StreamingStatus};
+
+use crate::bridge::actions::Action;
+use crate::bridge::events::EventHub;
+use crate::bridge::SharedAtem;
+
+/// What we answer to VERSION, some clients check the major version.
+const VMIX_VERSION: &str = "27.0.0.49";
+
+/// One character per input as vMix sends it: 0 off, 1 program, 2 preview.
+fn tally_string(state: &AtemState) -> String {
+    state
+        .tally
+        .by_index
+        .iter()
+        .map(|t| {
+            if t.program {
+                '1'
+            } else if t.preview {
+                '2'
+            } else {
+                '0'
+            }
+        })
+        .collect()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The part of vMix's XML state that maps onto the switcher: inputs, active and preview.
+fn state_xml(state: &AtemState) -> String {
+    let main = state.mix_effects.mix_effects.get(&0);
+    let mut xml = format!("<vmix><version>{}</version><inputs>", VMIX_VERSION);
+    for (id, p) in &state.inputs {
+        xml.push_str(&format!(
+            "<input key=\"{id}\" number=\"{id}\" type=\"Capture\" title=\"{name}\" shortTitle=\"{short}\">{name}</input>",
+            id = id,
+            name = xml_escape(&p.long_name),
+            short = xml_escape(&p.short_name),
+        ));
+    }
+    xml.push_str(&format!(
+        "</inputs><active>{}</active><preview>{}</preview><streaming>{}</streaming><recording>{}</recording></vmix>",
+        main.and_then(|m| m.program).unwrap_or(0),
+        main.and_then(|m| m.preview).unwrap_or(0),
+        if state.streaming.status == Some(StreamingStatus::Streaming) {
+            "True"
+        } else {
+            "False"
+        },
+        if state.recording.status == Some(RecordingStatus::Recording) {
+            "True"
+        } else {
+            "False"
+        },
+    ));
+    xml
+}
+
+fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Looks up the value of `key` in a query like "Input=2&Mix=0", keys ignore case as in vMix.
+fn query_value(query: &str, key: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (k, v) = pair.split_once('=')?;
+        k.eq_ignore_ascii_case(key).then(|| url_decode(v))
+    })
+}
+
+/// vMix inputs are given by number or by title, we take ATEM source ids and input names.
+fn input(state: &AtemState, query: &str) -> anyhow::Result<u16> {
+    let v =
+        query_value(query, "Input").ok_or_else(|| anyhow::anyhow!("Input parameter missing"))?;
+    if let Ok(n) = v.parse() {
+        return Ok(n);
+    }
+    state
+        .inputs
+        .iter()
+        .find(|(_, p)| {
+            p.long_name.eq_ignore_ascii_case(&v) || p.short_name.eq_ignore_ascii_case(&v)
+        })
+        .map(|(id, _)| *id)
+        .ok_or_else(|| anyhow::anyhow!("Input {} not found", v))
+}
+
+/// Translates a vMix function, Mix=N picks the ME (counting from 0 in vMix as well).
+fn action(state: &AtemState, function: &str, query: &str) -> anyhow::Result<Action> {
+    let me = match query_value(query, "Mix") {
+        Some(m) => m.parse()?,
+        None => 0,
+    };
+    let action = match function.to_ascii_lowercase().as_str() {
+        "cut" => Action::Cut { me },
+        "fade" | "merge" | "wipe" | "transition1" | "transition2" | "transition3"
+        | "transition4" => Action::Auto { me },
+        "previewinput" => Action::Preview {
+            me,
+            input: input(state, query)?,
+        },
+        "activeinput" | "cutdirect" => Action::Program {
+            me,
+            input: input(state, query)?,
+        },
+        "startstreaming" => Action::StartStreaming,
+        "stopstreaming" => Action::StopStreaming,
+        "startrecording" => Action::StartRecording,
+        "stoprecording" => Action::StopRecording,
+        "scriptstart" | "macrostart" => {
+            let index: u8 = query_value(query, "Value")
+                .ok_or_else(|| anyhow::anyhow!("Value parameter missing"))?
+                .parse()?;
+            // vMix numbers from 1, ATEM macros from 0
+            Action::RunMacro(index.saturating_sub(1))
+        }
+        _ => anyhow::bail!("Function {} not supported", function),
+    };
+    Ok(action)
+}
+
+/// Answers one line of the vMix TCP protocol, `None` closes the connection.
+fn respond(atem: &SharedAtem, line: &str, subscribed: &mut bool) -> Option<String> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let reply = match command.to_ascii_uppercase().as_str() {
+        "FUNCTION" => {
+            let (function, query) = rest.split_once(' ').unwrap_or((rest, ""));
+            let mut atem = atem.lock().unwrap();
+            match action(atem.state(), function, query).and_then(|a| a.apply(&mut atem)) {
+                Ok(()) => "FUNCTION OK Completed\r\n".to_string(),
+                Err(e) => format!("FUNCTION ER {}\r\n", e),
+            }
+        }
+        "TALLY" => format!(
+            "TALLY OK {}\r\n",
+            tally_string(atem.lock().unwrap().state())
+        ),
+        "XML" => {
+            // the length counts the line break after the document
+            let xml = state_xml(atem.lock().unwrap().state()) + "\r\n";
+            format!("XML {}\r\n{}", xml.len(), xml)
+        }
+        "VERSION" => format!("VERSION OK {}\r\n", VMIX_VERSION),
+        "SUBSCRIBE" | "UNSUBSCRIBE" if rest.trim().eq_ignore_ascii_case("TALLY") => {
+            *subscribed = command.eq_ignore_ascii_case("SUBSCRIBE");
+            format!("{} OK TALLY\r\n", command.to_ascii_uppercase())
+        }
+        "SUBSCRIBE" | "UNSUBSCRIBE" => {
+            format!(
+                "{} ER {} not supported\r\n",
+                command.to_ascii_uppercase(),
+                rest.trim()
+            )
+        }
+        "QUIT" => return None,
+        o => format!("{} ER Unknown command\r\n", o),
+    };
+    Some(reply)
+}
+
+async fn serve(atem: SharedAtem, events: EventHub, stream: TcpStream) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut rx = events.subscribe();
+    let mut subscribed = false;
+    let mut last_tally = String::new();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else { return Ok(()) };
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let was_subscribed = subscribed;
+                match respond(&atem, line, &mut subscribed) {
+                    Some(reply) => writer.write_all(reply.as_bytes()).await?,
+                    None => return Ok(()),
+                }
+                // vMix follows SUBSCRIBE OK with the current tally
+                if subscribed && !was_subscribed {
+                    last_tally = tally_string(atem.lock().unwrap().state());
+                    writer.write_all(format!("TALLY OK {}\r\n", last_tally).as_bytes()).await?;
+                }
+            }
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    if !subscribed || event["event"] != "tally" {
+                        continue;
+                    }
+                    // a cut changes several sources, only send the result once
+                    let tally = tally_string(atem.lock().unwrap().state());
+                    if tally != last_tally {
+                        writer.write_all(format!("TALLY OK {}\r\n", tally).as_bytes()).await?;
+                        last_tally = tally;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Emulates the vMix TCP API (normally port 8099) so tools written for vMix drive the switcher.
+pub async fn run(atem: SharedAtem, events: EventHub, addr: String) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&addr).await?;
+    println!("vMix TCP API on {}", addr);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let atem = atem.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(atem, events, stream).await {
+                println!("vMix client {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use bmda_bridge::{AtemMini, InputProperties, InternalPortType, TallyState};
+
+    fn state() -> AtemState {
+        let mut state = AtemState::default();
+        for (id, name, short) in [(1, "Camera 1", "CAM1"), (2, "Slides & <Notes>", "PC")] {
+            state.inputs.insert(
+                id,
+                InputProperties {
+                    long_name: name.to_string(),
+                    short_name: short.to_string(),
+                    are_names_default: false,
+                    external_port_type: 2,
+                    internal_port_type: InternalPortType::External,
+                    source_availability: 0x1f,
+                    me_availability: 0x01,
+                },
+            );
+        }
+        state.tally.by_index = vec![
+            TallyState {
+                program: true,
+                preview: false,
+            },
+            TallyState {
+                program: false,
+                preview: true,
+            },
+            TallyState::default(),
+        ];
+        state
+    }
+
+    #[test]
+    fn tally_and_xml() {
+        let state = state();
+        assert_eq!(tally_string(&state), "120");
+        let xml = state_xml(&state);
+        assert!(xml.contains("title=\"Slides &amp; &lt;Notes&gt;\" shortTitle=\"PC\""));
+        assert!(xml.ends_with(
+            "<active>0</active><preview>0</preview><streaming>False</streaming><recording>False</recording></vmix>"
+        ));
+    }
+
+    #[test]
+    fn queries() {
+        assert_eq!(url_decode("Camera+1%2F2%zz%4"), "Camera 1/2%zz%4");
+        assert_eq!(
+            query_value("Input=Camera%201&mix=1", "MIX").as_deref(),
+            Some("1")
+        );
+        assert_eq!(query_value("Input", "Input"), None);
+
+        let state = state();
+        assert_eq!(input(&state, "Input=3").unwrap(), 3);
+        assert_eq!(input(&state, "Input=cam1").unwrap(), 1);
+        assert_eq!(input(&state, "Input=Camera+1").unwrap(), 1);
+        assert!(input(&state, "Input=Nope").is_err());
+        assert!(input(&state, "").is_err());
+    }
+
+    #[test]
+    fn functions() {
+        let state = state();
+        assert_eq!(
+            action(&state, "PreviewInput", "Input=PC&Mix=1").unwrap(),
+            Action::Preview { me: 1, input: 2 }
+        );
+        assert_eq!(action(&state, "Wipe", "").unwrap(), Action::Auto { me: 0 });
+        assert_eq!(
+            action(&state, "ScriptStart", "Value=1").unwrap(),
+            Action::RunMacro(0)
+        );
+        assert!(action(&state, "Cut", "Mix=x").is_err());
+        assert!(action(&state, "Overlay1In", "").is_err());
+    }
+
+    #[test]
+    fn lines() {
+        let atem: SharedAtem = Arc::new(Mutex::new(AtemMini::new()));
+        let mut subscribed = false;
+        let mut respond = |line: &str| respond(&atem, line, &mut subscribed);
+
+        assert_eq!(
+            respond("VERSION").as_deref(),
+            Some("VERSION OK 27.0.0.49\r\n")
+        );
+        assert_eq!(
+            respond("subscribe tally").as_deref(),
+            Some("SUBSCRIBE OK TALLY\r\n")
+        );
+        assert_eq!(
+            respond("SUBSCRIBE ACTS").as_deref(),
+            Some("SUBSCRIBE ER ACTS not supported\r\n")
+        );
+        assert_eq!(respond("TALLY").as_deref(), Some("TALLY OK \r\n"));
+        let xml = respond("XML").unwrap();
+        let (head, body) = xml.split_once("\r\n").unwrap();
+        assert_eq!(head, format!("XML {}", body.len()));
+        assert!(respond("FUNCTION Cut").unwrap().starts_with("FUNCTION ER "));
+        assert_eq!(
+            respond("NOPE").as_deref(),
+            Some("NOPE ER Unknown command\r\n")
+        );
+        assert_eq!(respond("QUIT"), None);
+        assert!(subscribed);
+    }
+}
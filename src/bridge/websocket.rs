@@ -0,0 +1,105 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{Message, Role};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::bridge::actions::Action;
+use crate::bridge::events::EventHub;
+use crate::bridge::json;
+use crate::bridge::SharedAtem;
+
+/// Runs a command message like {"action": "program", "me": 0, "input": 3}.
+fn handle_message(atem: &SharedAtem, text: &str) -> Value {
+    let result = serde_json::from_str::<Value>(text)
+        .map_err(anyhow::Error::from)
+        .and_then(|msg| {
+            let name = msg
+                .get("action")
+                .and_then(|a| a.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing \"action\""))?;
+            Action::from_json(name, &msg)
+        })
+        .and_then(|action| action.apply(&mut atem.lock().unwrap()));
+
+    match result {
+        Ok(()) => json!({ "event": "result", "ok": true }),
+        Err(e) => json!({ "event": "result", "ok": false, "error": e.to_string() }),
+    }
+}
+
+/// Finishes the upgrade of an HTTP request to /events and serves the event feed on it.
+///
+/// Clients get the full state first, then an event for every change.
+pub async fn serve(
+    atem: SharedAtem,
+    events: EventHub,
+    mut stream: TcpStream,
+    key: &str,
+) -> anyhow::Result<()> {
+    let head = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    );
+    stream.write_all(head.as_bytes()).await?;
+
+    let mut ws = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+    let mut rx = events.subscribe();
+
+    let state = json::state_json(atem.lock().unwrap().state());
+    ws.send(Message::Text(
+        json!({ "event": "state", "state": state }).to_string(),
+    ))
+    .await?;
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) => ws.send(Message::Text(event.to_string())).await?,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    println!("WebSocket client missed {} events", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            msg = ws.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = handle_message(&atem, &text);
+                    ws.send(Message::Text(reply.to_string())).await?;
+                }
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use bmda_bridge::AtemMini;
+
+    #[test]
+    fn messages() {
+        let atem: SharedAtem = Arc::new(Mutex::new(AtemMini::new()));
+        let error = |text: &str| {
+            let reply = handle_message(&atem, text);
+            assert_eq!(reply["event"], "result");
+            assert_eq!(reply["ok"], false, "{}", text);
+            reply["error"].as_str().unwrap_or_default().to_string()
+        };
+        assert!(error("not json").contains("expected"));
+        assert_eq!(error(r#"{"input": 2}"#), "Missing \"action\"");
+        assert_eq!(error(r#"{"action": "fly"}"#), "Unknown action fly");
+        assert!(error(r#"{"action": "program", "input": 70000}"#).contains("out of range"));
+        // a valid action the switcher hasn't told us enough for yet
+        assert!(error(r#"{"action": "program", "input": 2}"#).contains("topology"));
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{byte_at, fixed_string_at, word_at, AtemCommand};
+
+// Parameters follow the Blackmagic SDI camera control protocol, see the
+// "Blackmagic Camera Control" developer manual for the full list.
+const CATEGORY_LENS: u8 = 0;
+const CATEGORY_VIDEO: u8 = 1;
+const CATEGORY_COLOR_CORRECTION: u8 = 8;
+
+const LENS_FOCUS: u8 = 0;
+const LENS_AUTO_FOCUS: u8 = 1;
+const LENS_IRIS: u8 = 3;
+const LENS_AUTO_IRIS: u8 = 5;
+const LENS_ZOOM: u8 = 8;
+const LENS_ZOOM_SPEED: u8 = 9;
+
+const VIDEO_WHITE_BALANCE: u8 = 2;
+const VIDEO_AUTO_WHITE_BALANCE: u8 = 3;
+const VIDEO_EXPOSURE: u8 = 5;
+const VIDEO_SHUTTER_SPEED: u8 = 12;
+const VIDEO_GAIN: u8 = 13;
+const VIDEO_ISO: u8 = 14;
+
+const COLOR_LIFT: u8 = 0;
+const COLOR_GAMMA: u8 = 1;
+const COLOR_GAIN: u8 = 2;
+const COLOR_OFFSET: u8 = 3;
+const COLOR_CONTRAST: u8 = 4;
+const COLOR_LUMA_MIX: u8 = 5;
+const COLOR_HUE_SATURATION: u8 = 6;
+
+const TYPE_BOOL: u8 = 0;
+const TYPE_INT8: u8 = 1;
+const TYPE_INT16: u8 = 2;
+const TYPE_INT32: u8 = 3;
+const TYPE_INT64: u8 = 4;
+const TYPE_STRING: u8 = 5;
+const TYPE_FIXED16: u8 = 128;
+
+// Values start after the fixed header, in CCdP as well as CCmd.
+const VALUES_OFFSET: usize = 16;
+
+/// Fixed point values are signed 5.11.
+fn fixed16_to_f32(v: i16) -> f32 {
+    (v as f32) / 2048.0
+}
+
+fn f32_to_fixed16(v: f32) -> i16 {
+    (v * 2048.0).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// The typed values of one camera control parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraControlData {
+    Void,
+    Bool(Vec<bool>),
+    Int8(Vec<i8>),
+    Int16(Vec<i16>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    String(String),
+    Fixed16(Vec<f32>),
+}
+
+impl CameraControlData {
+    fn type_id(&self) -> u8 {
+        match self {
+            CameraControlData::Void | CameraControlData::Bool(_) => TYPE_BOOL,
+            CameraControlData::Int8(_) => TYPE_INT8,
+            CameraControlData::Int16(_) => TYPE_INT16,
+            CameraControlData::Int32(_) => TYPE_INT32,
+            CameraControlData::Int64(_) => TYPE_INT64,
+            CameraControlData::String(_) => TYPE_STRING,
+            CameraControlData::Fixed16(_) => TYPE_FIXED16,
+        }
+    }
+
+    /// Index of the element count for this type.
+    ///
+    /// The counts sit at the same place in CCdP and CCmd, only the type byte moves.
+    fn count_index(type_id: u8) -> usize {
+        match type_id {
+            TYPE_BOOL | TYPE_INT8 => 6,
+            TYPE_INT16 | TYPE_FIXED16 => 8,
+            TYPE_INT32 => 10,
+            TYPE_INT64 => 12,
+            _ => 14,
+        }
+    }
+
+    /// `t` is the index of the type byte in `data`.
+    pub(crate) fn from_data(data: &[u8], t: usize) -> Self {
+        let type_id = byte_at(data, t);
+        let count = word_at(data, Self::count_index(type_id)) as usize;
+        let values = data.get(VALUES_OFFSET..).unwrap_or(&[]);
+
+        let ints = |size: usize| -> Vec<i64> {
+            values
+                .chunks_exact(size)
+                .take(count)
+                .map(|c| {
+                    let mut b = [0; 8];
+                    b[8 - size..].copy_from_slice(c);
+                    // sign extend
+                    (i64::from_be_bytes(b) << (64 - size * 8)) >> (64 - size * 8)
+                })
+                .collect()
+        };
+
+        match type_id {
+            TYPE_BOOL if count == 0 => CameraControlData::Void,
+            TYPE_BOOL => CameraControlData::Bool(ints(1).iter().map(|v| *v != 0).collect()),
+            TYPE_INT8 => CameraControlData::Int8(ints(1).iter().map(|v| *v as i8).collect()),
+            TYPE_INT16 => CameraControlData::Int16(ints(2).iter().map(|v| *v as i16).collect()),
+            TYPE_INT32 => CameraControlData::Int32(ints(4).iter().map(|v| *v as i32).collect()),
+            TYPE_INT64 => CameraControlData::Int64(ints(8)),
+            TYPE_STRING => {
+                CameraControlData::String(fixed_string_at(values, 0, count.min(values.len())))
+            }
+            TYPE_FIXED16 => CameraControlData::Fixed16(
+                ints(2).iter().map(|v| fixed16_to_f32(*v as i16)).collect(),
+            ),
+            _ => CameraControlData::Void,
+        }
+    }
+
+    fn write(&self, c: &mut AtemCommand, t: usize) {
+        let type_id = self.type_id();
+        let (count, bytes): (usize, Vec<u8>) = match self {
+            CameraControlData::Void => (0, Vec::new()),
+            CameraControlData::Bool(v) => (v.len(), v.iter().map(|b| *b as u8).collect()),
+            CameraControlData::Int8(v) => (v.len(), v.iter().map(|b| *b as u8).collect()),
+            CameraControlData::Int16(v) => {
+                (v.len(), v.iter().flat_map(|v| v.to_be_bytes()).collect())
+            }
+            CameraControlData::Int32(v) => {
+                (v.len(), v.iter().flat_map(|v| v.to_be_bytes()).collect())
+            }
+            CameraControlData::Int64(v) => {
+                (v.len(), v.iter().flat_map(|v| v.to_be_bytes()).collect())
+            }
+            CameraControlData::String(s) => (s.len(), s.as_bytes().to_vec()),
+            CameraControlData::Fixed16(v) => (
+                v.len(),
+                v.iter()
+                    .flat_map(|v| f32_to_fixed16(*v).to_be_bytes())
+                    .collect(),
+            ),
+        };
+        let p = c.payload();
+        p.set(t, type_id);
+        p.set_word(Self::count_index(type_id), count as u16);
+        p.set_bytes(VALUES_OFFSET, &bytes);
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            CameraControlData::Void => 0,
+            CameraControlData::Bool(v) => v.len(),
+            CameraControlData::Int8(v) => v.len(),
+            CameraControlData::Int16(v) => v.len() * 2,
+            CameraControlData::Int32(v) => v.len() * 4,
+            CameraControlData::Int64(v) => v.len() * 8,
+            CameraControlData::String(s) => s.len(),
+            CameraControlData::Fixed16(v) => v.len() * 2,
+        }
+    }
+
+    fn fixed16(&self) -> Option<&[f32]> {
+        match self {
+            CameraControlData::Fixed16(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn first_fixed16(&self) -> Option<f32> {
+        self.fixed16().and_then(|v| v.first().copied())
+    }
+
+    fn rgby(&self) -> Option<[f32; 4]> {
+        match self.fixed16() {
+            Some(&[r, g, b, y]) => Some([r, g, b, y]),
+            _ => None,
+        }
+    }
+}
+
+/// Lift/gamma/gain/offset of the colour corrector, red, green, blue and luma.
+pub type Rgby = [f32; 4];
+
+/// What we know about a Blackmagic camera connected to an input.
+///
+/// Normalised values (focus, iris, zoom) are 0.0 to 1.0.
+/// Fields stay `None` until the camera (or another client) has set them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CameraState {
+    pub focus: Option<f32>,
+    pub iris: Option<f32>,
+    pub zoom: Option<f32>,
+    pub zoom_speed: Option<f32>,
+    /// Kelvin and tint
+    pub white_balance: Option<(i16, i16)>,
+    /// dB
+    pub gain: Option<i8>,
+    pub iso: Option<i32>,
+    /// exposure in microseconds
+    pub exposure: Option<i32>,
+    /// 1/x seconds
+    pub shutter_speed: Option<i32>,
+    pub lift: Option<Rgby>,
+    pub gamma: Option<Rgby>,
+    pub color_gain: Option<Rgby>,
+    pub offset: Option<Rgby>,
+    /// pivot and adjustment
+    pub contrast: Option<(f32, f32)>,
+    pub luma_mix: Option<f32>,
+    /// hue (-1.0 to 1.0) and saturation (0.0 to 2.0)
+    pub hue_saturation: Option<(f32, f32)>,
+    /// Everything else we received, keyed by category and parameter.
+    pub other: BTreeMap<(u8, u8), CameraControlData>,
+}
+
+impl CameraState {
+    pub(crate) fn apply(&mut self, category: u8, parameter: u8, data: &CameraControlData) {
+        match (category, parameter, data) {
+            (CATEGORY_LENS, LENS_FOCUS, d) => self.focus = d.first_fixed16(),
+            (CATEGORY_LENS, LENS_IRIS, d) => self.iris = d.first_fixed16(),
+            (CATEGORY_LENS, LENS_ZOOM, d) => self.zoom = d.first_fixed16(),
+            (CATEGORY_LENS, LENS_ZOOM_SPEED, d) => self.zoom_speed = d.first_fixed16(),
+            (CATEGORY_VIDEO, VIDEO_WHITE_BALANCE, CameraControlData::Int16(v)) if v.len() >= 2 => {
+                self.white_balance = Some((v[0], v[1]))
+            }
+            (CATEGORY_VIDEO, VIDEO_GAIN, CameraControlData::Int8(v)) => {
+                self.gain = v.first().copied()
+            }
+            (CATEGORY_VIDEO, VIDEO_ISO, CameraControlData::Int32(v)) => {
+                self.iso = v.first().copied()
+            }
+            (CATEGORY_VIDEO, VIDEO_EXPOSURE, CameraControlData::Int32(v)) => {
+                self.exposure = v.first().copied()
+            }
+            (CATEGORY_VIDEO, VIDEO_SHUTTER_SPEED, CameraControlData::Int32(v)) => {
+                self.shutter_speed = v.first().copied()
+            }
+            (CATEGORY_COLOR_CORRECTION, COLOR_LIFT, d) => self.lift = d.rgby(),
+            (CATEGORY_COLOR_CORRECTION, COLOR_GAMMA, d) => self.gamma = d.rgby(),
+            (CATEGORY_COLOR_CORRECTION, COLOR_GAIN, d) => self.color_gain = d.rgby(),
+            (CATEGORY_COLOR_CORRECTION, COLOR_OFFSET, d) => self.offset = d.rgby(),
+            (CATEGORY_COLOR_CORRECTION, COLOR_CONTRAST, CameraControlData::Fixed16(v))
+                if v.len() >= 2 =>
+            {
+                self.contrast = Some((v[0], v[1]))
+            }
+            (CATEGORY_COLOR_CORRECTION, COLOR_LUMA_MIX, d) => self.luma_mix = d.first_fixed16(),
+            (CATEGORY_COLOR_CORRECTION, COLOR_HUE_SATURATION, CameraControlData::Fixed16(v))
+                if v.len() >= 2 =>
+            {
+                self.hue_saturation = Some((v[0], v[1]))
+            }
+            (c, p, d) => {
+                self.other.insert((c, p), d.clone());
+            }
+        }
+    }
+}
+
+/// Builds a CCmd for camera `input`, `relative` adds to the current value instead of setting it.
+pub(crate) fn create_camera_command(
+    input: u8,
+    category: u8,
+    parameter: u8,
+    relative: bool,
+    data: &CameraControlData,
+) -> AtemCommand {
+    let len = (VALUES_OFFSET + data.size()).div_ceil(4) * 4;
+    let mut c = AtemCommand::create_command(0, 0, b"CCmd", len.max(24) as u16);
+    let p = c.payload();
+    p.set(0, input);
+    p.set(1, category);
+    p.set(2, parameter);
+    p.set(3, relative as u8);
+    data.write(&mut c, 4);
+    c
+}
+
+pub(crate) fn create_set_focus(input: u8, focus: f32) -> AtemCommand {
+    let data = CameraControlData::Fixed16(vec![focus]);
+    create_camera_command(input, CATEGORY_LENS, LENS_FOCUS, false, &data)
+}
+
+pub(crate) fn create_auto_focus(input: u8) -> AtemCommand {
+    let data = CameraControlData::Void;
+    create_camera_command(input, CATEGORY_LENS, LENS_AUTO_FOCUS, false, &data)
+}
+
+pub(crate) fn create_set_iris(input: u8, iris: f32) -> AtemCommand {
+    let data = CameraControlData::Fixed16(vec![iris]);
+    create_camera_command(input, CATEGORY_LENS, LENS_IRIS, false, &data)
+}
+
+pub(crate) fn create_auto_iris(input: u8) -> AtemCommand {
+    let data = CameraControlData::Void;
+    create_camera_command(input, CATEGORY_LENS, LENS_AUTO_IRIS, false, &data)
+}
+
+pub(crate) fn create_set_zoom(input: u8, zoom: f32) -> AtemCommand {
+    let data = CameraControlData::Fixed16(vec![zoom]);
+    create_camera_command(input, CATEGORY_LENS, LENS_ZOOM, false, &data)
+}
+
+pub(crate) fn create_set_zoom_speed(input: u8, speed: f32) -> AtemCommand {
+    let data = CameraControlData::Fixed16(vec![speed]);
+    create_camera_command(input, CATEGORY_LENS, LENS_ZOOM_SPEED, false, &data)
+}
+
+pub(crate) fn create_set_white_balance(input: u8, kelvin: i16, tint: i16) -> AtemCommand {
+    let data = CameraControlData::Int16(vec![kelvin, tint]);
+    create_camera_command(input, CATEGORY_VIDEO, VIDEO_WHITE_BALANCE, false, &data)
+}
+
+pub(crate) fn create_auto_white_balance(input: u8) -> AtemCommand {
+    let data = CameraControlData::Void;
+    create_camera_command(
+        input,
+        CATEGORY_VIDEO,
+        VIDEO_AUTO_WHITE_BALANCE,
+        false,
+        &data,
+    )
+}
+
+pub(crate) fn create_set_gain(input: u8, gain: i8) -> AtemCommand {
+    let data = CameraControlData::Int8(vec![gain]);
+    create_camera_command(input, CATEGORY_VIDEO, VIDEO_GAIN, false, &data)
+}
+
+pub(crate) fn create_set_iso(input: u8, iso: i32) -> AtemCommand {
+    let data = CameraControlData::Int32(vec![iso]);
+    create_camera_command(input, CATEGORY_VIDEO, VIDEO_ISO, false, &data)
+}
+
+pub(crate) fn create_set_shutter_speed(input: u8, speed: i32) -> AtemCommand {
+    let data = CameraControlData::Int32(vec![speed]);
+    create_camera_command(input, CATEGORY_VIDEO, VIDEO_SHUTTER_SPEED, false, &data)
+}
+
+pub(crate) fn create_set_color(input: u8, parameter: u8, rgby: Rgby) -> AtemCommand {
+    let data = CameraControlData::Fixed16(rgby.to_vec());
+    create_camera_command(input, CATEGORY_COLOR_CORRECTION, parameter, false, &data)
+}
+
+pub(crate) fn create_set_lift(input: u8, lift: Rgby) -> AtemCommand {
+    create_set_color(input, COLOR_LIFT, lift)
+}
+
+pub(crate) fn create_set_gamma(input: u8, gamma: Rgby) -> AtemCommand {
+    create_set_color(input, COLOR_GAMMA, gamma)
+}
+
+pub(crate) fn create_set_color_gain(input: u8, gain: Rgby) -> AtemCommand {
+    create_set_color(input, COLOR_GAIN, gain)
+}
+
+pub(crate) fn create_set_offset(input: u8, offset: Rgby) -> AtemCommand {
+    create_set_color(input, COLOR_OFFSET, offset)
+}
+
+pub(crate) fn create_set_contrast(input: u8, pivot: f32, adjust: f32) -> AtemCommand {
+    let data = CameraControlData::Fixed16(vec![pivot, adjust]);
+    create_camera_command(
+        input,
+        CATEGORY_COLOR_CORRECTION,
+        COLOR_CONTRAST,
+        false,
+        &data,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::{command_data, decode};
+    use crate::payload::Payload;
+
+    fn round_trip(data: CameraControlData) {
+        let c = create_camera_command(2, CATEGORY_VIDEO, 99, false, &data);
+        let bytes = command_data(c);
+        assert_eq!(&bytes[..5], &[2, CATEGORY_VIDEO, 99, 0, data.type_id()]);
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(CameraControlData::from_data(&bytes, 4), data);
+    }
+
+    #[test]
+    fn every_type_round_trips() {
+        round_trip(CameraControlData::Void);
+        round_trip(CameraControlData::Bool(vec![true, false]));
+        round_trip(CameraControlData::Int8(vec![-3, 12]));
+        round_trip(CameraControlData::Int16(vec![5600, -10]));
+        round_trip(CameraControlData::Int32(vec![-1, 20000]));
+        round_trip(CameraControlData::Int64(vec![i64::MIN, 42]));
+        round_trip(CameraControlData::String("Camera 1".to_string()));
+        round_trip(CameraControlData::Fixed16(vec![0.5, -1.0, 0.25, 15.0]));
+    }
+
+    #[test]
+    fn string_count_stays_clear_of_the_value() {
+        let data = CameraControlData::String("ABC".to_string());
+        let bytes = command_data(create_camera_command(1, 0, 0, false, &data));
+        assert_eq!(word_at(&bytes, 14), 3);
+        assert_eq!(&bytes[16..19], b"ABC");
+    }
+
+    #[test]
+    fn update_from_data() {
+        let mut data = vec![0; 24];
+        data[..4].copy_from_slice(&[3, CATEGORY_COLOR_CORRECTION, COLOR_LIFT, TYPE_FIXED16]);
+        data[8..10].copy_from_slice(&4u16.to_be_bytes());
+        for (i, v) in [0.5f32, 0.25, -0.5, 1.0].iter().enumerate() {
+            data[16 + i * 2..18 + i * 2].copy_from_slice(&f32_to_fixed16(*v).to_be_bytes());
+        }
+
+        let payloads = decode(b"CCdP", &data);
+        let Some(Payload::CameraControl {
+            input,
+            category,
+            parameter,
+            data,
+        }) = payloads.first()
+        else {
+            panic!("no CCdP payload: {:?}", payloads);
+        };
+        assert_eq!(*input, 3);
+        let mut camera = CameraState::default();
+        camera.apply(*category, *parameter, data);
+        assert_eq!(camera.lift, Some([0.5, 0.25, -0.5, 1.0]));
+    }
+
+    #[test]
+    fn setters() {
+        let bytes = command_data(create_set_white_balance(1, 5600, 10));
+        assert_eq!(
+            CameraControlData::from_data(&bytes, 4),
+            CameraControlData::Int16(vec![5600, 10])
+        );
+        let bytes = command_data(create_auto_focus(1));
+        assert_eq!(
+            &bytes[..5],
+            &[1, CATEGORY_LENS, LENS_AUTO_FOCUS, 0, TYPE_BOOL]
+        );
+        assert_eq!(word_at(&bytes, 6), 0);
+    }
+}
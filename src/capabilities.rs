@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::byte_at;
+use crate::protocol_version::ProtocolVersion;
+
+/// Returned (inside `anyhow::Error`) when a control method asks for something this switcher doesn't have.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsupported(pub String);
+
+impl std::fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Unsupported: {}", self.0)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+pub(crate) fn unsupported(message: String) -> anyhow::Error {
+    Unsupported(message).into()
+}
+
+fn plural(what: &str, count: usize) -> String {
+    if count == 1 {
+        what.to_string()
+    } else if what.ends_with('x') || what.ends_with('s') {
+        format!("{}es", what)
+    } else {
+        format!("{}s", what)
+    }
+}
+
+/// Fails with `Unsupported` unless `index` (counting from 0) is below `count`.
+///
+/// `what` is singular, e.g. "ME" gives "this switcher has 1 ME, you asked for ME 2".
+pub(crate) fn check_index(what: &str, count: usize, index: usize) -> anyhow::Result<()> {
+    if index < count {
+        Ok(())
+    } else if count == 0 {
+        Err(unsupported(format!(
+            "this switcher has no {}",
+            plural(what, 0)
+        )))
+    } else {
+        Err(unsupported(format!(
+            "this switcher has {} {}, you asked for {} {}",
+            count,
+            plural(what, count),
+            what,
+            index + 1
+        )))
+    }
+}
+
+/// What the switcher has, as reported in _top.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Topology {
+    pub me_count: u8,
+    pub source_count: u8,
+    pub color_generator_count: u8,
+    pub aux_count: u8,
+    pub dsk_count: u8,
+    pub media_player_count: u8,
+    /// upstream keyers per ME, only the layouts before V8_0 carry it, see `Capabilities::keyer_count()`
+    pub usk_count: u8,
+    pub stinger_count: u8,
+    pub dve_count: u8,
+    pub supersource_count: u8,
+    pub has_sd_output: bool,
+}
+
+impl Topology {
+    /// Firmware after 8.0 inserted the multiview count at 6, moving everything behind it by one.
+    fn multiview_shift(version: Option<ProtocolVersion>) -> usize {
+        version.is_some_and(|v| v > ProtocolVersion::V8_0) as usize
+    }
+
+    /// Fields the layout of `version` doesn't carry stay 0.
+    pub(crate) fn from_data(data: &[u8], version: Option<ProtocolVersion>) -> Self {
+        if ProtocolVersion::has_v8_layouts(version) {
+            let shift = Self::multiview_shift(version);
+            Self {
+                me_count: byte_at(data, 0),
+                source_count: byte_at(data, 1),
+                dsk_count: byte_at(data, 2),
+                aux_count: byte_at(data, 3),
+                media_player_count: byte_at(data, 5),
+                dve_count: byte_at(data, 8 + shift),
+                stinger_count: byte_at(data, 9 + shift),
+                supersource_count: byte_at(data, 10 + shift),
+                ..Default::default()
+            }
+        } else {
+            Self {
+                me_count: byte_at(data, 0),
+                source_count: byte_at(data, 1),
+                color_generator_count: byte_at(data, 2),
+                aux_count: byte_at(data, 3),
+                dsk_count: byte_at(data, 5),
+                usk_count: byte_at(data, 7),
+                stinger_count: byte_at(data, 8),
+                dve_count: byte_at(data, 9),
+                supersource_count: byte_at(data, 10),
+                has_sd_output: byte_at(data, 11) > 0,
+                ..Default::default()
+            }
+        }
+    }
+
+    /// The _top data as a switcher speaking `version` sends it.
+    pub(crate) fn to_data(&self, version: Option<ProtocolVersion>) -> Vec<u8> {
+        let mut data = vec![0; 24];
+        data[0] = self.me_count;
+        data[1] = self.source_count;
+        if ProtocolVersion::has_v8_layouts(version) {
+            let shift = Self::multiview_shift(version);
+            data[2] = self.dsk_count;
+            data[3] = self.aux_count;
+            data[5] = self.media_player_count;
+            data[8 + shift] = self.dve_count;
+            data[9 + shift] = self.stinger_count;
+            data[10 + shift] = self.supersource_count;
+        } else {
+            data[2] = self.color_generator_count;
+            data[3] = self.aux_count;
+            data[5] = self.dsk_count;
+            data[7] = self.usk_count;
+            data[8] = self.stinger_count;
+            data[9] = self.dve_count;
+            data[10] = self.supersource_count;
+            data[11] = self.has_sd_output as u8;
+        }
+        data
+    }
+}
+
+/// Filled from the initial state dump, `None` until the switcher sent the chunk.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    pub protocol_version: Option<ProtocolVersion>,
+    pub topology: Option<Topology>,
+    pub macro_count: Option<u8>,
+    pub tally_channel_count: Option<u16>,
+    /// upstream keyers of each ME, from _MeC
+    pub keyer_counts: BTreeMap<u8, u8>,
+}
+
+impl Capabilities {
+    pub fn topology(&self) -> anyhow::Result<&Topology> {
+        self.topology
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Switcher topology not received yet"))
+    }
+
+    pub fn check_me(&self, me: u8) -> anyhow::Result<()> {
+        check_index("ME", self.topology()?.me_count as usize, me as usize)
+    }
+
+    /// Upstream keyers of `me`, _MeC wins over the count in older _top layouts.
+    pub fn keyer_count(&self, me: u8) -> anyhow::Result<u8> {
+        match self.keyer_counts.get(&me) {
+            Some(count) => Ok(*count),
+            None => Ok(self.topology()?.usk_count),
+        }
+    }
+
+    pub fn check_keyer(&self, me: u8, keyer: u8) -> anyhow::Result<()> {
+        self.check_me(me)?;
+        check_index(
+            "upstream keyer",
+            self.keyer_count(me)? as usize,
+            keyer as usize,
+        )
+    }
+
+    pub fn check_dsk(&self, index: u8) -> anyhow::Result<()> {
+        check_index(
+            "downstream keyer",
+            self.topology()?.dsk_count as usize,
+            index as usize,
+        )
+    }
+
+    /// Inputs count from 1, 0 is black.
+    pub fn check_input(&self, input: u16) -> anyhow::Result<()> {
+        let count = self.topology()?.source_count as u16;
+        if input == 0 || input > count {
+            Err(unsupported(format!(
+                "this switcher has {} inputs, you asked for input {}",
+                count, input
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn check_supersource(&self, supersource: u8) -> anyhow::Result<()> {
+        check_index(
+            "SuperSource",
+            self.topology()?.supersource_count as usize,
+            supersource as usize,
+        )
+    }
+
+    pub fn check_macro(&self, index: u8) -> anyhow::Result<()> {
+        let count = self
+            .macro_count
+            .ok_or_else(|| anyhow::anyhow!("Macro count not received yet"))?;
+        check_index("macro", count as usize, index as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::decode;
+    use crate::payload::Payload;
+
+    fn capabilities(me_count: u8, usk_count: u8) -> Capabilities {
+        Capabilities {
+            topology: Some(Topology {
+                me_count,
+                usk_count,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn check_index_messages() {
+        assert!(check_index("ME", 1, 0).is_ok());
+        let e = check_index("ME", 1, 1).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<Unsupported>().unwrap().0,
+            "this switcher has 1 ME, you asked for ME 2"
+        );
+        let e = check_index("SuperSource box", 0, 0).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Unsupported: this switcher has no SuperSource boxes"
+        );
+    }
+
+    #[test]
+    fn keyer_count_comes_from_mec() {
+        assert!(matches!(
+            decode(b"_MeC", &[0, 4, 0, 0]).first(),
+            Some(Payload::MixEffectConfig {
+                me: 0,
+                keyer_count: 4
+            })
+        ));
+
+        // v8 _top layouts have no keyer count
+        let mut c = capabilities(2, 0);
+        assert!(c.check_keyer(0, 0).is_err());
+        c.keyer_counts.insert(0, 4);
+        c.keyer_counts.insert(1, 1);
+        assert!(c.check_keyer(0, 3).is_ok());
+        assert!(c.check_keyer(1, 1).is_err());
+        assert!(c.check_keyer(2, 0).is_err());
+    }
+
+    #[test]
+    fn keyer_count_falls_back_to_topology() {
+        let c = capabilities(1, 1);
+        assert_eq!(c.keyer_count(0).unwrap(), 1);
+        assert!(c.check_keyer(0, 0).is_ok());
+        assert!(c.check_keyer(0, 1).is_err());
+        assert!(Capabilities::default().check_keyer(0, 0).is_err());
+    }
+
+    fn topology() -> Topology {
+        Topology {
+            me_count: 1,
+            source_count: 8,
+            dsk_count: 1,
+            aux_count: 1,
+            media_player_count: 2,
+            dve_count: 1,
+            stinger_count: 1,
+            supersource_count: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn topology_offsets() {
+        let data = topology().to_data(Some(ProtocolVersion::V8_0));
+        assert_eq!(&data[8..12], &[1, 1, 1, 0]);
+        let data = topology().to_data(Some(ProtocolVersion::V8_0_1));
+        assert_eq!(&data[8..12], &[0, 1, 1, 1]);
+
+        // without _ver the pre 8.0 layout applies
+        let mut old = Topology {
+            color_generator_count: 2,
+            usk_count: 1,
+            has_sd_output: true,
+            ..topology()
+        };
+        old.media_player_count = 0;
+        assert_eq!(Topology::from_data(&old.to_data(None), None), old);
+    }
+
+    #[test]
+    fn topology_round_trip() {
+        for minor in ProtocolVersion::V8_0.minor..=ProtocolVersion::NEWEST.minor {
+            let version = Some(ProtocolVersion::new(2, minor));
+            let t = topology();
+            assert_eq!(
+                Topology::from_data(&t.to_data(version), version),
+                t,
+                "{:?}",
+                version
+            );
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::atem_command::AtemCommand;
+use crate::atem_state::AtemState;
+use crate::protocol_version::ProtocolVersion;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From us to the switcher.
+    Sent,
+    /// From the switcher to us.
+    Received,
+}
+
+/// One datagram of a capture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPacket {
+    /// Since the unix epoch.
+    pub timestamp: Duration,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+impl CapturedPacket {
+    pub fn now(direction: Direction, data: &[u8]) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            direction,
+            data: data.to_vec(),
+        }
+    }
+
+    /// One line per packet: microseconds, S or R, the bytes in hex.
+    pub fn to_line(&self) -> String {
+        let direction = match self.direction {
+            Direction::Sent => 'S',
+            Direction::Received => 'R',
+        };
+        let hex: String = self.data.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{} {} {}", self.timestamp.as_micros(), direction, hex)
+    }
+
+    pub fn from_line(line: &str) -> anyhow::Result<Self> {
+        let mut parts = line.split_whitespace();
+        let mut next = |what: &str| {
+            parts
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing {} in capture line", what))
+        };
+        let timestamp = Duration::from_micros(next("timestamp")?.parse()?);
+        let direction = match next("direction")? {
+            "S" => Direction::Sent,
+            "R" => Direction::Received,
+            o => anyhow::bail!("Unknown direction {} in capture line", o),
+        };
+        let hex = next("data")?;
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            anyhow::bail!("Invalid hex digit in capture line");
+        }
+        if hex.len() % 2 != 0 {
+            anyhow::bail!("Odd number of hex digits in capture line");
+        }
+        let data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            timestamp,
+            direction,
+            data,
+        })
+    }
+}
+
+/// Writes packets to a capture file as they come, so a crash keeps what led to it.
+///
+/// The file is written on a thread of its own, the connection only queues the packets.
+pub struct CaptureWriter {
+    tx: mpsc::Sender<CapturedPacket>,
+}
+
+impl CaptureWriter {
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let (tx, rx) = mpsc::channel::<CapturedPacket>();
+        std::thread::spawn(move || {
+            for packet in rx {
+                let result = writeln!(out, "{}", packet.to_line()).and_then(|_| out.flush());
+                if let Err(e) = result {
+                    // dropping rx makes the next write fail
+                    println!("Capture stopped: {}", e);
+                    return;
+                }
+            }
+        });
+        Ok(Self { tx })
+    }
+
+    /// Fails once writing the file failed.
+    pub fn write(&self, packet: CapturedPacket) -> anyhow::Result<()> {
+        self.tx
+            .send(packet)
+            .map_err(|_| anyhow::anyhow!("Capture file is closed"))
+    }
+}
+
+/// Reads a capture written by `CaptureWriter`, empty lines and lines starting with # are skipped.
+pub fn read_capture(path: impl AsRef<Path>) -> anyhow::Result<Vec<CapturedPacket>> {
+    parse_capture(&std::fs::read_to_string(path)?)
+}
+
+/// Like `read_capture`, for a capture already in memory.
+pub fn parse_capture(text: &str) -> anyhow::Result<Vec<CapturedPacket>> {
+    let mut packets = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let packet = CapturedPacket::from_line(line)
+            .map_err(|e| anyhow::anyhow!("Line {}: {}", i + 1, e))?;
+        packets.push(packet);
+    }
+    Ok(packets)
+}
+
+/// Runs captured packets through the same decoding and state as a live connection.
+#[derive(Debug, Default)]
+pub struct Replay {
+    protocol_version: Option<ProtocolVersion>,
+    state: AtemState,
+    packets: usize,
+}
+
+impl Replay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies what the switcher sent, our own packets only count.
+    ///
+    /// Returns false when the packet couldn't be decoded.
+    pub fn feed(&mut self, packet: &CapturedPacket) -> bool {
+        self.packets += 1;
+        if packet.direction == Direction::Sent {
+            return true;
+        }
+        match AtemCommand::from_buffer(&packet.data, &mut self.protocol_version) {
+            Some(c) => {
+                for payload in c.payloads() {
+                    self.state.apply(payload);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn feed_all<'a>(&mut self, packets: impl IntoIterator<Item = &'a CapturedPacket>) {
+        for packet in packets {
+            self.feed(packet);
+        }
+    }
+
+    pub fn state(&self) -> &AtemState {
+        &self.state
+    }
+
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.protocol_version
+    }
+
+    /// Packets fed so far, in both directions.
+    pub fn packets(&self) -> usize {
+        self.packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::packet;
+    use crate::atem_command::SIZE_OF_HEADER;
+    use crate::capabilities::Topology;
+    use crate::server::RawChunk;
+
+    fn packet_at(micros: u64, direction: Direction, data: Vec<u8>) -> CapturedPacket {
+        CapturedPacket {
+            timestamp: Duration::from_micros(micros),
+            direction,
+            data,
+        }
+    }
+
+    #[test]
+    fn line_round_trip() {
+        let p = packet_at(
+            1_700_000_000_123_456,
+            Direction::Received,
+            vec![0x08, 0xff, 0],
+        );
+        assert_eq!(p.to_line(), "1700000000123456 R 08ff00");
+        assert_eq!(CapturedPacket::from_line(&p.to_line()).unwrap(), p);
+        let sent = packet_at(1, Direction::Sent, vec![0xab]);
+        assert_eq!(CapturedPacket::from_line(" 1  S  AB ").unwrap(), sent);
+    }
+
+    #[test]
+    fn bad_lines() {
+        for line in [
+            "", "1 R", "x R 00", "1 X 00", "1 R 0", "1 R aé0", "1 R é0", "1 R +f", "1 R 0g",
+        ] {
+            assert!(CapturedPacket::from_line(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn writer_round_trip() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("bmda-capture-{}.txt", std::process::id()));
+        let packets = vec![
+            packet_at(1, Direction::Sent, vec![1, 2]),
+            packet_at(2, Direction::Received, vec![3]),
+        ];
+        let writer = CaptureWriter::create(&path)?;
+        for p in &packets {
+            writer.write(p.clone())?;
+        }
+        // the thread finishes the file once the writer is gone
+        drop(writer);
+        let mut read = Vec::new();
+        for _ in 0..100 {
+            read = read_capture(&path)?;
+            if read.len() == packets.len() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::fs::remove_file(&path)?;
+        assert_eq!(read, packets);
+        Ok(())
+    }
+
+    #[test]
+    fn replay() {
+        let topology = Topology {
+            me_count: 1,
+            source_count: 4,
+            ..Default::default()
+        };
+        let v = ProtocolVersion::V8_1_1;
+        let mut version = v.major.to_be_bytes().to_vec();
+        version.extend_from_slice(&v.minor.to_be_bytes());
+        let received = packet(&[
+            RawChunk::new(b"_ver", &version),
+            RawChunk::new(b"_top", &topology.to_data(Some(v))),
+        ]);
+
+        let mut replay = Replay::new();
+        // our own packets aren't decoded
+        assert!(replay.feed(&packet_at(1, Direction::Sent, vec![0xff])));
+        assert!(replay.feed(&packet_at(2, Direction::Received, received)));
+        assert!(!replay.feed(&packet_at(3, Direction::Received, vec![0; 4])));
+        assert_eq!(replay.packets(), 3);
+        assert_eq!(replay.protocol_version(), Some(v));
+        assert_eq!(
+            replay.state().capabilities.topology.as_ref(),
+            Some(&topology)
+        );
+
+        // chunks claiming more than the packet holds, or less than their own header
+        let truncated = parse_capture("1 R 0814000000000000000000010050000041755853").unwrap();
+        replay.feed_all(&truncated);
+        let mut short = packet(&[RawChunk::new(b"_top", &[1, 4])]);
+        short[SIZE_OF_HEADER + 1] = 4;
+        assert!(replay.feed(&packet_at(4, Direction::Received, short)));
+        assert_eq!(replay.packets(), 5);
+        assert_eq!(
+            replay.state().capabilities.topology.as_ref(),
+            Some(&topology)
+        );
+    }
+}
@@ -0,0 +1,448 @@
+use std::time::Instant;
+
+use tokio::sync::oneshot;
+
+use crate::atem_command::AtemCommand;
+use crate::payload::Payload;
+use crate::still_image;
+
+/// Media pool stills live in store 0.
+pub(crate) const STILL_STORE_ID: u16 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DataTransferState {
+    WaitingForLock,
+    Transferring,
+    WaitingForComplete,
+    Done,
+    Failed(String),
+}
+
+#[derive(Debug)]
+enum DataTransferKind {
+    Upload {
+        name: String,
+        description: String,
+        data: Vec<u8>,
+        hash: [u8; 16],
+        offset: usize,
+        description_sent: bool,
+    },
+    Download {
+        data: Vec<u8>,
+    },
+}
+
+/// One transfer between us and the switcher, driven by the chunks passed into `handle()`.
+///
+/// Uploads go:
+/// LOCK the store -> LKOB -> FTSD -> (FTCD -> FTDa * chunk count)* -> FTDC -> unlock.
+/// Downloads go:
+/// LOCK the store -> LKOB -> FTSU -> (FTDa -> FTUA)* -> FTDC -> unlock.
+#[derive(Debug)]
+pub(crate) struct DataTransfer {
+    transfer_id: u16,
+    store_id: u16,
+    slot: u16,
+    kind: DataTransferKind,
+    state: DataTransferState,
+}
+
+impl DataTransfer {
+    /// `data` is the uncompressed still, it is hashed and RLE compressed for the transfer.
+    pub(crate) fn upload(
+        transfer_id: u16,
+        store_id: u16,
+        slot: u16,
+        name: &str,
+        description: &str,
+        data: &[u8],
+    ) -> Self {
+        Self {
+            transfer_id,
+            store_id,
+            slot,
+            kind: DataTransferKind::Upload {
+                name: name.to_string(),
+                description: description.to_string(),
+                data: still_image::rle_encode(data),
+                hash: md5::compute(data).0,
+                offset: 0,
+                description_sent: false,
+            },
+            state: DataTransferState::WaitingForLock,
+        }
+    }
+
+    pub(crate) fn download(transfer_id: u16, store_id: u16, slot: u16) -> Self {
+        Self {
+            transfer_id,
+            store_id,
+            slot,
+            kind: DataTransferKind::Download { data: Vec::new() },
+            state: DataTransferState::WaitingForLock,
+        }
+    }
+
+    /// The received data of a download, still RLE compressed.
+    pub(crate) fn into_data(self) -> Vec<u8> {
+        match self.kind {
+            DataTransferKind::Upload { .. } => Vec::new(),
+            DataTransferKind::Download { data } => data,
+        }
+    }
+
+    pub(crate) fn state(&self) -> &DataTransferState {
+        &self.state
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        matches!(
+            self.state,
+            DataTransferState::Done | DataTransferState::Failed(_)
+        )
+    }
+
+    pub(crate) fn start(&mut self) -> Vec<AtemCommand> {
+        vec![create_lock(self.store_id, true)]
+    }
+
+    pub(crate) fn handle(&mut self, payload: &Payload) -> Vec<AtemCommand> {
+        let mut commands = Vec::new();
+        match (payload, &self.state) {
+            (Payload::LockObtained { store_id }, DataTransferState::WaitingForLock)
+                if *store_id == self.store_id =>
+            {
+                match &self.kind {
+                    DataTransferKind::Upload { data, .. } => {
+                        commands.push(create_upload_request(
+                            self.transfer_id,
+                            self.store_id,
+                            self.slot,
+                            data.len() as u32,
+                        ));
+                    }
+                    DataTransferKind::Download { .. } => {
+                        commands.push(create_download_request(
+                            self.transfer_id,
+                            self.store_id,
+                            self.slot,
+                        ));
+                    }
+                }
+                self.state = DataTransferState::Transferring;
+            }
+            (
+                Payload::DataTransferContinue {
+                    transfer_id,
+                    chunk_size,
+                    chunk_count,
+                },
+                DataTransferState::Transferring,
+            ) if *transfer_id == self.transfer_id => match &mut self.kind {
+                DataTransferKind::Upload {
+                    name,
+                    description,
+                    data,
+                    hash,
+                    offset,
+                    description_sent,
+                } => {
+                    if !*description_sent {
+                        commands.push(create_file_description(
+                            self.transfer_id,
+                            name,
+                            description,
+                            hash,
+                        ));
+                        *description_sent = true;
+                    }
+                    // keep the chunks 8 byte aligned and leave room for the FTDa header
+                    let size = ((*chunk_size as usize).saturating_sub(4) / 8 * 8).max(8);
+                    for _ in 0..*chunk_count {
+                        if *offset >= data.len() {
+                            break;
+                        }
+                        let end = (*offset + size).min(data.len());
+                        commands.push(create_data(self.transfer_id, &data[*offset..end]));
+                        *offset = end;
+                    }
+                    if *offset >= data.len() {
+                        self.state = DataTransferState::WaitingForComplete;
+                    }
+                }
+                DataTransferKind::Download { .. } => {}
+            },
+            (Payload::DataTransferData { transfer_id, data }, DataTransferState::Transferring)
+                if *transfer_id == self.transfer_id =>
+            {
+                if let DataTransferKind::Download { data: received } = &mut self.kind {
+                    received.extend_from_slice(data);
+                    commands.push(create_data_ack(self.transfer_id, self.slot));
+                }
+            }
+            (Payload::DataTransferComplete { transfer_id }, _)
+                if *transfer_id == self.transfer_id =>
+            {
+                commands.push(create_lock(self.store_id, false));
+                self.state = DataTransferState::Done;
+            }
+            (
+                Payload::DataTransferError {
+                    transfer_id,
+                    error_code,
+                },
+                _,
+            ) if *transfer_id == self.transfer_id => {
+                commands.push(create_lock(self.store_id, false));
+                self.state =
+                    DataTransferState::Failed(format!("Transfer failed with code {}", error_code));
+            }
+            _ => {}
+        }
+        commands
+    }
+
+    /// Releases the lock when giving up on a transfer.
+    pub(crate) fn abort(&mut self, reason: &str) -> Vec<AtemCommand> {
+        self.state = DataTransferState::Failed(reason.to_string());
+        vec![create_lock(self.store_id, false)]
+    }
+}
+
+/// A data transfer started by `AtemMini`, it makes progress with every `update()`.
+///
+/// Wait for it without holding a lock on the `AtemMini`, so whatever calls `update()` can go on.
+pub struct PendingTransfer<T> {
+    done: oneshot::Receiver<anyhow::Result<Vec<u8>>>,
+    finish: Box<dyn FnOnce(Vec<u8>) -> anyhow::Result<T> + Send>,
+}
+
+impl<T: 'static> PendingTransfer<T> {
+    /// Runs `f` on the result once the transfer is done.
+    pub(crate) fn map<U>(
+        self,
+        f: impl FnOnce(T) -> anyhow::Result<U> + Send + 'static,
+    ) -> PendingTransfer<U> {
+        let finish = self.finish;
+        PendingTransfer {
+            done: self.done,
+            finish: Box::new(move |data| f(finish(data)?)),
+        }
+    }
+
+    pub async fn wait(self) -> anyhow::Result<T> {
+        let data = self
+            .done
+            .await
+            .map_err(|_| anyhow::anyhow!("Data transfer vanished"))??;
+        (self.finish)(data)
+    }
+}
+
+/// The `AtemMini`'s side of a `PendingTransfer`.
+pub(crate) struct RunningTransfer {
+    pub(crate) transfer: DataTransfer,
+    pub(crate) started: Instant,
+    done: oneshot::Sender<anyhow::Result<Vec<u8>>>,
+}
+
+impl RunningTransfer {
+    pub(crate) fn new(transfer: DataTransfer) -> (Self, PendingTransfer<Vec<u8>>) {
+        let (done_tx, done_rx) = oneshot::channel();
+        let running = Self {
+            transfer,
+            started: Instant::now(),
+            done: done_tx,
+        };
+        let pending = PendingTransfer {
+            done: done_rx,
+            finish: Box::new(Ok),
+        };
+        (running, pending)
+    }
+
+    /// Hands the outcome of a finished transfer to whoever waits for it.
+    pub(crate) fn finish(self) {
+        let result = match self.transfer.state() {
+            DataTransferState::Failed(reason) => Err(anyhow::anyhow!("{}", reason)),
+            _ => Ok(self.transfer.into_data()),
+        };
+        // nobody waiting is fine
+        let _ = self.done.send(result);
+    }
+}
+
+fn create_lock(store_id: u16, locked: bool) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"LOCK", 4);
+    let p = c.payload();
+    p.set_word(0, store_id);
+    p.set(2, locked as u8);
+    c
+}
+
+fn create_upload_request(transfer_id: u16, store_id: u16, slot: u16, size: u32) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"FTSD", 16);
+    let p = c.payload();
+    p.set_word(0, transfer_id);
+    p.set_word(2, store_id);
+    p.set_word(6, slot);
+    p.set_long(8, size);
+    p.set_word(12, 1); // write
+    c
+}
+
+fn create_download_request(transfer_id: u16, store_id: u16, slot: u16) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"FTSU", 12);
+    let p = c.payload();
+    p.set_word(0, transfer_id);
+    p.set_word(2, store_id);
+    p.set_word(6, slot);
+    p.set_word(8, 0x00f9); // still
+    c
+}
+
+fn create_data_ack(transfer_id: u16, slot: u16) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"FTUA", 4);
+    let p = c.payload();
+    p.set_word(0, transfer_id);
+    p.set_word(2, slot);
+    c
+}
+
+fn create_data(transfer_id: u16, data: &[u8]) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"FTDa", 4 + data.len() as u16);
+    let p = c.payload();
+    p.set_word(0, transfer_id);
+    p.set_word(2, data.len() as u16);
+    p.set_bytes(4, data);
+    c
+}
+
+fn create_file_description(
+    transfer_id: u16,
+    name: &str,
+    description: &str,
+    hash: &[u8; 16],
+) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"FTFD", 212);
+    let p = c.payload();
+    p.set_word(0, transfer_id);
+    let name = name.as_bytes();
+    p.set_bytes(2, &name[..name.len().min(63)]);
+    let description = description.as_bytes();
+    p.set_bytes(66, &description[..description.len().min(127)]);
+    p.set_bytes(194, hash);
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::command_chunk;
+    use crate::server::RawChunk;
+
+    fn chunks(commands: Vec<AtemCommand>) -> Vec<RawChunk> {
+        commands.into_iter().map(command_chunk).collect()
+    }
+
+    fn names(chunks: &[RawChunk]) -> Vec<String> {
+        chunks.iter().map(|c| c.name()).collect()
+    }
+
+    #[test]
+    fn upload() {
+        let still = [7u8; 64];
+        let mut transfer = DataTransfer::upload(3, STILL_STORE_ID, 5, "logo", "", &still);
+        let lock = chunks(transfer.start());
+        assert_eq!(lock, [RawChunk::new(b"LOCK", &[0, 0, 1, 0])]);
+
+        let request = chunks(transfer.handle(&Payload::LockObtained { store_id: 0 }));
+        assert_eq!(names(&request), ["FTSD"]);
+        assert_eq!(&request[0].data[..8], &[0, 3, 0, 0, 0, 0, 0, 5]);
+        // 64 equal bytes compress into a single run
+        assert_eq!(&request[0].data[8..12], &24u32.to_be_bytes());
+
+        let data = chunks(transfer.handle(&Payload::DataTransferContinue {
+            transfer_id: 3,
+            chunk_size: 1396,
+            chunk_count: 20,
+        }));
+        assert_eq!(names(&data), ["FTFD", "FTDa"]);
+        assert_eq!(&data[0].data[2..6], b"logo");
+        assert_eq!(&data[0].data[194..210], &md5::compute(still).0);
+        assert_eq!(&data[1].data[..4], &[0, 3, 0, 24]);
+        assert_eq!(data[1].data[4..28], still_image::rle_encode(&still));
+        assert_eq!(transfer.state(), &DataTransferState::WaitingForComplete);
+
+        // other transfers are none of our business
+        assert!(transfer
+            .handle(&Payload::DataTransferComplete { transfer_id: 4 })
+            .is_empty());
+        let unlock = chunks(transfer.handle(&Payload::DataTransferComplete { transfer_id: 3 }));
+        assert_eq!(unlock, [RawChunk::new(b"LOCK", &[0, 0, 0, 0])]);
+        assert_eq!(transfer.state(), &DataTransferState::Done);
+    }
+
+    #[test]
+    fn download() {
+        let mut transfer = DataTransfer::download(4, STILL_STORE_ID, 2);
+        transfer.start();
+        let request = chunks(transfer.handle(&Payload::LockObtained { store_id: 0 }));
+        assert_eq!(names(&request), ["FTSU"]);
+        assert_eq!(&request[0].data[..10], &[0, 4, 0, 0, 0, 0, 0, 2, 0, 0xf9]);
+
+        for part in [[1u8; 8], [2; 8]] {
+            let ack = chunks(transfer.handle(&Payload::DataTransferData {
+                transfer_id: 4,
+                data: part.to_vec(),
+            }));
+            assert_eq!(ack, [RawChunk::new(b"FTUA", &[0, 4, 0, 2])]);
+        }
+        transfer.handle(&Payload::DataTransferComplete { transfer_id: 4 });
+        assert!(transfer.is_finished());
+        assert_eq!(transfer.into_data(), [[1u8; 8], [2; 8]].concat());
+    }
+
+    #[test]
+    fn error_releases_the_lock() {
+        let mut transfer = DataTransfer::download(4, STILL_STORE_ID, 2);
+        transfer.handle(&Payload::LockObtained { store_id: 0 });
+        let unlock = chunks(transfer.handle(&Payload::DataTransferError {
+            transfer_id: 4,
+            error_code: 1,
+        }));
+        assert_eq!(names(&unlock), ["LOCK"]);
+        assert!(matches!(transfer.state(), DataTransferState::Failed(_)));
+    }
+
+    #[tokio::test]
+    async fn pending_transfer() {
+        let mut transfer = DataTransfer::download(4, STILL_STORE_ID, 2);
+        let (running, pending) = RunningTransfer::new(DataTransfer::download(4, STILL_STORE_ID, 2));
+        let pending = pending.map(|data| Ok(data.len()));
+        transfer.handle(&Payload::LockObtained { store_id: 0 });
+        transfer.handle(&Payload::DataTransferData {
+            transfer_id: 4,
+            data: vec![0; 16],
+        });
+        transfer.handle(&Payload::DataTransferComplete { transfer_id: 4 });
+        RunningTransfer {
+            transfer,
+            ..running
+        }
+        .finish();
+        assert_eq!(pending.wait().await.unwrap(), 16);
+
+        let (mut running, pending) =
+            RunningTransfer::new(DataTransfer::download(5, STILL_STORE_ID, 2));
+        running.transfer.abort("Data transfer timed out");
+        running.finish();
+        assert!(pending.wait().await.is_err());
+
+        // dropped without finishing, e.g. with the connection
+        let (running, pending) = RunningTransfer::new(DataTransfer::download(6, STILL_STORE_ID, 2));
+        drop(running);
+        assert!(pending.wait().await.is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{byte_at, AtemCommand};
+
+/// State of one downstream keyer (DskS).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownstreamKeyer {
+    pub on_air: bool,
+    pub in_transition: bool,
+    pub is_auto_transitioning: bool,
+    pub frames_remaining: u8,
+}
+
+impl DownstreamKeyer {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            on_air: byte_at(data, 1) > 0,
+            in_transition: byte_at(data, 2) > 0,
+            is_auto_transitioning: byte_at(data, 3) > 0,
+            frames_remaining: byte_at(data, 4),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DownstreamKeyerState {
+    pub keyers: BTreeMap<u8, DownstreamKeyer>,
+}
+
+pub(crate) fn create_set_on_air(index: u8, on_air: bool) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CDsL", 4);
+    let p = c.payload();
+    p.set(0, index);
+    p.set(1, on_air as u8);
+    c
+}
+
+pub(crate) fn create_auto(index: u8) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"DDsA", 4);
+    c.payload().set(0, index);
+    c
+}
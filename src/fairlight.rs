@@ -0,0 +1,1139 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{
+    byte_at, long_at, signed_long_at, signed_long_long_at, signed_word_at, word_at, AtemCommand,
+    AtemCommandPayload,
+};
+
+// gains are transmitted as hundredths of a dB, balance as hundredths of -100..100
+fn raw_to_db(raw: i32) -> f32 {
+    (raw as f32) / 100.0
+}
+
+fn db_to_raw(db: f32) -> i32 {
+    (db * 100.0).round() as i32
+}
+
+const CFSP_FLAG_FRAMES_DELAY: u16 = 0x0001;
+const CFSP_FLAG_GAIN: u16 = 0x0002;
+const CFSP_FLAG_STEREO_SIMULATION: u16 = 0x0004;
+const CFSP_FLAG_EQUALIZER_ENABLED: u16 = 0x0008;
+const CFSP_FLAG_EQUALIZER_GAIN: u16 = 0x0010;
+const CFSP_FLAG_MAKE_UP_GAIN: u16 = 0x0020;
+const CFSP_FLAG_BALANCE: u16 = 0x0040;
+const CFSP_FLAG_FADER_GAIN: u16 = 0x0080;
+const CFSP_FLAG_MIX_OPTION: u16 = 0x0100;
+
+const CFMP_FLAG_EQUALIZER_ENABLED: u8 = 0x01;
+const CFMP_FLAG_EQUALIZER_GAIN: u8 = 0x02;
+const CFMP_FLAG_MAKE_UP_GAIN: u8 = 0x04;
+const CFMP_FLAG_FADER_GAIN: u8 = 0x08;
+const CFMP_FLAG_FOLLOW_FADE_TO_BLACK: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FairlightMixOption {
+    Off,
+    On,
+    AudioFollowVideo,
+    Unknown(u8),
+}
+
+impl FairlightMixOption {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => FairlightMixOption::Off,
+            2 => FairlightMixOption::On,
+            4 => FairlightMixOption::AudioFollowVideo,
+            o => FairlightMixOption::Unknown(o),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            FairlightMixOption::Off => 1,
+            FairlightMixOption::On => 2,
+            FairlightMixOption::AudioFollowVideo => 4,
+            FairlightMixOption::Unknown(o) => o,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FairlightInputConfiguration {
+    Mono,
+    Stereo,
+    DualMono,
+    Unknown(u8),
+}
+
+impl FairlightInputConfiguration {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => FairlightInputConfiguration::Mono,
+            2 => FairlightInputConfiguration::Stereo,
+            4 => FairlightInputConfiguration::DualMono,
+            o => FairlightInputConfiguration::Unknown(o),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FairlightInputLevel {
+    None,
+    Microphone,
+    ConsumerLine,
+    ProLine,
+    Unknown(u8),
+}
+
+impl FairlightInputLevel {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0 => FairlightInputLevel::None,
+            1 => FairlightInputLevel::Microphone,
+            2 => FairlightInputLevel::ConsumerLine,
+            4 => FairlightInputLevel::ProLine,
+            o => FairlightInputLevel::Unknown(o),
+        }
+    }
+}
+
+/// Properties of a physical/logical audio input (FAIP).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairlightInputProperties {
+    pub input_type: u8,
+    pub external_port_type: u16,
+    pub supported_configurations: u8,
+    pub active_configuration: FairlightInputConfiguration,
+    pub supported_input_levels: u8,
+    pub active_input_level: FairlightInputLevel,
+}
+
+impl FairlightInputProperties {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            input_type: byte_at(data, 2),
+            external_port_type: word_at(data, 6),
+            supported_configurations: byte_at(data, 10),
+            active_configuration: FairlightInputConfiguration::from_u8(byte_at(data, 11)),
+            supported_input_levels: byte_at(data, 12),
+            active_input_level: FairlightInputLevel::from_u8(byte_at(data, 13)),
+        }
+    }
+}
+
+/// Properties of one source (channel) of an audio input (FASP).
+///
+/// All gains are in dB, balance is -100.0 (left) to 100.0 (right).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairlightSourceProperties {
+    pub source_type: u8,
+    pub max_frames_delay: u8,
+    pub frames_delay: u8,
+    pub gain: f32,
+    pub has_stereo_simulation: bool,
+    pub stereo_simulation: f32,
+    pub equalizer_bands: u8,
+    pub equalizer_enabled: bool,
+    pub equalizer_gain: f32,
+    pub make_up_gain: f32,
+    pub balance: f32,
+    pub fader_gain: f32,
+    pub supported_mix_options: u8,
+    pub mix_option: FairlightMixOption,
+}
+
+impl FairlightSourceProperties {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            source_type: byte_at(data, 16),
+            max_frames_delay: byte_at(data, 17),
+            frames_delay: byte_at(data, 18),
+            gain: raw_to_db(signed_long_at(data, 20)),
+            has_stereo_simulation: byte_at(data, 24) > 0,
+            stereo_simulation: (word_at(data, 26) as f32) / 100.0,
+            equalizer_bands: byte_at(data, 28),
+            equalizer_enabled: byte_at(data, 29) > 0,
+            equalizer_gain: raw_to_db(signed_long_at(data, 32)),
+            make_up_gain: raw_to_db(signed_long_at(data, 36)),
+            balance: (signed_word_at(data, 40) as f32) / 100.0,
+            fader_gain: raw_to_db(signed_long_at(data, 44)),
+            supported_mix_options: byte_at(data, 48),
+            mix_option: FairlightMixOption::from_u8(byte_at(data, 49)),
+        }
+    }
+}
+
+/// Master output (program) properties (FAMP + FMPP).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FairlightMasterProperties {
+    pub equalizer_bands: u8,
+    pub equalizer_enabled: bool,
+    pub equalizer_gain: f32,
+    pub make_up_gain: f32,
+    pub fader_gain: f32,
+    pub follow_fade_to_black: bool,
+}
+
+impl FairlightMasterProperties {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            equalizer_bands: byte_at(data, 0),
+            equalizer_enabled: byte_at(data, 1) > 0,
+            equalizer_gain: raw_to_db(signed_long_at(data, 4)),
+            make_up_gain: raw_to_db(signed_long_at(data, 8)),
+            fader_gain: raw_to_db(signed_long_at(data, 12)),
+            follow_fade_to_black: byte_at(data, 16) > 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FairlightEqualizerShape {
+    LowShelf,
+    LowPass,
+    BandPass,
+    Notch,
+    HighPass,
+    HighShelf,
+    Unknown(u8),
+}
+
+impl FairlightEqualizerShape {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0x01 => FairlightEqualizerShape::LowShelf,
+            0x02 => FairlightEqualizerShape::LowPass,
+            0x04 => FairlightEqualizerShape::BandPass,
+            0x08 => FairlightEqualizerShape::Notch,
+            0x10 => FairlightEqualizerShape::HighPass,
+            0x20 => FairlightEqualizerShape::HighShelf,
+            o => FairlightEqualizerShape::Unknown(o),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            FairlightEqualizerShape::LowShelf => 0x01,
+            FairlightEqualizerShape::LowPass => 0x02,
+            FairlightEqualizerShape::BandPass => 0x04,
+            FairlightEqualizerShape::Notch => 0x08,
+            FairlightEqualizerShape::HighPass => 0x10,
+            FairlightEqualizerShape::HighShelf => 0x20,
+            FairlightEqualizerShape::Unknown(o) => o,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FairlightFrequencyRange {
+    Low,
+    MidLow,
+    MidHigh,
+    High,
+    Unknown(u8),
+}
+
+impl FairlightFrequencyRange {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0x01 => FairlightFrequencyRange::Low,
+            0x02 => FairlightFrequencyRange::MidLow,
+            0x04 => FairlightFrequencyRange::MidHigh,
+            0x08 => FairlightFrequencyRange::High,
+            o => FairlightFrequencyRange::Unknown(o),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            FairlightFrequencyRange::Low => 0x01,
+            FairlightFrequencyRange::MidLow => 0x02,
+            FairlightFrequencyRange::MidHigh => 0x04,
+            FairlightFrequencyRange::High => 0x08,
+            FairlightFrequencyRange::Unknown(o) => o,
+        }
+    }
+}
+
+/// One parametric EQ band (AEBP for sources, AMBP for the master).
+///
+/// `frequency` is in Hz, `gain` in dB.
+/// `supported_shapes` and `supported_frequency_ranges` are bit masks and read only.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairlightEqualizerBand {
+    pub enabled: bool,
+    pub supported_shapes: u8,
+    pub shape: FairlightEqualizerShape,
+    pub supported_frequency_ranges: u8,
+    pub frequency_range: FairlightFrequencyRange,
+    pub frequency: u32,
+    pub gain: f32,
+    pub q_factor: f32,
+}
+
+impl FairlightEqualizerBand {
+    /// `e` is the index of the enabled flag, `o` the index of the frequency.
+    fn write(&self, p: &mut AtemCommandPayload, e: usize, o: usize) {
+        p.set(e, self.enabled as u8);
+        p.set(e + 1, self.shape.to_u8());
+        p.set(e + 2, self.frequency_range.to_u8());
+        p.set_long(o, self.frequency);
+        p.set_signed_long(o + 4, db_to_raw(self.gain));
+        p.set_signed_word(o + 8, (self.q_factor * 100.0).round() as i16);
+    }
+
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            enabled: byte_at(data, 0) > 0,
+            supported_shapes: byte_at(data, 1),
+            shape: FairlightEqualizerShape::from_u8(byte_at(data, 2)),
+            supported_frequency_ranges: byte_at(data, 3),
+            frequency_range: FairlightFrequencyRange::from_u8(byte_at(data, 4)),
+            frequency: long_at(data, 7),
+            gain: raw_to_db(signed_long_at(data, 11)),
+            q_factor: (signed_word_at(data, 15) as f32) / 100.0,
+        }
+    }
+}
+
+/// Compressor settings (AICP for sources, MOCP for the master).
+///
+/// `threshold` in dB, `ratio` as x:1, times in ms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairlightCompressor {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub ratio: f32,
+    pub attack: f32,
+    pub hold: f32,
+    pub release: f32,
+}
+
+impl FairlightCompressor {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            enabled: byte_at(data, 0) > 0,
+            threshold: raw_to_db(signed_long_at(data, 4)),
+            ratio: (signed_word_at(data, 8) as f32) / 100.0,
+            attack: (signed_long_at(data, 12) as f32) / 100.0,
+            hold: (signed_long_at(data, 16) as f32) / 100.0,
+            release: (signed_long_at(data, 20) as f32) / 100.0,
+        }
+    }
+
+    /// `e` is the index of the enabled flag, `o` the index of the threshold.
+    fn write(&self, p: &mut AtemCommandPayload, e: usize, o: usize) {
+        p.set(e, self.enabled as u8);
+        p.set_signed_long(o, db_to_raw(self.threshold));
+        p.set_signed_word(o + 4, (self.ratio * 100.0).round() as i16);
+        p.set_signed_long(o + 8, (self.attack * 100.0).round() as i32);
+        p.set_signed_long(o + 12, (self.hold * 100.0).round() as i32);
+        p.set_signed_long(o + 16, (self.release * 100.0).round() as i32);
+    }
+}
+
+/// Limiter settings (AILP for sources, AMLP for the master).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairlightLimiter {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub attack: f32,
+    pub hold: f32,
+    pub release: f32,
+}
+
+impl FairlightLimiter {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            enabled: byte_at(data, 0) > 0,
+            threshold: raw_to_db(signed_long_at(data, 4)),
+            attack: (signed_long_at(data, 8) as f32) / 100.0,
+            hold: (signed_long_at(data, 12) as f32) / 100.0,
+            release: (signed_long_at(data, 16) as f32) / 100.0,
+        }
+    }
+
+    /// `e` is the index of the enabled flag, `o` the index of the threshold.
+    fn write(&self, p: &mut AtemCommandPayload, e: usize, o: usize) {
+        p.set(e, self.enabled as u8);
+        p.set_signed_long(o, db_to_raw(self.threshold));
+        p.set_signed_long(o + 4, (self.attack * 100.0).round() as i32);
+        p.set_signed_long(o + 8, (self.hold * 100.0).round() as i32);
+        p.set_signed_long(o + 12, (self.release * 100.0).round() as i32);
+    }
+}
+
+/// Expander/gate settings (AIXP), only available on sources.
+///
+/// `range` in dB, `gate_enabled` switches the expander into gate mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairlightExpander {
+    pub enabled: bool,
+    pub gate_enabled: bool,
+    pub threshold: f32,
+    pub range: f32,
+    pub ratio: f32,
+    pub attack: f32,
+    pub hold: f32,
+    pub release: f32,
+}
+
+impl FairlightExpander {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            enabled: byte_at(data, 0) > 0,
+            gate_enabled: byte_at(data, 1) > 0,
+            threshold: raw_to_db(signed_long_at(data, 4)),
+            range: (signed_word_at(data, 8) as f32) / 100.0,
+            ratio: (signed_word_at(data, 10) as f32) / 100.0,
+            attack: (signed_long_at(data, 12) as f32) / 100.0,
+            hold: (signed_long_at(data, 16) as f32) / 100.0,
+            release: (signed_long_at(data, 20) as f32) / 100.0,
+        }
+    }
+
+    fn write(&self, p: &mut AtemCommandPayload, o: usize) {
+        p.set(o, self.enabled as u8);
+        p.set(o + 1, self.gate_enabled as u8);
+        p.set_signed_long(o + 4, db_to_raw(self.threshold));
+        p.set_signed_word(o + 8, (self.range * 100.0).round() as i16);
+        p.set_signed_word(o + 10, (self.ratio * 100.0).round() as i16);
+        p.set_signed_long(o + 12, (self.attack * 100.0).round() as i32);
+        p.set_signed_long(o + 16, (self.hold * 100.0).round() as i32);
+        p.set_signed_long(o + 20, (self.release * 100.0).round() as i32);
+    }
+}
+
+/// Headphone/monitor output mix (FMHP), all gains in dB.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FairlightMonitor {
+    pub gain: f32,
+    pub input_master_gain: f32,
+    pub input_talkback_gain: f32,
+    pub input_sidetone_gain: f32,
+}
+
+impl FairlightMonitor {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            gain: raw_to_db(signed_long_at(data, 0)),
+            input_master_gain: raw_to_db(signed_long_at(data, 4)),
+            input_talkback_gain: raw_to_db(signed_long_at(data, 12)),
+            input_sidetone_gain: raw_to_db(signed_long_at(data, 24)),
+        }
+    }
+}
+
+/// Which source is soloed on the monitor output (FAMS).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FairlightSolo {
+    pub solo: bool,
+    pub input: u16,
+    pub source: i64,
+}
+
+impl FairlightSolo {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            solo: byte_at(data, 0) > 0,
+            input: word_at(data, 8),
+            source: signed_long_long_at(data, 16),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FairlightSource {
+    pub properties: Option<FairlightSourceProperties>,
+    pub equalizer_bands: BTreeMap<u8, FairlightEqualizerBand>,
+    pub compressor: Option<FairlightCompressor>,
+    pub limiter: Option<FairlightLimiter>,
+    pub expander: Option<FairlightExpander>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FairlightInput {
+    pub properties: Option<FairlightInputProperties>,
+    pub sources: BTreeMap<i64, FairlightSource>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FairlightMaster {
+    pub properties: FairlightMasterProperties,
+    pub equalizer_bands: BTreeMap<u8, FairlightEqualizerBand>,
+    pub compressor: Option<FairlightCompressor>,
+    pub limiter: Option<FairlightLimiter>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FairlightState {
+    pub inputs: BTreeMap<u16, FairlightInput>,
+    pub master: FairlightMaster,
+    pub audio_follow_video_crossfade_transition: bool,
+    pub monitor: Option<FairlightMonitor>,
+    pub solo: Option<FairlightSolo>,
+}
+
+impl FairlightState {
+    pub(crate) fn source_mut(&mut self, input: u16, source: i64) -> &mut FairlightSource {
+        self.inputs
+            .entry(input)
+            .or_default()
+            .sources
+            .entry(source)
+            .or_default()
+    }
+}
+
+// levels are transmitted as hundredths of a dBFS, -100.0 means silence
+fn level_at(data: &[u8], index: usize) -> f32 {
+    (signed_word_at(data, index) as f32) / 100.0
+}
+
+/// RMS level and peak of a stereo pair in dBFS.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StereoLevels {
+    pub left: f32,
+    pub right: f32,
+    pub left_peak: f32,
+    pub right_peak: f32,
+}
+
+impl StereoLevels {
+    fn from_data(data: &[u8], o: usize) -> Self {
+        Self {
+            left: level_at(data, o),
+            right: level_at(data, o + 2),
+            left_peak: level_at(data, o + 4),
+            right_peak: level_at(data, o + 6),
+        }
+    }
+
+    pub fn level(&self) -> f32 {
+        self.left.max(self.right)
+    }
+
+    pub fn peak(&self) -> f32 {
+        self.left_peak.max(self.right_peak)
+    }
+
+    /// True if both channels peak below `threshold` dBFS.
+    pub fn is_silent(&self, threshold: f32) -> bool {
+        self.peak() < threshold
+    }
+}
+
+/// Meter values of one source (FMLv).
+///
+/// `input` is before, `output` after the dynamics, `fader` after the fader.
+/// Gain reductions are in dB.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FairlightSourceLevels {
+    pub input: StereoLevels,
+    pub expander_gain_reduction: f32,
+    pub compressor_gain_reduction: f32,
+    pub limiter_gain_reduction: f32,
+    pub output: StereoLevels,
+    pub fader: StereoLevels,
+}
+
+impl FairlightSourceLevels {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            input: StereoLevels::from_data(data, 16),
+            expander_gain_reduction: level_at(data, 24),
+            compressor_gain_reduction: level_at(data, 26),
+            limiter_gain_reduction: level_at(data, 28),
+            output: StereoLevels::from_data(data, 30),
+            fader: StereoLevels::from_data(data, 38),
+        }
+    }
+}
+
+/// Meter values of the program mix (FDLv).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FairlightMasterLevels {
+    pub input: StereoLevels,
+    pub compressor_gain_reduction: f32,
+    pub limiter_gain_reduction: f32,
+    pub output: StereoLevels,
+    pub fader: StereoLevels,
+}
+
+impl FairlightMasterLevels {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            input: StereoLevels::from_data(data, 0),
+            compressor_gain_reduction: level_at(data, 8),
+            limiter_gain_reduction: level_at(data, 10),
+            output: StereoLevels::from_data(data, 12),
+            fader: StereoLevels::from_data(data, 20),
+        }
+    }
+}
+
+/// Latest meter values, published through `AtemMini::audio_levels()`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioLevels {
+    pub sources: BTreeMap<(u16, i64), FairlightSourceLevels>,
+    pub master: FairlightMasterLevels,
+}
+
+/// Builds a CFSP with only the given change flag set, `f` fills in the value for that flag.
+fn source_command(
+    input: u16,
+    source: i64,
+    flag: u16,
+    f: impl FnOnce(&mut AtemCommandPayload),
+) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CFSP", 48);
+    let p = c.payload();
+    p.set_word(0, flag);
+    p.set_word(2, input);
+    p.set_signed_long_long(8, source);
+    f(p);
+    c
+}
+
+/// Builds a CFMP with only the given change flag set, `f` fills in the value for that flag.
+fn master_command(flag: u8, f: impl FnOnce(&mut AtemCommandPayload)) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CFMP", 20);
+    let p = c.payload();
+    p.set(0, flag);
+    f(p);
+    c
+}
+
+pub(crate) fn create_set_source_frames_delay(input: u16, source: i64, frames: u8) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_FRAMES_DELAY, |p| p.set(16, frames))
+}
+
+pub(crate) fn create_set_source_gain(input: u16, source: i64, gain: f32) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_GAIN, |p| {
+        p.set_signed_long(20, db_to_raw(gain))
+    })
+}
+
+pub(crate) fn create_set_source_stereo_simulation(
+    input: u16,
+    source: i64,
+    stereo_simulation: f32,
+) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_STEREO_SIMULATION, |p| {
+        p.set_word(24, (stereo_simulation * 100.0).round() as u16)
+    })
+}
+
+pub(crate) fn create_set_source_equalizer_enabled(
+    input: u16,
+    source: i64,
+    enabled: bool,
+) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_EQUALIZER_ENABLED, |p| {
+        p.set(26, enabled as u8)
+    })
+}
+
+pub(crate) fn create_set_source_equalizer_gain(input: u16, source: i64, gain: f32) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_EQUALIZER_GAIN, |p| {
+        p.set_signed_long(28, db_to_raw(gain))
+    })
+}
+
+pub(crate) fn create_set_source_make_up_gain(input: u16, source: i64, gain: f32) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_MAKE_UP_GAIN, |p| {
+        p.set_signed_long(32, db_to_raw(gain))
+    })
+}
+
+pub(crate) fn create_set_source_balance(input: u16, source: i64, balance: f32) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_BALANCE, |p| {
+        p.set_signed_word(36, (balance.clamp(-100.0, 100.0) * 100.0).round() as i16)
+    })
+}
+
+pub(crate) fn create_set_source_fader_gain(input: u16, source: i64, gain: f32) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_FADER_GAIN, |p| {
+        p.set_signed_long(40, db_to_raw(gain))
+    })
+}
+
+pub(crate) fn create_set_source_mix_option(
+    input: u16,
+    source: i64,
+    mix_option: FairlightMixOption,
+) -> AtemCommand {
+    source_command(input, source, CFSP_FLAG_MIX_OPTION, |p| {
+        p.set(44, mix_option.to_u8())
+    })
+}
+
+pub(crate) fn create_set_master_equalizer_enabled(enabled: bool) -> AtemCommand {
+    master_command(CFMP_FLAG_EQUALIZER_ENABLED, |p| p.set(1, enabled as u8))
+}
+
+pub(crate) fn create_set_master_equalizer_gain(gain: f32) -> AtemCommand {
+    master_command(CFMP_FLAG_EQUALIZER_GAIN, |p| {
+        p.set_signed_long(4, db_to_raw(gain))
+    })
+}
+
+pub(crate) fn create_set_master_make_up_gain(gain: f32) -> AtemCommand {
+    master_command(CFMP_FLAG_MAKE_UP_GAIN, |p| {
+        p.set_signed_long(8, db_to_raw(gain))
+    })
+}
+
+pub(crate) fn create_set_master_fader_gain(gain: f32) -> AtemCommand {
+    master_command(CFMP_FLAG_FADER_GAIN, |p| {
+        p.set_signed_long(12, db_to_raw(gain))
+    })
+}
+
+pub(crate) fn create_set_master_follow_fade_to_black(follow: bool) -> AtemCommand {
+    master_command(CFMP_FLAG_FOLLOW_FADE_TO_BLACK, |p| p.set(16, follow as u8))
+}
+
+// The EQ and dynamics setters below always send every parameter,
+// so a band or processor read from the state can be stored and recalled as a whole.
+
+fn source_dynamics_command(
+    cmd: &[u8; 4],
+    len: u16,
+    input: u16,
+    source: i64,
+    flag: u8,
+) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, cmd, len);
+    let p = c.payload();
+    p.set(0, flag);
+    p.set_word(2, input);
+    p.set_signed_long_long(8, source);
+    c
+}
+
+pub(crate) fn create_set_source_equalizer_band(
+    input: u16,
+    source: i64,
+    index: u8,
+    band: &FairlightEqualizerBand,
+) -> AtemCommand {
+    let mut c = source_dynamics_command(b"CEBP", 32, input, source, 0x3f);
+    let p = c.payload();
+    p.set(16, index);
+    band.write(p, 17, 20);
+    c
+}
+
+pub(crate) fn create_set_source_compressor(
+    input: u16,
+    source: i64,
+    compressor: &FairlightCompressor,
+) -> AtemCommand {
+    let mut c = source_dynamics_command(b"CICP", 40, input, source, 0x3f);
+    compressor.write(c.payload(), 16, 20);
+    c
+}
+
+pub(crate) fn create_set_source_limiter(
+    input: u16,
+    source: i64,
+    limiter: &FairlightLimiter,
+) -> AtemCommand {
+    let mut c = source_dynamics_command(b"CILP", 36, input, source, 0x1f);
+    limiter.write(c.payload(), 16, 20);
+    c
+}
+
+pub(crate) fn create_set_source_expander(
+    input: u16,
+    source: i64,
+    expander: &FairlightExpander,
+) -> AtemCommand {
+    let mut c = source_dynamics_command(b"CIXP", 40, input, source, 0xff);
+    expander.write(c.payload(), 16);
+    c
+}
+
+pub(crate) fn create_set_master_equalizer_band(
+    index: u8,
+    band: &FairlightEqualizerBand,
+) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CMBP", 20);
+    let p = c.payload();
+    p.set(0, 0x3f);
+    p.set(1, index);
+    band.write(p, 2, 8);
+    c
+}
+
+pub(crate) fn create_set_master_compressor(compressor: &FairlightCompressor) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CMCP", 24);
+    let p = c.payload();
+    p.set(0, 0x3f);
+    compressor.write(p, 1, 4);
+    c
+}
+
+pub(crate) fn create_set_master_limiter(limiter: &FairlightLimiter) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CMLP", 20);
+    let p = c.payload();
+    p.set(0, 0x1f);
+    limiter.write(p, 1, 4);
+    c
+}
+
+/// Level reporting is off by default.
+/// SALN switches it for the classic audio mixer, SFLN for Fairlight,
+/// we send both and the switcher ignores the one it doesn't have.
+pub(crate) fn create_send_levels(enabled: bool) -> [AtemCommand; 2] {
+    let mut classic = AtemCommand::create_command(0, 0, b"SALN", 4);
+    classic.payload().set(0, enabled as u8);
+    let mut fairlight = AtemCommand::create_command(0, 0, b"SFLN", 4);
+    fairlight.payload().set(0, enabled as u8);
+    [classic, fairlight]
+}
+
+pub(crate) fn create_set_audio_follow_video_crossfade_transition(enabled: bool) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CMPP", 4);
+    let p = c.payload();
+    p.set(0, 0x01);
+    p.set(1, enabled as u8);
+    c
+}
+
+pub(crate) fn create_set_monitor(monitor: &FairlightMonitor) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CFMH", 36);
+    let p = c.payload();
+    p.set(0, 0x01 | 0x02 | 0x08 | 0x40);
+    p.set_signed_long(4, db_to_raw(monitor.gain));
+    p.set_signed_long(8, db_to_raw(monitor.input_master_gain));
+    p.set_signed_long(16, db_to_raw(monitor.input_talkback_gain));
+    p.set_signed_long(28, db_to_raw(monitor.input_sidetone_gain));
+    c
+}
+
+pub(crate) fn create_set_solo(solo: bool, input: u16, source: i64) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CFMS", 24);
+    let p = c.payload();
+    // only touch the soloed source when switching solo on
+    p.set(0, if solo { 0x07 } else { 0x01 });
+    p.set(1, solo as u8);
+    p.set_word(8, input);
+    p.set_signed_long_long(16, source);
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::{command_data, decode};
+    use crate::payload::Payload;
+
+    #[test]
+    fn source_properties_from_data() {
+        let mut data = vec![0; 52];
+        data[0..2].copy_from_slice(&1301u16.to_be_bytes());
+        data[8..16].copy_from_slice(&(-256i64).to_be_bytes());
+        data[18] = 2;
+        data[20..24].copy_from_slice(&(-650i32).to_be_bytes());
+        data[40..42].copy_from_slice(&(-2500i16).to_be_bytes());
+        data[44..48].copy_from_slice(&(-1000i32).to_be_bytes());
+        data[49] = 4;
+
+        let payloads = decode(b"FASP", &data);
+        let Some(Payload::FairlightSource {
+            input,
+            source,
+            properties,
+        }) = payloads.first()
+        else {
+            panic!("no FASP payload: {:?}", payloads);
+        };
+        assert_eq!((*input, *source), (1301, -256));
+        assert_eq!(properties.frames_delay, 2);
+        assert_eq!(properties.gain, -6.5);
+        assert_eq!(properties.balance, -25.0);
+        assert_eq!(properties.fader_gain, -10.0);
+        assert_eq!(properties.mix_option, FairlightMixOption::AudioFollowVideo);
+    }
+
+    #[test]
+    fn source_setters() {
+        let data = command_data(create_set_source_gain(1301, -256, -6.5));
+        assert_eq!(data.len(), 48);
+        assert_eq!(word_at(&data, 0), CFSP_FLAG_GAIN);
+        assert_eq!(word_at(&data, 2), 1301);
+        assert_eq!(signed_long_long_at(&data, 8), -256);
+        assert_eq!(signed_long_at(&data, 20), -650);
+
+        let data = command_data(create_set_source_balance(1, 0, -250.0));
+        assert_eq!(word_at(&data, 0), CFSP_FLAG_BALANCE);
+        assert_eq!(signed_word_at(&data, 36), -10000);
+
+        let data = command_data(create_set_source_mix_option(
+            1,
+            0,
+            FairlightMixOption::AudioFollowVideo,
+        ));
+        assert_eq!(word_at(&data, 0), CFSP_FLAG_MIX_OPTION);
+        assert_eq!(byte_at(&data, 44), 4);
+    }
+
+    #[test]
+    fn master_setters() {
+        let data = command_data(create_set_master_fader_gain(3.25));
+        assert_eq!(data.len(), 20);
+        assert_eq!(byte_at(&data, 0), CFMP_FLAG_FADER_GAIN);
+        assert_eq!(signed_long_at(&data, 12), 325);
+
+        let data = command_data(create_set_master_follow_fade_to_black(true));
+        assert_eq!(byte_at(&data, 0), CFMP_FLAG_FOLLOW_FADE_TO_BLACK);
+        assert_eq!(byte_at(&data, 16), 1);
+    }
+
+    #[test]
+    fn equalizer_band_from_data() {
+        let mut data = vec![0; 36];
+        data[0..2].copy_from_slice(&1301u16.to_be_bytes());
+        data[16] = 3;
+        data[17] = 1;
+        data[18] = 0x3f;
+        data[19] = 0x04;
+        data[21] = 0x02;
+        data[24..28].copy_from_slice(&1200u32.to_be_bytes());
+        data[28..32].copy_from_slice(&(-350i32).to_be_bytes());
+        data[32..34].copy_from_slice(&140i16.to_be_bytes());
+
+        let payloads = decode(b"AEBP", &data);
+        let Some(Payload::FairlightSourceEqualizerBand {
+            input,
+            band,
+            properties,
+            ..
+        }) = payloads.first()
+        else {
+            panic!("no AEBP payload: {:?}", payloads);
+        };
+        assert_eq!((*input, *band), (1301, 3));
+        assert!(properties.enabled);
+        assert_eq!(properties.supported_shapes, 0x3f);
+        assert_eq!(properties.shape, FairlightEqualizerShape::BandPass);
+        assert_eq!(properties.frequency_range, FairlightFrequencyRange::MidLow);
+        assert_eq!(properties.frequency, 1200);
+        assert_eq!(properties.gain, -3.5);
+        assert_eq!(properties.q_factor, 1.4);
+    }
+
+    #[test]
+    fn equalizer_band_setter() {
+        let band = FairlightEqualizerBand {
+            enabled: true,
+            supported_shapes: 0x3f,
+            shape: FairlightEqualizerShape::HighShelf,
+            supported_frequency_ranges: 0x0f,
+            frequency_range: FairlightFrequencyRange::High,
+            frequency: 8000,
+            gain: 2.5,
+            q_factor: 0.7,
+        };
+        let data = command_data(create_set_source_equalizer_band(1301, -256, 3, &band));
+        assert_eq!(data.len(), 32);
+        assert_eq!(word_at(&data, 2), 1301);
+        assert_eq!(signed_long_long_at(&data, 8), -256);
+        assert_eq!(&data[16..20], &[3, 1, 0x20, 0x08]);
+        assert_eq!(long_at(&data, 20), 8000);
+        assert_eq!(signed_long_at(&data, 24), 250);
+        assert_eq!(signed_word_at(&data, 28), 70);
+
+        let data = command_data(create_set_master_equalizer_band(3, &band));
+        assert_eq!(&data[0..5], &[0x3f, 3, 1, 0x20, 0x08]);
+        assert_eq!(long_at(&data, 8), 8000);
+    }
+
+    #[test]
+    fn source_dynamics_round_trip() {
+        let compressor = FairlightCompressor {
+            enabled: true,
+            threshold: -20.0,
+            ratio: 4.0,
+            attack: 1.4,
+            hold: 0.0,
+            release: 93.0,
+        };
+        let data = command_data(create_set_source_compressor(1301, -256, &compressor));
+        let payloads = decode(b"AICP", &data);
+        assert!(matches!(
+            payloads.first(),
+            Some(Payload::FairlightSourceCompressor { compressor: c, .. }) if *c == compressor
+        ));
+
+        let limiter = FairlightLimiter {
+            enabled: false,
+            threshold: -6.0,
+            attack: 0.7,
+            hold: 1.0,
+            release: 50.0,
+        };
+        let data = command_data(create_set_source_limiter(1301, -256, &limiter));
+        let payloads = decode(b"AILP", &data);
+        assert!(matches!(
+            payloads.first(),
+            Some(Payload::FairlightSourceLimiter { limiter: l, .. }) if *l == limiter
+        ));
+
+        let expander = FairlightExpander {
+            enabled: true,
+            gate_enabled: true,
+            threshold: -45.0,
+            range: 18.0,
+            ratio: 1.1,
+            attack: 1.4,
+            hold: 4.0,
+            release: 93.0,
+        };
+        let data = command_data(create_set_source_expander(1301, -256, &expander));
+        let payloads = decode(b"AIXP", &data);
+        assert!(matches!(
+            payloads.first(),
+            Some(Payload::FairlightSourceExpander { expander: x, .. }) if *x == expander
+        ));
+    }
+
+    #[test]
+    fn master_dynamics_round_trip() {
+        let compressor = FairlightCompressor {
+            enabled: true,
+            threshold: -12.5,
+            ratio: 2.0,
+            attack: 5.0,
+            hold: 0.0,
+            release: 100.0,
+        };
+        // CMCP puts its change flags first, MOCP the enabled flag
+        let mut data = command_data(create_set_master_compressor(&compressor));
+        data[0] = data[1];
+        assert!(matches!(
+            decode(b"MOCP", &data).first(),
+            Some(Payload::FairlightMasterCompressor(c)) if *c == compressor
+        ));
+
+        let limiter = FairlightLimiter {
+            enabled: true,
+            threshold: -1.0,
+            attack: 0.7,
+            hold: 0.0,
+            release: 50.0,
+        };
+        let mut data = command_data(create_set_master_limiter(&limiter));
+        data[0] = data[1];
+        assert!(matches!(
+            decode(b"AMLP", &data).first(),
+            Some(Payload::FairlightMasterLimiter(l)) if *l == limiter
+        ));
+    }
+
+    fn levels_data(values: &[i16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn source_levels_from_data() {
+        let mut data = vec![0; 16];
+        data[0..2].copy_from_slice(&1301u16.to_be_bytes());
+        data[8..16].copy_from_slice(&(-256i64).to_be_bytes());
+        data.extend(levels_data(&[
+            -2000, -1800, -600, -550, -300, -200, -100, -2100, -1900, -700, -650, -2200, -2000,
+            -800, -750,
+        ]));
+
+        let payloads = decode(b"FMLv", &data);
+        let Some(Payload::FairlightSourceLevels {
+            input,
+            source,
+            levels,
+        }) = payloads.first()
+        else {
+            panic!("no FMLv payload: {:?}", payloads);
+        };
+        assert_eq!((*input, *source), (1301, -256));
+        assert_eq!(levels.input.level(), -18.0);
+        assert_eq!(levels.input.peak(), -5.5);
+        assert_eq!(levels.expander_gain_reduction, -3.0);
+        assert_eq!(levels.limiter_gain_reduction, -1.0);
+        assert_eq!(levels.output.left, -21.0);
+        assert_eq!(levels.fader.right_peak, -7.5);
+        assert!(!levels.fader.is_silent(-60.0));
+    }
+
+    #[test]
+    fn master_levels_from_data() {
+        let data = levels_data(&[
+            -10000, -10000, -10000, -10000, -400, -200, -1000, -1100, -500, -450, -1200, -1300,
+            -600, -650,
+        ]);
+        let payloads = decode(b"FDLv", &data);
+        let Some(Payload::FairlightMasterLevels(levels)) = payloads.first() else {
+            panic!("no FDLv payload: {:?}", payloads);
+        };
+        assert!(levels.input.is_silent(-60.0));
+        assert_eq!(levels.compressor_gain_reduction, -4.0);
+        assert_eq!(levels.limiter_gain_reduction, -2.0);
+        assert_eq!(levels.output.level(), -10.0);
+        assert_eq!(levels.fader.peak(), -6.0);
+    }
+
+    #[test]
+    fn send_levels() {
+        let [classic, fairlight] = create_send_levels(true);
+        assert_eq!(command_data(classic), [1, 0, 0, 0]);
+        assert_eq!(command_data(fairlight), [1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn monitor_round_trip() {
+        let monitor = FairlightMonitor {
+            gain: -3.0,
+            input_master_gain: 0.0,
+            input_talkback_gain: -10.5,
+            input_sidetone_gain: -60.0,
+        };
+        // CFMH has the change flags in front of the FMHP layout
+        let data = command_data(create_set_monitor(&monitor));
+        assert_eq!(byte_at(&data, 0), 0x4b);
+        assert!(matches!(
+            decode(b"FMHP", &data[4..]).first(),
+            Some(Payload::FairlightMonitor(m)) if *m == monitor
+        ));
+    }
+
+    #[test]
+    fn solo_round_trip() {
+        let mut data = command_data(create_set_solo(true, 1301, -256));
+        assert_eq!(byte_at(&data, 0), 0x07);
+        data[0] = data[1];
+        let solo = FairlightSolo {
+            solo: true,
+            input: 1301,
+            source: -256,
+        };
+        assert!(matches!(
+            decode(b"FAMS", &data).first(),
+            Some(Payload::FairlightSolo(s)) if *s == solo
+        ));
+
+        let data = command_data(create_set_solo(false, 1301, -256));
+        assert_eq!(&data[0..2], &[0x01, 0]);
+    }
+
+    #[test]
+    fn audio_follow_video_crossfade() {
+        let data = command_data(create_set_audio_follow_video_crossfade_transition(true));
+        assert_eq!(data, [0x01, 1, 0, 0]);
+        assert!(matches!(
+            decode(b"FMPP", &[1, 0, 0, 0]).first(),
+            Some(Payload::FairlightMasterCrossfade(true))
+        ));
+    }
+}
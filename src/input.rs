@@ -0,0 +1,122 @@
+use crate::atem_command::{byte_at, fixed_string_at, word_at};
+use crate::protocol_version::ProtocolVersion;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InternalPortType {
+    External,
+    Black,
+    ColorBars,
+    ColorGenerator,
+    MediaPlayerFill,
+    MediaPlayerKey,
+    SuperSource,
+    MeOutput,
+    Auxiliary,
+    Mask,
+    Multiview,
+    Unknown(u8),
+}
+
+impl InternalPortType {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0 => InternalPortType::External,
+            1 => InternalPortType::Black,
+            2 => InternalPortType::ColorBars,
+            3 => InternalPortType::ColorGenerator,
+            4 => InternalPortType::MediaPlayerFill,
+            5 => InternalPortType::MediaPlayerKey,
+            6 => InternalPortType::SuperSource,
+            128 => InternalPortType::MeOutput,
+            129 => InternalPortType::Auxiliary,
+            130 => InternalPortType::Mask,
+            131 => InternalPortType::Multiview,
+            o => InternalPortType::Unknown(o),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            InternalPortType::External => 0,
+            InternalPortType::Black => 1,
+            InternalPortType::ColorBars => 2,
+            InternalPortType::ColorGenerator => 3,
+            InternalPortType::MediaPlayerFill => 4,
+            InternalPortType::MediaPlayerKey => 5,
+            InternalPortType::SuperSource => 6,
+            InternalPortType::MeOutput => 128,
+            InternalPortType::Auxiliary => 129,
+            InternalPortType::Mask => 130,
+            InternalPortType::Multiview => 131,
+            InternalPortType::Unknown(o) => o,
+        }
+    }
+}
+
+/// Name and routing of one source (InPr).
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputProperties {
+    pub long_name: String,
+    pub short_name: String,
+    /// Always false before firmware 8.0.
+    pub are_names_default: bool,
+    /// 1 SDI, 2 HDMI, 4 component, 8 composite, 16 S-Video
+    pub external_port_type: u16,
+    pub internal_port_type: InternalPortType,
+    /// Bit mask of where the source can be used: 1 aux, 2 multiview, 4 SuperSource art, 8 SuperSource box, 16 key source
+    pub source_availability: u8,
+    /// Bit mask of the MEs the source can be used on.
+    pub me_availability: u8,
+}
+
+impl InputProperties {
+    pub(crate) fn from_data(data: &[u8], version: Option<ProtocolVersion>) -> Self {
+        let long_name = fixed_string_at(data, 2, 20);
+        let short_name = fixed_string_at(data, 22, 4);
+        if ProtocolVersion::has_v8_layouts(version) {
+            Self {
+                long_name,
+                short_name,
+                are_names_default: byte_at(data, 26) > 0,
+                external_port_type: word_at(data, 30),
+                internal_port_type: InternalPortType::from_u8(byte_at(data, 32)),
+                source_availability: byte_at(data, 34),
+                me_availability: byte_at(data, 35),
+            }
+        } else {
+            Self {
+                long_name,
+                short_name,
+                are_names_default: false,
+                external_port_type: byte_at(data, 29) as u16,
+                internal_port_type: InternalPortType::from_u8(byte_at(data, 30)),
+                source_availability: byte_at(data, 32),
+                me_availability: byte_at(data, 33),
+            }
+        }
+    }
+    /// The InPr data for source `input` as a switcher speaking `version` sends it.
+    pub(crate) fn to_data(&self, input: u16, version: Option<ProtocolVersion>) -> Vec<u8> {
+        let mut data = vec![0; 36];
+        data[0..2].copy_from_slice(&input.to_be_bytes());
+        let long = self.long_name.as_bytes();
+        let long = &long[..long.len().min(19)];
+        data[2..2 + long.len()].copy_from_slice(long);
+        let short = self.short_name.as_bytes();
+        let short = &short[..short.len().min(4)];
+        data[22..22 + short.len()].copy_from_slice(short);
+        if ProtocolVersion::has_v8_layouts(version) {
+            data[26] = self.are_names_default as u8;
+            data[30..32].copy_from_slice(&self.external_port_type.to_be_bytes());
+            data[32] = self.internal_port_type.to_u8();
+            data[34] = self.source_availability;
+            data[35] = self.me_availability;
+        } else {
+            data[29] = self.external_port_type as u8;
+            data[30] = self.internal_port_type.to_u8();
+            data[32] = self.source_availability;
+            data[33] = self.me_availability;
+        }
+        data
+    }
+}
@@ -1,5 +1,86 @@
 mod atem_mini;
 pub use atem_mini::AtemMini;
 
+pub use image::RgbaImage;
+
+mod atem_state;
+pub use atem_state::AtemState;
+
+mod mix_effect;
+pub use mix_effect::{MixEffect, MixEffectState};
+
+mod downstream_keyer;
+pub use downstream_keyer::{DownstreamKeyer, DownstreamKeyerState};
+
+mod fairlight;
+pub use fairlight::{
+    AudioLevels, FairlightCompressor, FairlightEqualizerBand, FairlightEqualizerShape,
+    FairlightExpander, FairlightFrequencyRange, FairlightInput, FairlightInputConfiguration,
+    FairlightInputLevel, FairlightInputProperties, FairlightLimiter, FairlightMaster,
+    FairlightMasterLevels, FairlightMasterProperties, FairlightMixOption, FairlightMonitor,
+    FairlightSolo, FairlightSource, FairlightSourceLevels, FairlightSourceProperties,
+    FairlightState, StereoLevels,
+};
+
+mod media_pool;
+pub use media_pool::{MediaPlayerSource, MediaPoolState, MediaPoolStill, MediaSourceType};
+
+mod video_mode;
+pub use video_mode::VideoMode;
+
+mod timecode;
+pub use timecode::Timecode;
+
+mod streaming;
+pub use streaming::{StreamingService, StreamingState, StreamingStatus};
+
+mod recording;
+pub use recording::{RecordingDisk, RecordingSettings, RecordingState, RecordingStatus};
+
+mod camera_control;
+pub use camera_control::{CameraControlData, CameraState, Rgby};
+
+mod multiview;
+pub use multiview::{
+    Multiview, MultiviewLayout, MultiviewState, MultiviewWindow, MULTIVIEW_BOTTOM_LEFT_SMALL,
+    MULTIVIEW_BOTTOM_RIGHT_SMALL, MULTIVIEW_TOP_LEFT_SMALL, MULTIVIEW_TOP_RIGHT_SMALL,
+};
+
+mod supersource;
+pub use supersource::{
+    SuperSource, SuperSourceArt, SuperSourceArtOption, SuperSourceBox, SuperSourceState,
+};
+
+mod capabilities;
+pub use capabilities::{Capabilities, Topology, Unsupported};
+
+mod protocol_version;
+pub use protocol_version::ProtocolVersion;
+
+mod input;
+pub use input::{InputProperties, InternalPortType};
+
+mod tally;
+pub use tally::{Tally, TallyChange, TallyState};
+
+mod macros;
+pub use macros::{Macro, MacroPlayer, MacroState};
+
+mod server;
+pub use server::{AtemServer, RawChunk, ServerEvent};
+
+mod simulator;
+pub use simulator::{Simulator, SimulatorSetup};
+
+mod capture;
+pub use capture::{parse_capture, read_capture, CaptureWriter, CapturedPacket, Direction, Replay};
+
+mod pcap;
+pub use pcap::{is_pcap, parse_pcap, read_pcap};
+
+mod data_transfer;
+pub use data_transfer::PendingTransfer;
+
 mod atem_command;
 mod payload;
+mod still_image;
@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{byte_at, string_at, word_at};
+
+/// A macro slot (MPrp), `description` is what the switcher calls the body.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Macro {
+    pub is_used: bool,
+    pub name: String,
+    pub description: String,
+}
+
+impl Macro {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        let name_len = word_at(data, 4) as usize;
+        let description_len = word_at(data, 6) as usize;
+        let string = |start: usize, len: usize| {
+            if len > 0 && data.len() >= start + len {
+                string_at(data, start, Some(len))
+            } else {
+                String::new()
+            }
+        };
+        Self {
+            is_used: byte_at(data, 2) > 0,
+            name: string(8, name_len),
+            description: string(8 + name_len, description_len),
+        }
+    }
+}
+
+/// What the macro player is doing (MRPr).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MacroPlayer {
+    pub running: Option<u16>,
+    /// The running macro waits for user input.
+    pub waiting: bool,
+    pub looping: bool,
+}
+
+impl MacroPlayer {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        let status = byte_at(data, 0);
+        Self {
+            running: (status & 0x01 != 0).then(|| word_at(data, 2)),
+            waiting: status & 0x02 != 0,
+            looping: byte_at(data, 1) > 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MacroState {
+    pub macros: BTreeMap<u16, Macro>,
+    pub player: MacroPlayer,
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{byte_at, string_at, AtemCommand};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaSourceType {
+    Still,
+    Clip,
+    Unknown(u8),
+}
+
+impl MediaSourceType {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => MediaSourceType::Still,
+            2 => MediaSourceType::Clip,
+            o => MediaSourceType::Unknown(o),
+        }
+    }
+}
+
+/// One frame slot in the media pool (MPfe).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaPoolStill {
+    pub is_used: bool,
+    pub hash: [u8; 16],
+    pub filename: String,
+}
+
+impl MediaPoolStill {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        let mut hash = [0; 16];
+        if data.len() >= 21 {
+            hash.copy_from_slice(&data[5..21]);
+        }
+        let filename_len = byte_at(data, 23) as usize;
+        let filename = if filename_len > 0 && data.len() >= 24 + filename_len {
+            string_at(data, 24, Some(filename_len))
+        } else {
+            String::new()
+        };
+        Self {
+            is_used: byte_at(data, 4) > 0,
+            hash,
+            filename,
+        }
+    }
+}
+
+/// What a media player has loaded (MPCE).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaPlayerSource {
+    pub source_type: MediaSourceType,
+    pub still_index: u8,
+    pub clip_index: u8,
+}
+
+impl MediaPlayerSource {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            source_type: MediaSourceType::from_u8(byte_at(data, 1)),
+            still_index: byte_at(data, 2),
+            clip_index: byte_at(data, 3),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MediaPoolState {
+    pub still_count: u8,
+    pub clip_count: u8,
+    pub stills: BTreeMap<u16, MediaPoolStill>,
+    pub players: BTreeMap<u8, MediaPlayerSource>,
+}
+
+/// Bank 0 of MPfe holds the stills, the others belong to clips.
+pub(crate) const MEDIA_POOL_STILL_BANK: u8 = 0;
+
+pub(crate) fn create_set_media_player_still(player: u8, still: u8) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"MPSS", 8);
+    let p = c.payload();
+    p.set(0, 0x01 | 0x02);
+    p.set(1, player);
+    p.set(2, 1);
+    p.set(3, still);
+    c
+}
+
+pub(crate) fn create_set_media_player_clip(player: u8, clip: u8) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"MPSS", 8);
+    let p = c.payload();
+    p.set(0, 0x01 | 0x04);
+    p.set(1, player);
+    p.set(2, 2);
+    p.set(4, clip);
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::{command_data, decode};
+    use crate::payload::Payload;
+
+    #[test]
+    fn still_from_data() {
+        let mut data = vec![0; 24];
+        data[2..4].copy_from_slice(&7u16.to_be_bytes());
+        data[4] = 1;
+        data[5..21].copy_from_slice(&[0xab; 16]);
+        data[23] = 8;
+        data.extend_from_slice(b"logo.png");
+
+        let payloads = decode(b"MPfe", &data);
+        let Some(Payload::MediaPoolStill { index, still }) = payloads.first() else {
+            panic!("no MPfe payload: {:?}", payloads);
+        };
+        assert_eq!(*index, 7);
+        assert!(still.is_used);
+        assert_eq!(still.hash, [0xab; 16]);
+        assert_eq!(still.filename, "logo.png");
+
+        // clips are in the other banks
+        data[0] = 1;
+        assert!(decode(b"MPfe", &data).is_empty());
+    }
+
+    #[test]
+    fn player_source_from_data() {
+        let payloads = decode(b"MPCE", &[1, 2, 0, 3]);
+        let Some(Payload::MediaPlayerSource { player, source }) = payloads.first() else {
+            panic!("no MPCE payload: {:?}", payloads);
+        };
+        assert_eq!(*player, 1);
+        assert_eq!(source.source_type, MediaSourceType::Clip);
+        assert_eq!(source.clip_index, 3);
+    }
+
+    #[test]
+    fn player_source_setters() {
+        assert_eq!(
+            command_data(create_set_media_player_still(1, 5)),
+            [0x03, 1, 1, 5, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            command_data(create_set_media_player_clip(1, 2)),
+            [0x05, 1, 2, 0, 2, 0, 0, 0]
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::AtemCommand;
+
+/// Program, preview (PrgI, PrvI) and upstream keyers on air (KeOn) of one ME.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MixEffect {
+    pub program: Option<u16>,
+    pub preview: Option<u16>,
+    pub keyers_on_air: BTreeMap<u8, bool>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MixEffectState {
+    pub mix_effects: BTreeMap<u8, MixEffect>,
+}
+
+pub(crate) fn create_set_program(me: u8, source: u16) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CPgI", 4);
+    let p = c.payload();
+    p.set(0, me);
+    p.set_word(2, source);
+    c
+}
+
+pub(crate) fn create_set_preview(me: u8, source: u16) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CPvI", 4);
+    let p = c.payload();
+    p.set(0, me);
+    p.set_word(2, source);
+    c
+}
+
+pub(crate) fn create_cut(me: u8) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"DCut", 4);
+    c.payload().set(0, me);
+    c
+}
+
+pub(crate) fn create_auto(me: u8) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"DAut", 4);
+    c.payload().set(0, me);
+    c
+}
+
+pub(crate) fn create_set_keyer_on_air(me: u8, keyer: u8, on_air: bool) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CKOn", 4);
+    let p = c.payload();
+    p.set(0, me);
+    p.set(1, keyer);
+    p.set(2, on_air as u8);
+    c
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{byte_at, word_at, AtemCommand};
+
+/// Quadrants of the multiview that are split into four small windows (MvPr).
+///
+/// 0 is the default layout with program and preview on top, the bits are
+/// 1 top left, 2 top right, 4 bottom left and 8 bottom right.
+pub type MultiviewLayout = u8;
+
+pub const MULTIVIEW_TOP_LEFT_SMALL: MultiviewLayout = 1;
+pub const MULTIVIEW_TOP_RIGHT_SMALL: MultiviewLayout = 2;
+pub const MULTIVIEW_BOTTOM_LEFT_SMALL: MultiviewLayout = 4;
+pub const MULTIVIEW_BOTTOM_RIGHT_SMALL: MultiviewLayout = 8;
+
+/// One window of a multiview (MvIn, MvVM, SaMw).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MultiviewWindow {
+    pub source: u16,
+    pub supports_vu_meter: bool,
+    pub supports_safe_area: bool,
+    pub vu_meter_enabled: bool,
+    pub safe_area_enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Multiview {
+    pub layout: MultiviewLayout,
+    pub program_preview_swapped: bool,
+    pub windows: BTreeMap<u8, MultiviewWindow>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MultiviewState {
+    pub multiview_count: u8,
+    pub window_count: u8,
+    pub multiviews: BTreeMap<u8, Multiview>,
+}
+
+impl MultiviewState {
+    pub(crate) fn window_mut(&mut self, multiview: u8, window: u8) -> &mut MultiviewWindow {
+        self.multiviews
+            .entry(multiview)
+            .or_default()
+            .windows
+            .entry(window)
+            .or_default()
+    }
+}
+
+/// Reads source and capabilities of a window from MvIn.
+pub(crate) fn window_source_from_data(data: &[u8]) -> (u16, bool, bool) {
+    (word_at(data, 2), byte_at(data, 4) > 0, byte_at(data, 5) > 0)
+}
+
+pub(crate) fn create_set_properties(
+    multiview: u8,
+    layout: MultiviewLayout,
+    program_preview_swapped: bool,
+) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CMvP", 4);
+    let p = c.payload();
+    p.set(0, 0x01 | 0x02);
+    p.set(1, multiview);
+    p.set(2, layout);
+    p.set(3, program_preview_swapped as u8);
+    c
+}
+
+pub(crate) fn create_set_window_source(multiview: u8, window: u8, source: u16) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CMvI", 4);
+    let p = c.payload();
+    p.set(0, multiview);
+    p.set(1, window);
+    p.set_word(2, source);
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::{command_data, decode};
+    use crate::payload::Payload;
+
+    #[test]
+    fn properties_round_trip() {
+        let layout = MULTIVIEW_TOP_LEFT_SMALL | MULTIVIEW_BOTTOM_RIGHT_SMALL;
+        let data = command_data(create_set_properties(1, layout, true));
+        assert_eq!(data, [0x03, 1, layout, 1]);
+        // MvPr is CMvP without the change flags
+        let payloads = decode(b"MvPr", &data[1..]);
+        assert!(matches!(
+            payloads.first(),
+            Some(Payload::MultiviewProperties {
+                multiview: 1,
+                layout: 9,
+                program_preview_swapped: true
+            })
+        ));
+    }
+
+    #[test]
+    fn window_source_round_trip() {
+        let mut data = command_data(create_set_window_source(0, 5, 3010));
+        assert_eq!(data, [0, 5, 0x0b, 0xc2]);
+        data.extend_from_slice(&[1, 0, 0, 0]);
+        let payloads = decode(b"MvIn", &data);
+        assert!(matches!(
+            payloads.first(),
+            Some(Payload::MultiviewWindowSource {
+                multiview: 0,
+                window: 5,
+                source: 3010,
+                supports_vu_meter: true,
+                supports_safe_area: false
+            })
+        ));
+    }
+
+    #[test]
+    fn window_options_from_data() {
+        assert!(matches!(
+            decode(b"MvVM", &[0, 7, 1, 0]).first(),
+            Some(Payload::MultiviewVuMeter {
+                multiview: 0,
+                window: 7,
+                enabled: true
+            })
+        ));
+        assert!(matches!(
+            decode(b"SaMw", &[1, 2, 0, 0]).first(),
+            Some(Payload::MultiviewSafeArea {
+                multiview: 1,
+                window: 2,
+                enabled: false
+            })
+        ));
+    }
+}
@@ -1,4 +1,210 @@
-#[derive(Debug)]
+use std::collections::BTreeMap;
+
+use crate::camera_control::CameraControlData;
+use crate::capabilities::Topology;
+use crate::downstream_keyer::DownstreamKeyer;
+use crate::fairlight::{
+    FairlightCompressor, FairlightEqualizerBand, FairlightExpander, FairlightInputProperties,
+    FairlightLimiter, FairlightMasterLevels, FairlightMasterProperties, FairlightMonitor,
+    FairlightSolo, FairlightSourceLevels, FairlightSourceProperties,
+};
+use crate::input::InputProperties;
+use crate::macros::{Macro, MacroPlayer};
+use crate::media_pool::{MediaPlayerSource, MediaPoolStill};
+use crate::multiview::MultiviewLayout;
+use crate::protocol_version::ProtocolVersion;
+use crate::recording::{RecordingDisk, RecordingSettings, RecordingStatus};
+use crate::streaming::{StreamingService, StreamingStatus};
+use crate::supersource::{SuperSourceArt, SuperSourceBox};
+use crate::tally::TallyState;
+use crate::timecode::Timecode;
+use crate::video_mode::VideoMode;
+
+#[derive(Debug, Clone)]
 pub enum Payload {
-    KeOn { who: u8, index: u8, state: u8 },
+    KeOn {
+        who: u8,
+        index: u8,
+        state: u8,
+    },
+    FairlightInput {
+        input: u16,
+        properties: FairlightInputProperties,
+    },
+    FairlightSource {
+        input: u16,
+        source: i64,
+        properties: FairlightSourceProperties,
+    },
+    FairlightSourceEqualizerBand {
+        input: u16,
+        source: i64,
+        band: u8,
+        properties: FairlightEqualizerBand,
+    },
+    FairlightSourceCompressor {
+        input: u16,
+        source: i64,
+        compressor: FairlightCompressor,
+    },
+    FairlightSourceLimiter {
+        input: u16,
+        source: i64,
+        limiter: FairlightLimiter,
+    },
+    FairlightSourceExpander {
+        input: u16,
+        source: i64,
+        expander: FairlightExpander,
+    },
+    FairlightMaster(FairlightMasterProperties),
+    FairlightMasterCrossfade(bool),
+    FairlightMasterEqualizerBand {
+        band: u8,
+        properties: FairlightEqualizerBand,
+    },
+    FairlightMasterCompressor(FairlightCompressor),
+    FairlightMasterLimiter(FairlightLimiter),
+    FairlightSourceLevels {
+        input: u16,
+        source: i64,
+        levels: FairlightSourceLevels,
+    },
+    FairlightMasterLevels(FairlightMasterLevels),
+    FairlightMonitor(FairlightMonitor),
+    FairlightSolo(FairlightSolo),
+    MediaPoolConfig {
+        still_count: u8,
+        clip_count: u8,
+    },
+    MediaPoolStill {
+        index: u16,
+        still: MediaPoolStill,
+    },
+    MediaPlayerSource {
+        player: u8,
+        source: MediaPlayerSource,
+    },
+    VideoMode(VideoMode),
+    LockObtained {
+        store_id: u16,
+    },
+    DataTransferContinue {
+        transfer_id: u16,
+        chunk_size: u16,
+        chunk_count: u16,
+    },
+    DataTransferData {
+        transfer_id: u16,
+        data: Vec<u8>,
+    },
+    DataTransferComplete {
+        transfer_id: u16,
+    },
+    DataTransferError {
+        transfer_id: u16,
+        error_code: u8,
+    },
+    StreamingStatus {
+        status: StreamingStatus,
+        error: u16,
+    },
+    StreamingDuration(Timecode),
+    StreamingStats {
+        encoding_bitrate: u32,
+        cache_used: u16,
+    },
+    StreamingService(StreamingService),
+    StreamingAudioBitrates {
+        low: u32,
+        high: u32,
+    },
+    StreamingLowBandwidth(bool),
+    RecordingStatus {
+        status: RecordingStatus,
+        error: u16,
+        recording_time_available: u32,
+    },
+    RecordingDuration(Timecode),
+    RecordingSettings(RecordingSettings),
+    RecordingDisk {
+        disk_id: u32,
+        disk: RecordingDisk,
+    },
+    CameraControl {
+        input: u8,
+        category: u8,
+        parameter: u8,
+        data: CameraControlData,
+    },
+    MultiviewConfig {
+        multiview_count: u8,
+        window_count: u8,
+    },
+    MultiviewProperties {
+        multiview: u8,
+        layout: MultiviewLayout,
+        program_preview_swapped: bool,
+    },
+    MultiviewWindowSource {
+        multiview: u8,
+        window: u8,
+        source: u16,
+        supports_vu_meter: bool,
+        supports_safe_area: bool,
+    },
+    MultiviewVuMeter {
+        multiview: u8,
+        window: u8,
+        enabled: bool,
+    },
+    MultiviewSafeArea {
+        multiview: u8,
+        window: u8,
+        enabled: bool,
+    },
+    SuperSourceConfig {
+        supersource: u8,
+        box_count: u8,
+    },
+    SuperSourceArt {
+        supersource: u8,
+        art: SuperSourceArt,
+    },
+    SuperSourceBox {
+        supersource: u8,
+        index: u8,
+        properties: SuperSourceBox,
+    },
+    ProtocolVersion(ProtocolVersion),
+    Topology(Topology),
+    MixEffectConfig {
+        me: u8,
+        keyer_count: u8,
+    },
+    MacroCount(u8),
+    TallyChannelCount(u16),
+    InputProperties {
+        input: u16,
+        properties: InputProperties,
+    },
+    TallyByIndex(Vec<TallyState>),
+    TallyBySource(BTreeMap<u16, TallyState>),
+    ProgramInput {
+        me: u8,
+        input: u16,
+    },
+    PreviewInput {
+        me: u8,
+        input: u16,
+    },
+    MacroProperties {
+        index: u16,
+        properties: Macro,
+    },
+    MacroPlayer(MacroPlayer),
+    DownstreamKeyer {
+        index: u8,
+        keyer: DownstreamKeyer,
+    },
 }
@@ -0,0 +1,568 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::capture::{CapturedPacket, Direction};
+
+const ATEM_PORT: u16 = 9910;
+
+// file magics as read big endian
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_MICROS_SWAPPED: u32 = 0xd4c3b2a1;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+const PCAP_MAGIC_NANOS_SWAPPED: u32 = 0x4d3cb2a1;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+// pcapng block types
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_PACKET: u32 = 2;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+
+// link types
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+/// Reads numbers in the byte order of the file or section.
+struct Bytes<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Bytes<'_> {
+    fn u16_at(&self, o: usize) -> anyhow::Result<u16> {
+        let b: [u8; 2] = self
+            .data
+            .get(o..o + 2)
+            .ok_or_else(|| anyhow::anyhow!("Capture ends in the middle of a record"))?
+            .try_into()?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32_at(&self, o: usize) -> anyhow::Result<u32> {
+        let b: [u8; 4] = self
+            .data
+            .get(o..o + 4)
+            .ok_or_else(|| anyhow::anyhow!("Capture ends in the middle of a record"))?
+            .try_into()?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn slice(&self, o: usize, len: usize) -> anyhow::Result<&[u8]> {
+        self.data
+            .get(o..o + len)
+            .ok_or_else(|| anyhow::anyhow!("Capture ends in the middle of a record"))
+    }
+}
+
+fn be16(data: &[u8], o: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(o..o + 2)?.try_into().ok()?))
+}
+
+/// Finds the UDP payload to or from the switcher port in a frame of `link_type`.
+fn atem_datagram(link_type: u32, frame: &[u8]) -> Option<(Direction, &[u8])> {
+    let (ethertype, ip) = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut o = 12;
+            let mut ethertype = be16(frame, o)?;
+            while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+                o += 4;
+                ethertype = be16(frame, o)?;
+            }
+            (ethertype, frame.get(o + 2..)?)
+        }
+        LINKTYPE_LINUX_SLL => (be16(frame, 14)?, frame.get(16..)?),
+        LINKTYPE_LINUX_SLL2 => (be16(frame, 0)?, frame.get(20..)?),
+        // BSD loopback, the address family is in host order
+        LINKTYPE_NULL => {
+            let ip = frame.get(4..)?;
+            match ip.first()? >> 4 {
+                4 => (ETHERTYPE_IPV4, ip),
+                _ => (ETHERTYPE_IPV6, ip),
+            }
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => match frame.first()? >> 4 {
+            4 => (ETHERTYPE_IPV4, frame),
+            6 => (ETHERTYPE_IPV6, frame),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let udp = match ethertype {
+        ETHERTYPE_IPV4 => {
+            let header_len = ((ip.first()? & 0x0f) as usize) * 4;
+            let fragment = be16(ip, 6)?;
+            // only whole datagrams, the switchers stay below the MTU
+            if *ip.get(9)? != IP_PROTOCOL_UDP || fragment & 0x3fff != 0 {
+                return None;
+            }
+            let total_len = be16(ip, 2)? as usize;
+            ip.get(header_len..total_len.min(ip.len()))?
+        }
+        ETHERTYPE_IPV6 => {
+            // extension headers are rare on a LAN, skip those packets
+            if *ip.get(6)? != IP_PROTOCOL_UDP {
+                return None;
+            }
+            let payload_len = be16(ip, 4)? as usize;
+            ip.get(40..(40 + payload_len).min(ip.len()))?
+        }
+        _ => return None,
+    };
+
+    let source_port = be16(udp, 0)?;
+    let destination_port = be16(udp, 2)?;
+    let len = (be16(udp, 4)? as usize).clamp(8, udp.len());
+    let payload = udp.get(8..len)?;
+    if destination_port == ATEM_PORT {
+        Some((Direction::Sent, payload))
+    } else if source_port == ATEM_PORT {
+        Some((Direction::Received, payload))
+    } else {
+        None
+    }
+}
+
+/// `original_len` is the frame's length on the wire, `frame` may have been cut at the snapshot length.
+fn push_frame(
+    packets: &mut Vec<CapturedPacket>,
+    link_type: u32,
+    timestamp: Duration,
+    frame: &[u8],
+    original_len: usize,
+) {
+    // the end of a cut off packet is missing, decoding the rest would misread it
+    if frame.len() < original_len {
+        return;
+    }
+    if let Some((direction, data)) = atem_datagram(link_type, frame) {
+        packets.push(CapturedPacket {
+            timestamp,
+            direction,
+            data: data.to_vec(),
+        });
+    }
+}
+
+fn read_classic(data: &[u8], magic: u32) -> anyhow::Result<Vec<CapturedPacket>> {
+    let big_endian = magic == PCAP_MAGIC_MICROS || magic == PCAP_MAGIC_NANOS;
+    let nanos = magic == PCAP_MAGIC_NANOS || magic == PCAP_MAGIC_NANOS_SWAPPED;
+    let bytes = Bytes { data, big_endian };
+    let link_type = bytes.u32_at(20)? & 0x0fff_ffff;
+
+    let mut packets = Vec::new();
+    let mut o = 24;
+    while o + 16 <= data.len() {
+        let seconds = bytes.u32_at(o)? as u64;
+        let fraction = bytes.u32_at(o + 4)? as u64;
+        let captured_len = bytes.u32_at(o + 8)? as usize;
+        let original_len = bytes.u32_at(o + 12)? as usize;
+        let timestamp = if nanos {
+            Duration::from_secs(seconds) + Duration::from_nanos(fraction)
+        } else {
+            Duration::from_secs(seconds) + Duration::from_micros(fraction)
+        };
+        let Ok(frame) = bytes.slice(o + 16, captured_len) else {
+            // cut off while capturing, keep the records that are whole
+            break;
+        };
+        push_frame(&mut packets, link_type, timestamp, frame, original_len);
+        o += 16 + captured_len;
+    }
+    Ok(packets)
+}
+
+struct Interface {
+    link_type: u32,
+    /// Timestamp units per second.
+    units_per_second: u64,
+}
+
+impl Interface {
+    fn timestamp(&self, high: u32, low: u32) -> Duration {
+        let units = ((high as u64) << 32) | low as u64;
+        let seconds = units / self.units_per_second;
+        let rest = units % self.units_per_second;
+        Duration::from_secs(seconds)
+            + Duration::from_nanos(
+                (rest as u128 * 1_000_000_000 / self.units_per_second as u128) as u64,
+            )
+    }
+}
+
+fn read_interface(block: &Bytes) -> anyhow::Result<Interface> {
+    let mut interface = Interface {
+        link_type: block.u16_at(8)? as u32,
+        units_per_second: 1_000_000,
+    };
+    // options follow the fixed part, up to the trailing length
+    let mut o = 16;
+    while o + 4 <= block.data.len() - 4 {
+        let code = block.u16_at(o)?;
+        let len = block.u16_at(o + 2)? as usize;
+        if code == 0 {
+            break;
+        }
+        if code == PCAPNG_OPTION_TSRESOL && len >= 1 {
+            let v = block.slice(o + 4, 1)?[0];
+            let exponent = (v & 0x7f) as u32;
+            interface.units_per_second = if v & 0x80 == 0 {
+                10u64.checked_pow(exponent)
+            } else {
+                2u64.checked_pow(exponent)
+            }
+            .ok_or_else(|| anyhow::anyhow!("Unsupported timestamp resolution {:#x}", v))?;
+        }
+        o += 4 + len.div_ceil(4) * 4;
+    }
+    Ok(interface)
+}
+
+fn read_ng(data: &[u8]) -> anyhow::Result<Vec<CapturedPacket>> {
+    let mut packets = Vec::new();
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut big_endian = false;
+
+    let mut o = 0;
+    while o + 12 <= data.len() {
+        let block_type = u32::from_be_bytes(data[o..o + 4].try_into()?);
+        if block_type == PCAPNG_SECTION_HEADER {
+            // every section brings its own byte order and interfaces
+            let order = Bytes {
+                data: &data[o..],
+                big_endian: true,
+            };
+            big_endian = order.u32_at(8)? == PCAPNG_BYTE_ORDER_MAGIC;
+            interfaces.clear();
+        }
+
+        let header = Bytes {
+            data: &data[o..],
+            big_endian,
+        };
+        let block_type = header.u32_at(0)?;
+        let block_len = header.u32_at(4)? as usize;
+        if block_len < 12 {
+            anyhow::bail!("Broken pcapng block at offset {}", o);
+        }
+        if o + block_len > data.len() {
+            // cut off while capturing, keep the blocks that are whole
+            break;
+        }
+        let block = Bytes {
+            data: &data[o..o + block_len],
+            big_endian,
+        };
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => interfaces.push(read_interface(&block)?),
+            PCAPNG_ENHANCED_PACKET | PCAPNG_PACKET => {
+                // the old packet block has a 16 bit interface id and drop count
+                let interface_id = if block_type == PCAPNG_ENHANCED_PACKET {
+                    block.u32_at(8)? as usize
+                } else {
+                    block.u16_at(8)? as usize
+                };
+                let interface = interfaces.get(interface_id).ok_or_else(|| {
+                    anyhow::anyhow!("Packet for unknown interface {}", interface_id)
+                })?;
+                let timestamp = interface.timestamp(block.u32_at(12)?, block.u32_at(16)?);
+                let captured_len = block.u32_at(20)? as usize;
+                let original_len = block.u32_at(24)? as usize;
+                let frame = block.slice(28, captured_len)?;
+                push_frame(
+                    &mut packets,
+                    interface.link_type,
+                    timestamp,
+                    frame,
+                    original_len,
+                );
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                if block_len < 16 {
+                    anyhow::bail!("Simple packet block at offset {} is too short", o);
+                }
+                let interface = interfaces
+                    .first()
+                    .ok_or_else(|| anyhow::anyhow!("Packet before any interface"))?;
+                // the captured length is whatever the block holds
+                let frame = block.slice(12, block_len - 16)?;
+                let original_len = block.u32_at(8)? as usize;
+                let frame = &frame[..frame.len().min(original_len)];
+                push_frame(
+                    &mut packets,
+                    interface.link_type,
+                    Duration::ZERO,
+                    frame,
+                    original_len,
+                );
+            }
+            _ => {}
+        }
+        o += block_len;
+    }
+    Ok(packets)
+}
+
+/// Reads the switcher traffic (UDP port 9910, both directions) from a pcap or pcapng file.
+///
+/// Packets to port 9910 count as `Sent`, those from it as `Received`, so a `Replay` decodes the
+/// switcher's side. Fragmented IP packets are skipped.
+pub fn read_pcap(path: impl AsRef<Path>) -> anyhow::Result<Vec<CapturedPacket>> {
+    let data = std::fs::read(path)?;
+    parse_pcap(&data)
+}
+
+fn magic(data: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+/// Whether `data` starts like a pcap or pcapng file.
+pub fn is_pcap(data: &[u8]) -> bool {
+    matches!(
+        magic(data),
+        Some(
+            PCAP_MAGIC_MICROS
+                | PCAP_MAGIC_MICROS_SWAPPED
+                | PCAP_MAGIC_NANOS
+                | PCAP_MAGIC_NANOS_SWAPPED
+                | PCAPNG_SECTION_HEADER
+        )
+    )
+}
+
+/// Like `read_pcap`, for a capture already in memory.
+pub fn parse_pcap(data: &[u8]) -> anyhow::Result<Vec<CapturedPacket>> {
+    let magic = magic(data).ok_or_else(|| anyhow::anyhow!("Capture too short"))?;
+    match magic {
+        PCAP_MAGIC_MICROS
+        | PCAP_MAGIC_MICROS_SWAPPED
+        | PCAP_MAGIC_NANOS
+        | PCAP_MAGIC_NANOS_SWAPPED => read_classic(data, magic),
+        PCAPNG_SECTION_HEADER => read_ng(data),
+        _ => anyhow::bail!("Not a pcap or pcapng file"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ethernet, IPv4 and UDP around `payload`.
+    fn frame(source_port: u16, destination_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut f = vec![0; 12];
+        f.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        let total_len = (20 + 8 + payload.len()) as u16;
+        f.extend_from_slice(&[0x45, 0]);
+        f.extend_from_slice(&total_len.to_be_bytes());
+        f.extend_from_slice(&[0, 0, 0x40, 0, 64, IP_PROTOCOL_UDP, 0, 0]);
+        f.extend_from_slice(&[192, 168, 1, 2, 192, 168, 1, 240]);
+        f.extend_from_slice(&source_port.to_be_bytes());
+        f.extend_from_slice(&destination_port.to_be_bytes());
+        f.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        f.extend_from_slice(&[0, 0]);
+        f.extend_from_slice(payload);
+        // ethernet pads short frames
+        f.resize(f.len().max(60), 0);
+        f
+    }
+
+    fn u32_bytes(v: u32, big_endian: bool) -> [u8; 4] {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    }
+
+    fn classic(big_endian: bool, nanos: bool, records: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+        let magic = if nanos {
+            PCAP_MAGIC_NANOS
+        } else {
+            PCAP_MAGIC_MICROS
+        };
+        let mut out = u32_bytes(magic, big_endian).to_vec();
+        let version = if big_endian {
+            [0, 2, 0, 4]
+        } else {
+            [2, 0, 4, 0]
+        };
+        out.extend_from_slice(&version);
+        out.extend_from_slice(&[0; 8]);
+        out.extend_from_slice(&u32_bytes(65535, big_endian));
+        out.extend_from_slice(&u32_bytes(LINKTYPE_ETHERNET, big_endian));
+        for (seconds, fraction, frame) in records {
+            out.extend_from_slice(&u32_bytes(*seconds, big_endian));
+            out.extend_from_slice(&u32_bytes(*fraction, big_endian));
+            out.extend_from_slice(&u32_bytes(frame.len() as u32, big_endian));
+            out.extend_from_slice(&u32_bytes(frame.len() as u32, big_endian));
+            out.extend_from_slice(frame);
+        }
+        out
+    }
+
+    fn records() -> Vec<(u32, u32, Vec<u8>)> {
+        vec![
+            (10, 500, frame(55555, ATEM_PORT, &[1, 2, 3])),
+            (11, 0, frame(53, 53, &[9])),
+            (12, 7, frame(ATEM_PORT, 55555, &[4, 5])),
+        ]
+    }
+
+    #[test]
+    fn classic_little_endian_micros() {
+        let packets = parse_pcap(&classic(false, false, &records())).unwrap();
+        assert_eq!(
+            packets,
+            [
+                CapturedPacket {
+                    timestamp: Duration::new(10, 500_000),
+                    direction: Direction::Sent,
+                    data: vec![1, 2, 3],
+                },
+                CapturedPacket {
+                    timestamp: Duration::new(12, 7_000),
+                    direction: Direction::Received,
+                    data: vec![4, 5],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn classic_big_endian_nanos() {
+        let data = classic(true, true, &records());
+        assert!(is_pcap(&data));
+        let packets = parse_pcap(&data).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].timestamp, Duration::new(10, 500));
+        assert_eq!(packets[1].timestamp, Duration::new(12, 7));
+    }
+
+    #[test]
+    fn classic_truncated() {
+        let data = classic(false, false, &records());
+        // the last record loses its end, its header alone is left
+        let packets = parse_pcap(&data[..data.len() - 10]).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(parse_pcap(&data[..20]).is_err());
+        assert!(!is_pcap(b"1 R 00"));
+    }
+
+    #[test]
+    fn classic_snaplen() {
+        let mut data = classic(false, false, &records());
+        // the first frame was longer on the wire than what got captured
+        let original_len = records()[0].2.len() as u32 + 10;
+        data[36..40].copy_from_slice(&original_len.to_le_bytes());
+        let packets = parse_pcap(&data).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].direction, Direction::Received);
+    }
+
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut body = body.to_vec();
+        body.resize(body.len().div_ceil(4) * 4, 0);
+        let len = (12 + body.len()) as u32;
+        let mut out = block_type.to_le_bytes().to_vec();
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&body);
+        out.extend_from_slice(&len.to_le_bytes());
+        out
+    }
+
+    fn ng() -> Vec<u8> {
+        let mut shb = PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes().to_vec();
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&[0xff; 8]);
+        let mut out = block(PCAPNG_SECTION_HEADER, &shb);
+
+        let mut idb = (LINKTYPE_ETHERNET as u16).to_le_bytes().to_vec();
+        idb.extend_from_slice(&[0, 0]);
+        idb.extend_from_slice(&65535u32.to_le_bytes());
+        // if_tsresol of 10^-9
+        idb.extend_from_slice(&PCAPNG_OPTION_TSRESOL.to_le_bytes());
+        idb.extend_from_slice(&[1, 0, 9, 0, 0, 0]);
+        idb.extend_from_slice(&[0; 4]);
+        out.extend(block(PCAPNG_INTERFACE_DESCRIPTION, &idb));
+
+        let f = frame(ATEM_PORT, 55555, &[4, 5]);
+        let nanos = 1_500_000_000u64;
+        let mut epb = 0u32.to_le_bytes().to_vec();
+        epb.extend_from_slice(&((nanos >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(nanos as u32).to_le_bytes());
+        epb.extend_from_slice(&(f.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&(f.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&f);
+        out.extend(block(PCAPNG_ENHANCED_PACKET, &epb));
+
+        let f = frame(55555, ATEM_PORT, &[1]);
+        let mut spb = (f.len() as u32).to_le_bytes().to_vec();
+        spb.extend_from_slice(&f);
+        out.extend(block(PCAPNG_SIMPLE_PACKET, &spb));
+        out
+    }
+
+    #[test]
+    fn pcapng_blocks() {
+        let data = ng();
+        assert!(is_pcap(&data));
+        let packets = parse_pcap(&data).unwrap();
+        assert_eq!(
+            packets,
+            [
+                CapturedPacket {
+                    timestamp: Duration::from_millis(1500),
+                    direction: Direction::Received,
+                    data: vec![4, 5],
+                },
+                CapturedPacket {
+                    timestamp: Duration::ZERO,
+                    direction: Direction::Sent,
+                    data: vec![1],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pcapng_broken_blocks() {
+        let data = ng();
+        // the simple packet block is cut off
+        assert_eq!(parse_pcap(&data[..data.len() - 8]).unwrap().len(), 1);
+
+        // a simple packet block without room for the original length
+        let mut short = data.clone();
+        short.extend(block(PCAPNG_SIMPLE_PACKET, &[]));
+        let e = parse_pcap(&short).unwrap_err();
+        assert!(e.to_string().contains("too short"), "{}", e);
+
+        // a simple packet block holding less than the original length
+        let mut cut = data.clone();
+        let f = frame(55555, ATEM_PORT, &[1]);
+        let spb = cut.len() - block(PCAPNG_SIMPLE_PACKET, &[0; 4]).len() - f.len();
+        cut[spb + 8..spb + 12].copy_from_slice(&(f.len() as u32 + 4).to_le_bytes());
+        assert_eq!(parse_pcap(&cut).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,44 @@
+/// Protocol version from _ver, the switcher sends it as the very first chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ProtocolVersion {
+    pub const V7_2: ProtocolVersion = ProtocolVersion::new(2, 27);
+    pub const V7_5_2: ProtocolVersion = ProtocolVersion::new(2, 28);
+    pub const V8_0: ProtocolVersion = ProtocolVersion::new(2, 29);
+    pub const V8_0_1: ProtocolVersion = ProtocolVersion::new(2, 30);
+    pub const V8_1_1: ProtocolVersion = ProtocolVersion::new(2, 31);
+    pub const V9_4: ProtocolVersion = ProtocolVersion::new(2, 32);
+    pub const V9_6: ProtocolVersion = ProtocolVersion::new(2, 33);
+
+    /// Oldest firmware we know the chunk layouts of.
+    pub const OLDEST: ProtocolVersion = ProtocolVersion::V7_2;
+    /// Newest firmware we know the chunk layouts of, newer ones are decoded like this.
+    pub const NEWEST: ProtocolVersion = ProtocolVersion::V9_6;
+
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        *self >= Self::OLDEST
+    }
+
+    pub fn is_known(&self) -> bool {
+        (Self::OLDEST..=Self::NEWEST).contains(self)
+    }
+
+    /// Firmware 8.0 reworked _top and InPr, without a _ver we assume the old layouts.
+    pub(crate) fn has_v8_layouts(version: Option<ProtocolVersion>) -> bool {
+        version.is_some_and(|v| v >= Self::V8_0)
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{byte_at, fixed_string_at, long_at, word_at, AtemCommand};
+use crate::timecode::Timecode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingStatus {
+    Idle,
+    Recording,
+    Stopping,
+    Unknown(u16),
+}
+
+impl RecordingStatus {
+    pub(crate) fn from_u16(v: u16) -> Self {
+        match v {
+            0 => RecordingStatus::Idle,
+            1 => RecordingStatus::Recording,
+            128 => RecordingStatus::Stopping,
+            o => RecordingStatus::Unknown(o),
+        }
+    }
+}
+
+const DISK_STATUS_IDLE: u16 = 0x01;
+const DISK_STATUS_UNFORMATTED: u16 = 0x02;
+const DISK_STATUS_ACTIVE: u16 = 0x04;
+const DISK_STATUS_RECORDING: u16 = 0x08;
+const DISK_STATUS_REMOVED: u16 = 0x20;
+
+/// A disk attached to the switcher (RMRD).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordingDisk {
+    pub volume_name: String,
+    /// seconds of recording time left
+    pub recording_time_available: u32,
+    pub status: u16,
+}
+
+impl RecordingDisk {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            volume_name: fixed_string_at(data, 10, 64),
+            recording_time_available: long_at(data, 4),
+            status: word_at(data, 8),
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.status & DISK_STATUS_IDLE > 0
+    }
+    pub fn is_unformatted(&self) -> bool {
+        self.status & DISK_STATUS_UNFORMATTED > 0
+    }
+    pub fn is_active(&self) -> bool {
+        self.status & DISK_STATUS_ACTIVE > 0
+    }
+    pub fn is_recording(&self) -> bool {
+        self.status & DISK_STATUS_RECORDING > 0
+    }
+    pub fn is_removed(&self) -> bool {
+        self.status & DISK_STATUS_REMOVED > 0
+    }
+}
+
+/// Recording settings (RMSu), the working sets are the disks recorded to, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordingSettings {
+    pub filename: String,
+    pub working_set_1_disk_id: u32,
+    pub working_set_2_disk_id: u32,
+    pub record_in_all_cameras: bool,
+}
+
+impl RecordingSettings {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            filename: fixed_string_at(data, 0, 128),
+            working_set_1_disk_id: long_at(data, 128),
+            working_set_2_disk_id: long_at(data, 132),
+            record_in_all_cameras: byte_at(data, 136) > 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RecordingState {
+    pub status: Option<RecordingStatus>,
+    pub error: u16,
+    /// seconds of recording time left on the active disk
+    pub recording_time_available: u32,
+    pub duration: Option<Timecode>,
+    pub settings: Option<RecordingSettings>,
+    pub disks: BTreeMap<u32, RecordingDisk>,
+}
+
+impl RecordingState {
+    pub fn is_recording(&self) -> bool {
+        self.status == Some(RecordingStatus::Recording)
+    }
+}
+
+pub(crate) fn create_set_recording(recording: bool) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"RcTM", 4);
+    c.payload().set(0, recording as u8);
+    c
+}
+
+pub(crate) fn create_set_filename(filename: &str) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CRMS", 144);
+    let p = c.payload();
+    p.set(0, 0x01);
+    let b = filename.as_bytes();
+    p.set_bytes(1, &b[..b.len().min(127)]);
+    c
+}
+
+pub(crate) fn create_switch_disk() -> AtemCommand {
+    AtemCommand::create_command(0, 0, b"RMSw", 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::{command_data, decode};
+    use crate::payload::Payload;
+
+    #[test]
+    fn status_from_data() {
+        let payloads = decode(b"RTMS", &[0, 1, 0, 0, 0, 0, 0x0e, 0x10]);
+        assert!(matches!(
+            payloads.first(),
+            Some(Payload::RecordingStatus {
+                status: RecordingStatus::Recording,
+                error: 0,
+                recording_time_available: 3600
+            })
+        ));
+    }
+
+    #[test]
+    fn disk_from_data() {
+        let mut data = vec![0; 76];
+        data[0..4].copy_from_slice(&2u32.to_be_bytes());
+        data[4..8].copy_from_slice(&7200u32.to_be_bytes());
+        data[8..10].copy_from_slice(&(DISK_STATUS_ACTIVE | DISK_STATUS_RECORDING).to_be_bytes());
+        data[10..18].copy_from_slice(b"Untitled");
+
+        let payloads = decode(b"RMRD", &data);
+        let Some(Payload::RecordingDisk { disk_id, disk }) = payloads.first() else {
+            panic!("no RMRD payload: {:?}", payloads);
+        };
+        assert_eq!(*disk_id, 2);
+        assert_eq!(disk.volume_name, "Untitled");
+        assert_eq!(disk.recording_time_available, 7200);
+        assert!(disk.is_active() && disk.is_recording() && !disk.is_removed());
+    }
+
+    #[test]
+    fn filename_round_trip() {
+        let data = command_data(create_set_filename("Show"));
+        assert_eq!(data.len(), 144);
+        assert_eq!(data[0], 0x01);
+        // CRMS starts like RMSu behind the change flags
+        let payloads = decode(b"RMSu", &data[1..]);
+        let Some(Payload::RecordingSettings(settings)) = payloads.first() else {
+            panic!("no RMSu payload: {:?}", payloads);
+        };
+        assert_eq!(settings.filename, "Show");
+    }
+
+    #[test]
+    fn setters() {
+        assert_eq!(command_data(create_set_recording(true)), [1, 0, 0, 0]);
+        assert_eq!(command_data(create_switch_disk()), [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use tokio::net::UdpSocket;
+
+use crate::atem_command::{
+    word_at, COMMAND_MASK_ACK, COMMAND_MASK_ACK_REQUEST, COMMAND_MASK_HELLO,
+    COMMAND_MASK_REQUEST_NEXT, COMMAND_MASK_RESEND, PACKAGE_ID_MASK, SIZE_OF_HEADER,
+};
+
+/// Chunks are packed into packets up to this size, about what the switchers send.
+const MAX_PACKET_SIZE: usize = 1400;
+
+const TICK: Duration = Duration::from_millis(100);
+const RESEND_AFTER: Duration = Duration::from_millis(500);
+const KEEP_ALIVE: Duration = Duration::from_secs(1);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One command of a packet's payload, e.g. "PrgI" and its data, without interpreting it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawChunk {
+    pub name: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl RawChunk {
+    pub fn new(name: &[u8; 4], data: &[u8]) -> Self {
+        Self {
+            name: *name,
+            data: data.to_vec(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.name).to_string()
+    }
+
+    /// Splits the payload of a packet (everything after the header) into its chunks.
+    pub fn split(payload: &[u8]) -> Vec<RawChunk> {
+        let mut chunks = Vec::new();
+        let mut o = 0;
+        while o + 8 <= payload.len() {
+            let size = word_at(payload, o) as usize;
+            if size < 8 || o + size > payload.len() {
+                break;
+            }
+            let mut name = [0; 4];
+            name.copy_from_slice(&payload[o + 4..o + 8]);
+            chunks.push(RawChunk {
+                name,
+                data: payload[o + 8..o + size].to_vec(),
+            });
+            o += size;
+        }
+        chunks
+    }
+
+    /// Size on the wire, including length and name.
+    pub fn len(&self) -> usize {
+        self.data.len() + 8
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.len() as u16).to_be_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&self.name);
+        out.extend_from_slice(&self.data);
+    }
+}
+
+fn packet(flags: u8, session_id: u16, ack_id: u16, package_id: u16, payload: &[u8]) -> Vec<u8> {
+    let len = (SIZE_OF_HEADER + payload.len()) as u16;
+    let mut p = vec![(flags << 3) | ((len >> 8) as u8 & 0x07), (len & 0xff) as u8];
+    p.extend_from_slice(&session_id.to_be_bytes());
+    p.extend_from_slice(&ack_id.to_be_bytes());
+    p.extend_from_slice(&[0, 0, 0, 0]);
+    p.extend_from_slice(&package_id.to_be_bytes());
+    p.extend_from_slice(payload);
+    p
+}
+
+/// True when `id` is `ack` or comes before it, allowing for the wrap around.
+fn is_covered(id: u16, ack: u16) -> bool {
+    ack.wrapping_sub(id) & PACKAGE_ID_MASK < 0x4000
+}
+
+struct InFlight {
+    package_id: u16,
+    packet: Vec<u8>,
+    sent: Instant,
+}
+
+struct Session {
+    session_id: u16,
+    /// The client acked our hello.
+    established: bool,
+    next_package_id: u16,
+    in_flight: VecDeque<InFlight>,
+    last_remote_id: Option<u16>,
+    last_received: Instant,
+    last_sent: Instant,
+}
+
+/// What happened on the server since the last call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    /// The handshake is done, the client now expects the initial state ending with InCm.
+    Connected(SocketAddr),
+    /// Commands a client sent, already acked.
+    Commands(SocketAddr, Vec<RawChunk>),
+    Disconnected(SocketAddr),
+}
+
+/// The switcher's side of the UDP protocol, for proxies and simulators.
+///
+/// Takes care of the handshake, acks, resends and keep alives. What to send is up to the caller,
+/// which answers `Connected` with the initial state and `Commands` with whatever changed.
+pub struct AtemServer {
+    socket: UdpSocket,
+    sessions: HashMap<SocketAddr, Session>,
+    next_session_id: u16,
+    tick: tokio::time::Interval,
+    lose_next_packet: bool,
+}
+
+impl AtemServer {
+    /// `addr` is where to listen, switchers use port 9910.
+    pub async fn bind(addr: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr).await?,
+            sessions: HashMap::new(),
+            next_session_id: 1,
+            tick: tokio::time::interval(TICK),
+            lose_next_packet: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Skips the first transmission of the next reliable packet, as a bad network would.
+    ///
+    /// It is resent like any packet that wasn't acked, which is what this is for testing.
+    #[doc(hidden)]
+    pub fn lose_next_packet(&mut self) {
+        self.lose_next_packet = true;
+    }
+
+    /// Clients that finished the handshake.
+    pub fn clients(&self) -> Vec<SocketAddr> {
+        self.sessions
+            .iter()
+            .filter(|(_, s)| s.established)
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+
+    /// Waits for the next event, serving the protocol meanwhile.
+    ///
+    /// Cancel safe, so it can sit in a `select!` with whatever feeds the server.
+    pub async fn next_event(&mut self) -> anyhow::Result<ServerEvent> {
+        let mut buf = [0; 2048];
+        loop {
+            tokio::select! {
+                r = self.socket.recv_from(&mut buf) => {
+                    let (n, addr) = r?;
+                    if let Some(event) = self.handle(&buf[..n], addr).await? {
+                        return Ok(event);
+                    }
+                }
+                _ = self.tick.tick() => {
+                    if let Some(event) = self.maintain().await? {
+                        return Ok(event);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle(
+        &mut self,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> anyhow::Result<Option<ServerEvent>> {
+        if buf.len() < SIZE_OF_HEADER {
+            return Ok(None);
+        }
+        let flags = buf[0] >> 3;
+        let session_id = word_at(buf, 2);
+        let ack_id = word_at(buf, 4);
+        let resend_id = word_at(buf, 6);
+        let package_id = word_at(buf, 10);
+
+        if flags & COMMAND_MASK_HELLO > 0 {
+            // a new client, or one that starts over
+            let reply = packet(
+                COMMAND_MASK_HELLO,
+                session_id,
+                0,
+                0,
+                &[0x02, 0, 0, 0, 0, 0, 0, 0],
+            );
+            self.socket.send_to(&reply, addr).await?;
+            let now = Instant::now();
+            let session = Session {
+                session_id: 0x8000 | self.next_session_id,
+                established: false,
+                next_package_id: 1,
+                in_flight: VecDeque::new(),
+                last_remote_id: None,
+                last_received: now,
+                last_sent: now,
+            };
+            self.next_session_id = (self.next_session_id + 1) & PACKAGE_ID_MASK;
+            let was_established = self
+                .sessions
+                .insert(addr, session)
+                .is_some_and(|s| s.established);
+            return Ok(was_established.then_some(ServerEvent::Disconnected(addr)));
+        }
+
+        let Some(session) = self.sessions.get_mut(&addr) else {
+            return Ok(None);
+        };
+        session.last_received = Instant::now();
+
+        if flags & COMMAND_MASK_ACK > 0 {
+            session
+                .in_flight
+                .retain(|p| !is_covered(p.package_id, ack_id));
+            if !session.established {
+                session.established = true;
+                return Ok(Some(ServerEvent::Connected(addr)));
+            }
+        }
+
+        if flags & COMMAND_MASK_REQUEST_NEXT > 0 {
+            for p in session
+                .in_flight
+                .iter_mut()
+                .filter(|p| !is_covered(p.package_id, resend_id.wrapping_sub(1)))
+            {
+                self.socket.send_to(&p.packet, addr).await?;
+                p.sent = Instant::now();
+            }
+        }
+
+        if flags & COMMAND_MASK_ACK_REQUEST > 0 {
+            let ack = packet(COMMAND_MASK_ACK, session.session_id, package_id, 0, &[]);
+            self.socket.send_to(&ack, addr).await?;
+            session.last_sent = Instant::now();
+
+            // resent packets we already handled only get acked again
+            let is_new = session
+                .last_remote_id
+                .is_none_or(|last| !is_covered(package_id, last));
+            if is_new && session.established {
+                session.last_remote_id = Some(package_id);
+                let chunks = RawChunk::split(&buf[SIZE_OF_HEADER..]);
+                if !chunks.is_empty() {
+                    return Ok(Some(ServerEvent::Commands(addr, chunks)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Resends what wasn't acked, keeps idle clients alive and drops the silent ones.
+    async fn maintain(&mut self) -> anyhow::Result<Option<ServerEvent>> {
+        let now = Instant::now();
+        let timed_out = self
+            .sessions
+            .iter()
+            .find(|(_, s)| now.duration_since(s.last_received) > CLIENT_TIMEOUT)
+            .map(|(addr, _)| *addr);
+        if let Some(addr) = timed_out {
+            let session = self.sessions.remove(&addr);
+            return Ok(session
+                .is_some_and(|s| s.established)
+                .then_some(ServerEvent::Disconnected(addr)));
+        }
+
+        let mut keep_alive = Vec::new();
+        for (addr, session) in self.sessions.iter_mut() {
+            for p in session.in_flight.iter_mut() {
+                if now.duration_since(p.sent) > RESEND_AFTER {
+                    p.packet[0] |= COMMAND_MASK_RESEND << 3;
+                    self.socket.send_to(&p.packet, addr).await?;
+                    p.sent = now;
+                }
+            }
+            if session.established && now.duration_since(session.last_sent) > KEEP_ALIVE {
+                keep_alive.push(*addr);
+            }
+        }
+        for addr in keep_alive {
+            self.send_packet(addr, &[]).await?;
+        }
+        Ok(None)
+    }
+
+    /// Sends one reliable packet, it is resent until the client acks it.
+    async fn send_packet(&mut self, addr: SocketAddr, payload: &[u8]) -> anyhow::Result<()> {
+        let Some(session) = self.sessions.get_mut(&addr) else {
+            return Ok(());
+        };
+        let package_id = session.next_package_id;
+        session.next_package_id = (package_id + 1) & PACKAGE_ID_MASK;
+        let p = packet(
+            COMMAND_MASK_ACK_REQUEST,
+            session.session_id,
+            0,
+            package_id,
+            payload,
+        );
+        if !std::mem::take(&mut self.lose_next_packet) {
+            self.socket.send_to(&p, addr).await?;
+        }
+        let now = Instant::now();
+        session.last_sent = now;
+        session.in_flight.push_back(InFlight {
+            package_id,
+            packet: p,
+            sent: now,
+        });
+        Ok(())
+    }
+
+    /// Sends `chunks` to one client, split over as many packets as needed.
+    pub async fn send(&mut self, addr: SocketAddr, chunks: &[RawChunk]) -> anyhow::Result<()> {
+        let mut payload = Vec::new();
+        for chunk in chunks {
+            if !payload.is_empty() && SIZE_OF_HEADER + payload.len() + chunk.len() > MAX_PACKET_SIZE
+            {
+                self.send_packet(addr, &payload).await?;
+                payload.clear();
+            }
+            chunk.write(&mut payload);
+        }
+        if !payload.is_empty() {
+            self.send_packet(addr, &payload).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends `chunks` to every connected client.
+    pub async fn broadcast(&mut self, chunks: &[RawChunk]) -> anyhow::Result<()> {
+        for addr in self.clients() {
+            self.send(addr, chunks).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+use std::net::SocketAddr;
+
+use crate::atem_command::{byte_at, word_at};
+use crate::capabilities::Topology;
+use crate::input::{InputProperties, InternalPortType};
+use crate::protocol_version::ProtocolVersion;
+use crate::server::{AtemServer, RawChunk, ServerEvent};
+
+// StRS and RTMS status values
+const STREAMING_IDLE: u16 = 1;
+const STREAMING_ON_AIR: u16 = 4;
+const RECORDING_IDLE: u16 = 0;
+const RECORDING_ON: u16 = 1;
+
+/// What the simulated switcher has, the default is shaped like an ATEM Mini.
+#[derive(Debug, Clone)]
+pub struct SimulatorSetup {
+    pub protocol_version: ProtocolVersion,
+    pub topology: Topology,
+    /// Long names of the external inputs, which get source ids from 1.
+    pub inputs: Vec<String>,
+    /// Names of the macros in use, by index.
+    pub macros: Vec<String>,
+    pub macro_count: u8,
+    pub has_streaming: bool,
+    pub has_recording: bool,
+    /// Loses the first packet after the initial state, the client only gets the resend.
+    #[doc(hidden)]
+    pub lose_first_update: bool,
+}
+
+impl Default for SimulatorSetup {
+    fn default() -> Self {
+        Self {
+            protocol_version: ProtocolVersion::V8_1_1,
+            topology: Topology {
+                me_count: 1,
+                source_count: 4,
+                dsk_count: 1,
+                aux_count: 1,
+                usk_count: 1,
+                media_player_count: 1,
+                dve_count: 1,
+                ..Default::default()
+            },
+            inputs: (1..=4).map(|i| format!("Camera {}", i)).collect(),
+            macros: Vec::new(),
+            macro_count: 100,
+            has_streaming: true,
+            has_recording: false,
+            lose_first_update: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SimulatedMixEffect {
+    program: u16,
+    preview: u16,
+    keyers_on_air: Vec<bool>,
+}
+
+/// A switcher on the network, for tests and working without the hardware.
+///
+/// Speaks the server side of the protocol through `AtemServer`, sends the initial state to every
+/// client and answers the commands `AtemMini` sends like a switcher would. Transitions happen at
+/// once and macros finish as soon as they start.
+pub struct Simulator {
+    server: AtemServer,
+    setup: SimulatorSetup,
+    mix_effects: Vec<SimulatedMixEffect>,
+    dsks_on_air: Vec<bool>,
+    streaming: bool,
+    recording: bool,
+    lose_next_update: bool,
+}
+
+fn padded(mut data: Vec<u8>) -> Vec<u8> {
+    data.resize(data.len().div_ceil(4) * 4, 0);
+    data
+}
+
+impl Simulator {
+    pub async fn bind(addr: &str, setup: SimulatorSetup) -> anyhow::Result<Self> {
+        // tally follows the first ME
+        if setup.topology.me_count == 0 {
+            anyhow::bail!("A simulated switcher needs at least one ME");
+        }
+        let me = SimulatedMixEffect {
+            program: 1,
+            preview: 2.min(setup.topology.source_count as u16),
+            keyers_on_air: vec![false; setup.topology.usk_count as usize],
+        };
+        Ok(Self {
+            server: AtemServer::bind(addr).await?,
+            mix_effects: vec![me; setup.topology.me_count as usize],
+            dsks_on_air: vec![false; setup.topology.dsk_count as usize],
+            streaming: false,
+            recording: false,
+            lose_next_update: setup.lose_first_update,
+            setup,
+        })
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        self.server.local_addr()
+    }
+
+    fn version(&self) -> Option<ProtocolVersion> {
+        Some(self.setup.protocol_version)
+    }
+
+    fn program_chunk(&self, me: u8) -> RawChunk {
+        let mut data = vec![me, 0];
+        data.extend_from_slice(&self.mix_effects[me as usize].program.to_be_bytes());
+        RawChunk::new(b"PrgI", &data)
+    }
+
+    fn preview_chunk(&self, me: u8) -> RawChunk {
+        let mut data = vec![me, 0];
+        data.extend_from_slice(&self.mix_effects[me as usize].preview.to_be_bytes());
+        RawChunk::new(b"PrvI", &data)
+    }
+
+    fn keyer_chunk(&self, me: u8, keyer: u8) -> RawChunk {
+        let on_air = self.mix_effects[me as usize].keyers_on_air[keyer as usize];
+        RawChunk::new(b"KeOn", &[me, keyer, on_air as u8, 0])
+    }
+
+    fn dsk_chunk(&self, index: u8) -> RawChunk {
+        let on_air = self.dsks_on_air[index as usize];
+        RawChunk::new(b"DskS", &[index, on_air as u8, 0, 0, 0, 0, 0, 0])
+    }
+
+    fn macro_player_chunk(running: Option<u16>) -> RawChunk {
+        let mut data = vec![running.is_some() as u8, 0];
+        data.extend_from_slice(&running.unwrap_or(0).to_be_bytes());
+        RawChunk::new(b"MRPr", &data)
+    }
+
+    fn streaming_chunk(&self) -> RawChunk {
+        let status = if self.streaming {
+            STREAMING_ON_AIR
+        } else {
+            STREAMING_IDLE
+        };
+        let mut data = status.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0, 0]);
+        RawChunk::new(b"StRS", &data)
+    }
+
+    fn recording_chunk(&self) -> RawChunk {
+        let status = if self.recording {
+            RECORDING_ON
+        } else {
+            RECORDING_IDLE
+        };
+        let mut data = status.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 0x0e, 0x10]);
+        RawChunk::new(b"RTMS", &data)
+    }
+
+    /// Program and preview of the first ME light the tally, as on the switchers.
+    fn tally_chunks(&self) -> Vec<RawChunk> {
+        let count = self.setup.topology.source_count as u16;
+        let me = &self.mix_effects[0];
+        let flags =
+            |source: u16| (me.program == source) as u8 | ((me.preview == source) as u8) << 1;
+
+        let mut by_index = count.to_be_bytes().to_vec();
+        by_index.extend((1..=count).map(flags));
+        let mut by_source = count.to_be_bytes().to_vec();
+        for source in 1..=count {
+            by_source.extend_from_slice(&source.to_be_bytes());
+            by_source.push(flags(source));
+        }
+        vec![
+            RawChunk::new(b"TlIn", &padded(by_index)),
+            RawChunk::new(b"TlSr", &padded(by_source)),
+        ]
+    }
+
+    fn initial_state(&self) -> Vec<RawChunk> {
+        let v = self.setup.protocol_version;
+        let mut version = v.major.to_be_bytes().to_vec();
+        version.extend_from_slice(&v.minor.to_be_bytes());
+
+        let mut chunks = vec![
+            RawChunk::new(b"_ver", &version),
+            RawChunk::new(b"_top", &self.setup.topology.to_data(self.version())),
+            RawChunk::new(b"_MAC", &[self.setup.macro_count, 0, 0, 0]),
+        ];
+        let tally_channels = self.setup.topology.source_count as u16;
+        chunks.push(RawChunk::new(
+            b"_TlC",
+            &padded(tally_channels.to_be_bytes().to_vec()),
+        ));
+
+        for (i, name) in self.setup.inputs.iter().enumerate() {
+            let input = i as u16 + 1;
+            let properties = InputProperties {
+                long_name: name.clone(),
+                short_name: format!("CAM{}", input),
+                are_names_default: false,
+                external_port_type: 2,
+                internal_port_type: InternalPortType::External,
+                source_availability: 0x1f,
+                me_availability: 0x01,
+            };
+            chunks.push(RawChunk::new(
+                b"InPr",
+                &properties.to_data(input, self.version()),
+            ));
+        }
+
+        for me in 0..self.mix_effects.len() as u8 {
+            let keyers = self.setup.topology.usk_count;
+            chunks.push(RawChunk::new(b"_MeC", &[me, keyers, 0, 0]));
+            chunks.push(self.program_chunk(me));
+            chunks.push(self.preview_chunk(me));
+            for keyer in 0..keyers {
+                chunks.push(self.keyer_chunk(me, keyer));
+            }
+        }
+        for index in 0..self.dsks_on_air.len() as u8 {
+            chunks.push(self.dsk_chunk(index));
+        }
+
+        for (index, name) in self.setup.macros.iter().enumerate() {
+            let mut data = (index as u16).to_be_bytes().to_vec();
+            data.extend_from_slice(&[1, 0]);
+            data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(name.as_bytes());
+            chunks.push(RawChunk::new(b"MPrp", &padded(data)));
+        }
+        chunks.push(Self::macro_player_chunk(None));
+
+        if self.setup.has_streaming {
+            chunks.push(self.streaming_chunk());
+        }
+        if self.setup.has_recording {
+            chunks.push(self.recording_chunk());
+        }
+        chunks.extend(self.tally_chunks());
+        chunks.push(RawChunk::new(b"InCm", &[1, 0, 0, 0]));
+        chunks
+    }
+
+    fn is_source(&self, source: u16) -> bool {
+        source <= self.setup.topology.source_count as u16
+    }
+
+    /// Changes the state like the switcher would and returns what it reports back.
+    fn apply(&mut self, command: &RawChunk) -> Vec<RawChunk> {
+        let data = &command.data;
+        let me = byte_at(data, 0);
+        let has_me = (me as usize) < self.mix_effects.len();
+        match &command.name {
+            b"CPgI" if has_me && self.is_source(word_at(data, 2)) => {
+                self.mix_effects[me as usize].program = word_at(data, 2);
+                let mut chunks = vec![self.program_chunk(me)];
+                chunks.extend(self.tally_chunks());
+                chunks
+            }
+            b"CPvI" if has_me && self.is_source(word_at(data, 2)) => {
+                self.mix_effects[me as usize].preview = word_at(data, 2);
+                let mut chunks = vec![self.preview_chunk(me)];
+                chunks.extend(self.tally_chunks());
+                chunks
+            }
+            b"DCut" | b"DAut" if has_me => {
+                let m = &mut self.mix_effects[me as usize];
+                std::mem::swap(&mut m.program, &mut m.preview);
+                let mut chunks = vec![self.program_chunk(me), self.preview_chunk(me)];
+                chunks.extend(self.tally_chunks());
+                chunks
+            }
+            b"CKOn" if has_me && byte_at(data, 1) < self.setup.topology.usk_count => {
+                let keyer = byte_at(data, 1);
+                self.mix_effects[me as usize].keyers_on_air[keyer as usize] = byte_at(data, 2) > 0;
+                vec![self.keyer_chunk(me, keyer)]
+            }
+            b"CDsL" if (me as usize) < self.dsks_on_air.len() => {
+                self.dsks_on_air[me as usize] = byte_at(data, 1) > 0;
+                vec![self.dsk_chunk(me)]
+            }
+            b"DDsA" if (me as usize) < self.dsks_on_air.len() => {
+                self.dsks_on_air[me as usize] = !self.dsks_on_air[me as usize];
+                vec![self.dsk_chunk(me)]
+            }
+            b"MAct" if word_at(data, 0) < self.setup.macro_count as u16 => {
+                // 0 runs, everything else stops
+                let index = word_at(data, 0);
+                if byte_at(data, 2) == 0 {
+                    vec![
+                        Self::macro_player_chunk(Some(index)),
+                        Self::macro_player_chunk(None),
+                    ]
+                } else {
+                    vec![Self::macro_player_chunk(None)]
+                }
+            }
+            b"StrR" if self.setup.has_streaming => {
+                self.streaming = byte_at(data, 0) > 0;
+                vec![self.streaming_chunk()]
+            }
+            b"RcTM" if self.setup.has_recording => {
+                self.recording = byte_at(data, 0) > 0;
+                vec![self.recording_chunk()]
+            }
+            _ => {
+                println!("Simulator ignores {} {:?}", command.name(), data);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Serves clients until the socket fails.
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        loop {
+            match self.server.next_event().await? {
+                ServerEvent::Connected(addr) => {
+                    println!("Simulator client {} connected", addr);
+                    let chunks = self.initial_state();
+                    self.server.send(addr, &chunks).await?;
+                }
+                ServerEvent::Commands(_, commands) => {
+                    let mut changes = Vec::new();
+                    for c in &commands {
+                        changes.extend(self.apply(c));
+                    }
+                    if !changes.is_empty() {
+                        if std::mem::take(&mut self.lose_next_update) {
+                            self.server.lose_next_packet();
+                        }
+                        self.server.broadcast(&changes).await?;
+                    }
+                }
+                ServerEvent::Disconnected(addr) => {
+                    println!("Simulator client {} disconnected", addr);
+                }
+            }
+        }
+    }
+}
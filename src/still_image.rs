@@ -0,0 +1,207 @@
+use image::RgbaImage;
+
+// BT.709
+const KR: f32 = 0.2126;
+const KB: f32 = 0.0722;
+const KG: f32 = 1.0 - KR - KB;
+
+/// A run of identical 8 byte words is replaced by this marker, the run length and the word.
+const RLE_MARKER: [u8; 8] = [0xfe; 8];
+
+fn luma(r: f32, g: f32, b: f32) -> f32 {
+    KR * r + KG * g + KB * b
+}
+
+/// Converts to the switcher's native still format.
+///
+/// Every pair of pixels becomes two big endian u32 of 12 bit alpha, 10 bit chroma and 10 bit luma,
+/// the first carrying Cb, the second Cr. All values use the legal (studio) range.
+pub(crate) fn rgba_to_yuv422(image: &RgbaImage) -> Vec<u8> {
+    let pixels = image.as_raw();
+    let mut out = Vec::with_capacity(pixels.len());
+
+    for pair in pixels.chunks_exact(8) {
+        let rgb = |o: usize| {
+            (
+                pair[o] as f32 / 255.0,
+                pair[o + 1] as f32 / 255.0,
+                pair[o + 2] as f32 / 255.0,
+            )
+        };
+        let (r1, g1, b1) = rgb(0);
+        let (r2, g2, b2) = rgb(4);
+
+        let y1 = luma(r1, g1, b1);
+        let y2 = luma(r2, g2, b2);
+
+        // chroma is shared by both pixels
+        let r = (r1 + r2) / 2.0;
+        let b = (b1 + b2) / 2.0;
+        let y = (y1 + y2) / 2.0;
+        let pb = 0.5 * (b - y) / (1.0 - KB);
+        let pr = 0.5 * (r - y) / (1.0 - KR);
+
+        let y1 = (64.0 + 876.0 * y1).round() as u32;
+        let y2 = (64.0 + 876.0 * y2).round() as u32;
+        let cb = (512.0 + 896.0 * pb).round() as u32;
+        let cr = (512.0 + 896.0 * pr).round() as u32;
+        let a1 = (256.0 + 3504.0 * (pair[3] as f32 / 255.0)).round() as u32;
+        let a2 = (256.0 + 3504.0 * (pair[7] as f32 / 255.0)).round() as u32;
+
+        out.extend_from_slice(&((a1 << 20) | (cb << 10) | y1).to_be_bytes());
+        out.extend_from_slice(&((a2 << 20) | (cr << 10) | y2).to_be_bytes());
+    }
+
+    out
+}
+
+/// Inverse of `rgba_to_yuv422()`, `data` has to hold `width` * `height` pixels.
+pub(crate) fn yuv422_to_rgba(data: &[u8], width: u32, height: u32) -> anyhow::Result<RgbaImage> {
+    let expected = (width * height * 4) as usize;
+    if data.len() != expected {
+        anyhow::bail!(
+            "Still has {} bytes, expected {} for {}x{}",
+            data.len(),
+            expected,
+            width,
+            height
+        );
+    }
+
+    let unpack = |w: &[u8]| {
+        let v = u32::from_be_bytes([w[0], w[1], w[2], w[3]]);
+        let a = ((v >> 20) as f32 - 256.0) / 3504.0;
+        let c = (((v >> 10) & 0x3ff) as f32 - 512.0) / 896.0;
+        let y = ((v & 0x3ff) as f32 - 64.0) / 876.0;
+        (a, c, y)
+    };
+    let to_u8 = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+
+    let mut pixels = Vec::with_capacity(expected);
+    for pair in data.chunks_exact(8) {
+        let (a1, pb, y1) = unpack(&pair[0..4]);
+        let (a2, pr, y2) = unpack(&pair[4..8]);
+
+        for (y, a) in [(y1, a1), (y2, a2)] {
+            let r = y + 2.0 * (1.0 - KR) * pr;
+            let b = y + 2.0 * (1.0 - KB) * pb;
+            let g = (y - KR * r - KB * b) / KG;
+            pixels.extend_from_slice(&[to_u8(r), to_u8(g), to_u8(b), to_u8(a)]);
+        }
+    }
+
+    RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("Failed to build {}x{} image", width, height))
+}
+
+/// Compresses runs of identical 8 byte words, the way the switcher expects still data.
+pub(crate) fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let words: Vec<&[u8]> = data.chunks(8).collect();
+    let mut out = Vec::with_capacity(data.len());
+
+    let mut i = 0;
+    while i < words.len() {
+        let w = words[i];
+        let mut run = 1;
+        while i + run < words.len() && words[i + run] == w {
+            run += 1;
+        }
+
+        // a run only pays off once it is longer than the 24 bytes needed to describe it
+        if run > 2 && w.len() == 8 {
+            out.extend_from_slice(&RLE_MARKER);
+            out.extend_from_slice(&(run as u64).to_be_bytes());
+            out.extend_from_slice(w);
+        } else {
+            for _ in 0..run {
+                out.extend_from_slice(w);
+            }
+        }
+        i += run;
+    }
+
+    out
+}
+
+/// Expands `data` into at most `max_len` bytes, the size of the still it holds.
+///
+/// The run counts come from the switcher, so they aren't trusted to fit.
+pub(crate) fn rle_decode(data: &[u8], max_len: usize) -> anyhow::Result<Vec<u8>> {
+    let too_long = || anyhow::anyhow!("Still data expands past {} bytes", max_len);
+    let mut out = Vec::with_capacity(data.len().min(max_len));
+
+    let mut i = 0;
+    while i < data.len() {
+        let w = &data[i..(i + 8).min(data.len())];
+        if w == RLE_MARKER && i + 24 <= data.len() {
+            let mut count = [0; 8];
+            count.copy_from_slice(&data[i + 8..i + 16]);
+            let count = u64::from_be_bytes(count);
+            let value = &data[i + 16..i + 24];
+            let len = usize::try_from(count)
+                .ok()
+                .and_then(|c| c.checked_mul(8))
+                .filter(|len| out.len() + len <= max_len)
+                .ok_or_else(too_long)?;
+            out.extend(value.iter().cycle().take(len));
+            i += 24;
+        } else {
+            if out.len() + w.len() > max_len {
+                return Err(too_long());
+            }
+            out.extend_from_slice(w);
+            i += 8;
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rle_round_trip() {
+        let mut data = vec![1u8; 8];
+        data.extend([2u8; 8 * 100]);
+        data.extend([3u8; 16]);
+        data.extend([1, 2, 3, 4]);
+        let encoded = rle_encode(&data);
+        // 1 word, one run, 2 words too short for a run and the tail
+        assert_eq!(encoded.len(), 8 + 24 + 16 + 4);
+        assert_eq!(&encoded[8..16], &RLE_MARKER);
+        assert_eq!(&encoded[16..24], &100u64.to_be_bytes());
+        assert_eq!(rle_decode(&encoded, data.len()).unwrap(), data);
+        assert!(rle_decode(&encoded, data.len() - 1).is_err());
+    }
+
+    #[test]
+    fn rle_decode_caps_runs() {
+        let mut data = RLE_MARKER.to_vec();
+        data.extend(u64::MAX.to_be_bytes());
+        data.extend([7u8; 8]);
+        assert!(rle_decode(&data, 1920 * 1080 * 4).is_err());
+
+        data[8..16].copy_from_slice(&4u64.to_be_bytes());
+        assert_eq!(rle_decode(&data, 32).unwrap(), vec![7u8; 32]);
+        assert!(rle_decode(&data, 31).is_err());
+    }
+
+    #[test]
+    fn yuv_round_trip() {
+        let image = RgbaImage::from_fn(4, 2, |x, y| {
+            image::Rgba([(x * 60) as u8, (y * 200) as u8, 128, 255])
+        });
+        let data = rgba_to_yuv422(&image);
+        assert_eq!(data.len(), 4 * 2 * 4);
+        let back = yuv422_to_rgba(&data, 4, 2).unwrap();
+        // chroma is shared between neighbours, so close is all we get
+        for (a, b) in image.pixels().zip(back.pixels()) {
+            for c in 0..4 {
+                assert!((a[c] as i32 - b[c] as i32).abs() <= 40, "{:?} {:?}", a, b);
+            }
+        }
+        assert!(yuv422_to_rgba(&data, 4, 4).is_err());
+    }
+}
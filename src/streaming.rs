@@ -0,0 +1,154 @@
+use crate::atem_command::{fixed_string_at, long_at, AtemCommand};
+use crate::timecode::Timecode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamingStatus {
+    Idle,
+    Connecting,
+    Streaming,
+    Stopping,
+    Unknown(u16),
+}
+
+impl StreamingStatus {
+    pub(crate) fn from_u16(v: u16) -> Self {
+        match v {
+            1 => StreamingStatus::Idle,
+            2 => StreamingStatus::Connecting,
+            4 => StreamingStatus::Streaming,
+            32 => StreamingStatus::Stopping,
+            o => StreamingStatus::Unknown(o),
+        }
+    }
+}
+
+/// Where the stream goes (SRSU), `bitrates` are the low and high video bitrates in bit/s.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamingService {
+    pub name: String,
+    pub url: String,
+    pub key: String,
+    pub bitrates: (u32, u32),
+}
+
+impl StreamingService {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            name: fixed_string_at(data, 0, 64),
+            url: fixed_string_at(data, 64, 512),
+            key: fixed_string_at(data, 576, 512),
+            bitrates: (long_at(data, 1088), long_at(data, 1092)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StreamingState {
+    pub status: Option<StreamingStatus>,
+    pub error: u16,
+    pub duration: Option<Timecode>,
+    /// bit/s
+    pub encoding_bitrate: u32,
+    /// percent of the encoder cache in use, anything above 0 means the uplink can't keep up
+    pub cache_used: u16,
+    pub low_bandwidth: bool,
+    pub service: Option<StreamingService>,
+    /// low and high audio bitrates in bit/s
+    pub audio_bitrates: Option<(u32, u32)>,
+}
+
+impl StreamingState {
+    pub fn is_streaming(&self) -> bool {
+        self.status == Some(StreamingStatus::Streaming)
+    }
+}
+
+pub(crate) fn create_set_streaming(streaming: bool) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"StrR", 4);
+    c.payload().set(0, streaming as u8);
+    c
+}
+
+fn set_fixed_string(c: &mut AtemCommand, index: usize, len: usize, s: &str) {
+    let b = s.as_bytes();
+    c.payload().set_bytes(index, &b[..b.len().min(len - 1)]);
+}
+
+pub(crate) fn create_set_service(name: &str, url: &str, key: &str) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CRSS", 1100);
+    c.payload().set(0, 0x01 | 0x02 | 0x04);
+    set_fixed_string(&mut c, 1, 64, name);
+    set_fixed_string(&mut c, 65, 512, url);
+    set_fixed_string(&mut c, 577, 512, key);
+    c
+}
+
+pub(crate) fn create_set_bitrates(low: u32, high: u32) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CRSS", 1100);
+    let p = c.payload();
+    p.set(0, 0x08);
+    p.set_long(1092, low);
+    p.set_long(1096, high);
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::{command_data, decode};
+    use crate::payload::Payload;
+
+    #[test]
+    fn status_from_data() {
+        let payloads = decode(b"StRS", &[0, 4, 0, 0]);
+        assert!(matches!(
+            payloads.first(),
+            Some(Payload::StreamingStatus {
+                status: StreamingStatus::Streaming,
+                error: 0
+            })
+        ));
+
+        let payloads = decode(b"SRST", &[1, 2, 3, 4, 1, 0, 0, 0]);
+        let Some(Payload::StreamingDuration(duration)) = payloads.first() else {
+            panic!("no SRST payload: {:?}", payloads);
+        };
+        assert_eq!(duration.to_string(), "01:02:03;04");
+        assert_eq!(duration.as_secs(), 3723);
+    }
+
+    #[test]
+    fn service_round_trip() {
+        let data = command_data(create_set_service(
+            "YouTube",
+            "rtmp://a.rtmp.youtube.com/live2",
+            "abcd-efgh",
+        ));
+        assert_eq!(data.len(), 1100);
+        assert_eq!(data[0], 0x07);
+        // CRSS is SRSU behind the change flags
+        let payloads = decode(b"SRSU", &data[1..]);
+        let Some(Payload::StreamingService(service)) = payloads.first() else {
+            panic!("no SRSU payload: {:?}", payloads);
+        };
+        assert_eq!(service.name, "YouTube");
+        assert_eq!(service.url, "rtmp://a.rtmp.youtube.com/live2");
+        assert_eq!(service.key, "abcd-efgh");
+    }
+
+    #[test]
+    fn long_service_strings_keep_their_terminator() {
+        let data = command_data(create_set_service(&"n".repeat(100), "", ""));
+        assert_eq!(&data[1..64], "n".repeat(63).as_bytes());
+        assert_eq!(data[64], 0);
+    }
+
+    #[test]
+    fn setters() {
+        assert_eq!(command_data(create_set_streaming(true)), [1, 0, 0, 0]);
+        let data = command_data(create_set_bitrates(4_000_000, 6_000_000));
+        assert_eq!(data[0], 0x08);
+        assert_eq!(long_at(&data, 1092), 4_000_000);
+        assert_eq!(long_at(&data, 1096), 6_000_000);
+    }
+}
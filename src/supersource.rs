@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{byte_at, signed_word_at, word_at, AtemCommand};
+
+// positions are transmitted as hundredths, size and crop as thousandths
+fn raw_to_position(raw: i16) -> f32 {
+    (raw as f32) / 100.0
+}
+
+fn position_to_raw(v: f32) -> i16 {
+    (v * 100.0).round() as i16
+}
+
+fn raw_to_fraction(raw: u16) -> f32 {
+    (raw as f32) / 1000.0
+}
+
+fn fraction_to_raw(v: f32) -> u16 {
+    (v * 1000.0).round() as u16
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuperSourceArtOption {
+    Background,
+    Foreground,
+    Unknown(u8),
+}
+
+impl SuperSourceArtOption {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0 => SuperSourceArtOption::Background,
+            1 => SuperSourceArtOption::Foreground,
+            o => SuperSourceArtOption::Unknown(o),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            SuperSourceArtOption::Background => 0,
+            SuperSourceArtOption::Foreground => 1,
+            SuperSourceArtOption::Unknown(o) => o,
+        }
+    }
+}
+
+/// The art layer behind or in front of the boxes (SSrc).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuperSourceArt {
+    pub fill_source: u16,
+    pub cut_source: u16,
+    pub option: SuperSourceArtOption,
+    pub pre_multiplied: bool,
+    /// 0.0 to 100.0 percent
+    pub clip: f32,
+    /// 0.0 to 100.0 percent
+    pub gain: f32,
+    pub invert: bool,
+}
+
+impl SuperSourceArt {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            fill_source: word_at(data, 2),
+            cut_source: word_at(data, 4),
+            option: SuperSourceArtOption::from_u8(byte_at(data, 6)),
+            pre_multiplied: byte_at(data, 7) > 0,
+            clip: raw_to_fraction(word_at(data, 8)) * 100.0,
+            gain: raw_to_fraction(word_at(data, 10)) * 100.0,
+            invert: byte_at(data, 12) > 0,
+        }
+    }
+}
+
+/// One box of a SuperSource (SSBP).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuperSourceBox {
+    pub enabled: bool,
+    pub source: u16,
+    /// -48.0 to 48.0
+    pub x: f32,
+    /// -27.0 to 27.0
+    pub y: f32,
+    /// 0.07 to 1.0
+    pub size: f32,
+    pub cropped: bool,
+    /// 0.0 to 18.0
+    pub crop_top: f32,
+    /// 0.0 to 18.0
+    pub crop_bottom: f32,
+    /// 0.0 to 32.0
+    pub crop_left: f32,
+    /// 0.0 to 32.0
+    pub crop_right: f32,
+}
+
+impl SuperSourceBox {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            enabled: byte_at(data, 2) > 0,
+            source: word_at(data, 4),
+            x: raw_to_position(signed_word_at(data, 6)),
+            y: raw_to_position(signed_word_at(data, 8)),
+            size: raw_to_fraction(word_at(data, 10)),
+            cropped: byte_at(data, 12) > 0,
+            crop_top: raw_to_fraction(word_at(data, 14)),
+            crop_bottom: raw_to_fraction(word_at(data, 16)),
+            crop_left: raw_to_fraction(word_at(data, 18)),
+            crop_right: raw_to_fraction(word_at(data, 20)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SuperSource {
+    pub box_count: u8,
+    pub art: Option<SuperSourceArt>,
+    pub boxes: BTreeMap<u8, SuperSourceBox>,
+}
+
+/// Only switchers whose topology (_top) reports SuperSources have any.
+#[derive(Debug, Clone, Default)]
+pub struct SuperSourceState {
+    pub count: u8,
+    pub supersources: BTreeMap<u8, SuperSource>,
+}
+
+pub(crate) fn create_set_art(supersource: u8, art: &SuperSourceArt) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CSSc", 16);
+    let p = c.payload();
+    p.set(0, 0x7f);
+    p.set(1, supersource);
+    p.set_word(2, art.fill_source);
+    p.set_word(4, art.cut_source);
+    p.set(6, art.option.to_u8());
+    p.set(7, art.pre_multiplied as u8);
+    p.set_word(8, fraction_to_raw(art.clip / 100.0));
+    p.set_word(10, fraction_to_raw(art.gain / 100.0));
+    p.set(12, art.invert as u8);
+    c
+}
+
+pub(crate) fn create_set_box(supersource: u8, index: u8, b: &SuperSourceBox) -> AtemCommand {
+    let mut c = AtemCommand::create_command(0, 0, b"CSBP", 24);
+    let p = c.payload();
+    p.set_word(0, 0x03ff);
+    p.set(2, supersource);
+    p.set(3, index);
+    p.set(4, b.enabled as u8);
+    p.set_word(6, b.source);
+    p.set_signed_word(8, position_to_raw(b.x));
+    p.set_signed_word(10, position_to_raw(b.y));
+    p.set_word(12, fraction_to_raw(b.size));
+    p.set(14, b.cropped as u8);
+    p.set_word(16, fraction_to_raw(b.crop_top));
+    p.set_word(18, fraction_to_raw(b.crop_bottom));
+    p.set_word(20, fraction_to_raw(b.crop_left));
+    p.set_word(22, fraction_to_raw(b.crop_right));
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atem_command::tests::{command_data, decode};
+    use crate::payload::Payload;
+
+    #[test]
+    fn config_from_data() {
+        assert!(matches!(
+            decode(b"_SSC", &[1, 0, 4, 0]).first(),
+            Some(Payload::SuperSourceConfig {
+                supersource: 1,
+                box_count: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn art_round_trip() {
+        let art = SuperSourceArt {
+            fill_source: 3010,
+            cut_source: 3011,
+            option: SuperSourceArtOption::Foreground,
+            pre_multiplied: true,
+            clip: 25.0,
+            gain: 50.5,
+            invert: false,
+        };
+        let mut data = command_data(create_set_art(1, &art));
+        assert_eq!(data.len(), 16);
+        // SSrc matches CSSc with the supersource in place of the change flags
+        data[0] = data[1];
+        let payloads = decode(b"SSrc", &data);
+        let Some(Payload::SuperSourceArt {
+            supersource,
+            art: decoded,
+        }) = payloads.first()
+        else {
+            panic!("no SSrc payload: {:?}", payloads);
+        };
+        assert_eq!(*supersource, 1);
+        assert_eq!(*decoded, art);
+    }
+
+    #[test]
+    fn box_round_trip() {
+        let b = SuperSourceBox {
+            enabled: true,
+            source: 5,
+            x: -12.5,
+            y: 8.0,
+            size: 0.5,
+            cropped: true,
+            crop_top: 1.0,
+            crop_bottom: 2.5,
+            crop_left: 0.0,
+            crop_right: 31.0,
+        };
+        let data = command_data(create_set_box(0, 3, &b));
+        assert_eq!(&data[0..2], &[0x03, 0xff]);
+        // SSBP is CSBP without the change flags
+        let payloads = decode(b"SSBP", &data[2..]);
+        let Some(Payload::SuperSourceBox {
+            supersource,
+            index,
+            properties,
+        }) = payloads.first()
+        else {
+            panic!("no SSBP payload: {:?}", payloads);
+        };
+        assert_eq!((*supersource, *index), (0, 3));
+        assert_eq!(*properties, b);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use crate::atem_command::{byte_at, word_at};
+
+const TALLY_PROGRAM: u8 = 0x01;
+const TALLY_PREVIEW: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TallyState {
+    pub program: bool,
+    pub preview: bool,
+}
+
+impl TallyState {
+    fn from_u8(v: u8) -> Self {
+        Self {
+            program: v & TALLY_PROGRAM != 0,
+            preview: v & TALLY_PREVIEW != 0,
+        }
+    }
+}
+
+/// Tally lights as the switcher reports them.
+///
+/// `by_index` follows the tally channels (TlIn), 0 being input 1.
+/// `by_source` is keyed by source id (TlSr) and also covers internal sources.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tally {
+    pub by_index: Vec<TallyState>,
+    pub by_source: BTreeMap<u16, TallyState>,
+}
+
+/// Sent whenever the tally of a source changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TallyChange {
+    pub source: u16,
+    pub old: TallyState,
+    pub new: TallyState,
+}
+
+impl Tally {
+    /// Tally by source id, from TlIn (channel 0 being input 1) for switchers that send no TlSr.
+    fn sources(&self) -> BTreeMap<u16, TallyState> {
+        if !self.by_source.is_empty() {
+            return self.by_source.clone();
+        }
+        (1..).zip(self.by_index.iter().copied()).collect()
+    }
+
+    /// The changes between `self` and `new`, sources that disappeared count as off.
+    pub fn changes(&self, new: &Tally) -> Vec<TallyChange> {
+        let (old_sources, new_sources) = (self.sources(), new.sources());
+        let mut changes = Vec::new();
+        for (source, state) in &new_sources {
+            let old = old_sources.get(source).copied().unwrap_or_default();
+            if old != *state {
+                changes.push(TallyChange {
+                    source: *source,
+                    old,
+                    new: *state,
+                });
+            }
+        }
+        for (source, old) in &old_sources {
+            if !new_sources.contains_key(source) && *old != TallyState::default() {
+                changes.push(TallyChange {
+                    source: *source,
+                    old: *old,
+                    new: TallyState::default(),
+                });
+            }
+        }
+        changes
+    }
+}
+
+/// The count is capped at what `data` holds.
+pub(crate) fn tally_by_index_from_data(data: &[u8]) -> Vec<TallyState> {
+    let count = (word_at(data, 0) as usize).min(data.len().saturating_sub(2));
+    (0..count)
+        .map(|i| TallyState::from_u8(byte_at(data, 2 + i)))
+        .collect()
+}
+
+/// The count is capped at what `data` holds.
+pub(crate) fn tally_by_source_from_data(data: &[u8]) -> BTreeMap<u16, TallyState> {
+    let count = (word_at(data, 0) as usize).min(data.len().saturating_sub(2) / 3);
+    (0..count)
+        .map(|i| {
+            let o = 2 + i * 3;
+            (word_at(data, o), TallyState::from_u8(byte_at(data, o + 2)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: TallyState = TallyState {
+        program: true,
+        preview: false,
+    };
+    const PREVIEW: TallyState = TallyState {
+        program: false,
+        preview: true,
+    };
+
+    #[test]
+    fn by_index_from_data() {
+        assert_eq!(
+            tally_by_index_from_data(&[0, 3, 1, 2, 3]),
+            [
+                PROGRAM,
+                PREVIEW,
+                TallyState {
+                    program: true,
+                    preview: true
+                }
+            ]
+        );
+        // the count claims more channels than were sent
+        assert_eq!(tally_by_index_from_data(&[0, 40, 1, 0]).len(), 2);
+        assert!(tally_by_index_from_data(&[0xff]).is_empty());
+    }
+
+    #[test]
+    fn by_source_from_data() {
+        let data = [0, 2, 0, 1, 1, 0x1b, 0x59, 2];
+        let tally = tally_by_source_from_data(&data);
+        assert_eq!(tally.len(), 2);
+        assert_eq!(tally[&1], PROGRAM);
+        assert_eq!(tally[&7001], PREVIEW);
+
+        // a count larger than the data gives no made up sources
+        let mut data = data.to_vec();
+        data[1] = 200;
+        data.push(0);
+        assert_eq!(tally_by_source_from_data(&data), tally);
+    }
+
+    #[test]
+    fn changes_by_source() {
+        let old = Tally {
+            by_source: BTreeMap::from([(1, PROGRAM), (2, PREVIEW), (3, TallyState::default())]),
+            ..Default::default()
+        };
+        let new = Tally {
+            by_source: BTreeMap::from([(1, PREVIEW), (4, PROGRAM)]),
+            ..Default::default()
+        };
+        let changes = old.changes(&new);
+        let change = |source, old, new| TallyChange { source, old, new };
+        assert_eq!(
+            changes,
+            [
+                change(1, PROGRAM, PREVIEW),
+                change(4, TallyState::default(), PROGRAM),
+                // 2 disappeared while lit, 3 was dark anyway
+                change(2, PREVIEW, TallyState::default()),
+            ]
+        );
+        assert!(new.changes(&new).is_empty());
+    }
+
+    #[test]
+    fn changes_by_index() {
+        let old = Tally {
+            by_index: vec![PROGRAM, PREVIEW],
+            ..Default::default()
+        };
+        let new = Tally {
+            by_index: vec![PREVIEW, TallyState::default(), PROGRAM],
+            ..Default::default()
+        };
+        let sources: Vec<u16> = old.changes(&new).iter().map(|c| c.source).collect();
+        assert_eq!(sources, [1, 2, 3]);
+
+        // TlSr wins once the switcher sends it
+        let both = Tally {
+            by_source: BTreeMap::from([(1, PREVIEW), (2, PREVIEW)]),
+            ..new.clone()
+        };
+        let changes = both.changes(&Tally {
+            by_index: vec![PROGRAM],
+            ..both.clone()
+        });
+        assert!(changes.is_empty());
+    }
+}
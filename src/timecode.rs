@@ -0,0 +1,38 @@
+use crate::atem_command::byte_at;
+
+/// Duration as reported for streaming and recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub drop_frame: bool,
+}
+
+impl Timecode {
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        Self {
+            hours: byte_at(data, 0),
+            minutes: byte_at(data, 1),
+            seconds: byte_at(data, 2),
+            frames: byte_at(data, 3),
+            drop_frame: byte_at(data, 4) > 0,
+        }
+    }
+
+    pub fn as_secs(&self) -> u32 {
+        (self.hours as u32) * 3600 + (self.minutes as u32) * 60 + self.seconds as u32
+    }
+}
+
+impl std::fmt::Display for Timecode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sep = if self.drop_frame { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, sep, self.frames
+        )
+    }
+}
@@ -0,0 +1,54 @@
+/// Video mode as reported in VidM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoMode(pub u8);
+
+impl VideoMode {
+    pub fn name(&self) -> Option<&'static str> {
+        let n = match self.0 {
+            0 => "525i59.94 NTSC",
+            1 => "625i50 PAL",
+            2 => "525i59.94 NTSC 16:9",
+            3 => "625i50 PAL 16:9",
+            4 => "720p50",
+            5 => "720p59.94",
+            6 => "1080i50",
+            7 => "1080i59.94",
+            8 => "1080p23.98",
+            9 => "1080p24",
+            10 => "1080p25",
+            11 => "1080p29.97",
+            12 => "1080p50",
+            13 => "1080p59.94",
+            14 => "2160p23.98",
+            15 => "2160p24",
+            16 => "2160p25",
+            17 => "2160p29.97",
+            18 => "2160p50",
+            19 => "2160p59.94",
+            20 => "4320p23.98",
+            21 => "4320p24",
+            22 => "4320p25",
+            23 => "4320p29.97",
+            24 => "4320p50",
+            25 => "4320p59.94",
+            26 => "1080p30",
+            27 => "1080p60",
+            _ => return None,
+        };
+        Some(n)
+    }
+
+    /// Frame size in pixels, as used for stills in the media pool.
+    pub fn resolution(&self) -> Option<(u32, u32)> {
+        let r = match self.0 {
+            0 | 2 => (720, 486),
+            1 | 3 => (720, 576),
+            4 | 5 => (1280, 720),
+            6..=13 | 26 | 27 => (1920, 1080),
+            14..=19 => (3840, 2160),
+            20..=25 => (7680, 4320),
+            _ => return None,
+        };
+        Some(r)
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use bmda_bridge::{AtemMini, ProtocolVersion, Simulator, SimulatorSetup};
+
+/// Keeps updating until `done` holds, like the bridge's main loop does.
+async fn update_until(atem: &mut AtemMini, done: impl Fn(&AtemMini) -> bool) -> bool {
+    for _ in 0..500 {
+        atem.update().expect("connection lost");
+        if done(atem) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn mini_against_simulator() -> anyhow::Result<()> {
+    let simulator = Simulator::bind("127.0.0.1:0", SimulatorSetup::default()).await?;
+    let addr = simulator.local_addr()?.to_string();
+    tokio::spawn(simulator.run());
+
+    let mut atem = AtemMini::with_address(&addr);
+    atem.connect()?;
+
+    assert!(
+        update_until(&mut atem, |a| a.state().tally.by_index.len() == 4).await,
+        "initial state"
+    );
+    let topology = atem.capabilities().topology()?.clone();
+    assert_eq!(topology.me_count, 1);
+    assert_eq!(topology.source_count, 4);
+    assert_eq!(atem.state().inputs[&3].long_name, "Camera 3");
+    let me = &atem.state().mix_effects.mix_effects[&0];
+    assert_eq!((me.program, me.preview), (Some(1), Some(2)));
+
+    atem.set_program_input(0, 3)?;
+    assert!(
+        update_until(&mut atem, |a| a.state().mix_effects.mix_effects[&0].program
+            == Some(3))
+        .await,
+        "program follows the command"
+    );
+    assert!(update_until(&mut atem, |a| a.state().tally.by_index[2].program).await);
+
+    atem.cut(0)?;
+    assert!(
+        update_until(&mut atem, |a| {
+            let me = &a.state().mix_effects.mix_effects[&0];
+            me.program == Some(2) && me.preview == Some(3)
+        })
+        .await,
+        "cut swaps program and preview"
+    );
+
+    atem.set_dsk_on_air(0, true)?;
+    assert!(update_until(&mut atem, |a| a.state().downstream_keyers.keyers[&0].on_air).await);
+
+    assert!(
+        atem.set_program_input(1, 1).is_err(),
+        "there is only one ME"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn unsupported_protocol_is_refused() -> anyhow::Result<()> {
+    let setup = SimulatorSetup {
+        protocol_version: ProtocolVersion::new(2, 26),
+        ..Default::default()
+    };
+    let simulator = Simulator::bind("127.0.0.1:0", setup).await?;
+    let addr = simulator.local_addr()?.to_string();
+    tokio::spawn(simulator.run());
+
+    let mut atem = AtemMini::with_address(&addr);
+    atem.connect()?;
+    for _ in 0..500 {
+        if let Err(e) = atem.update() {
+            assert_eq!(e.to_string(), "Refusing protocol version 2.26");
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("protocol version 2.26 was accepted");
+}
+
+#[tokio::test]
+async fn lost_packets_are_resent() -> anyhow::Result<()> {
+    let setup = SimulatorSetup {
+        lose_first_update: true,
+        ..Default::default()
+    };
+    let simulator = Simulator::bind("127.0.0.1:0", setup).await?;
+    let addr = simulator.local_addr()?.to_string();
+    tokio::spawn(simulator.run());
+
+    let mut atem = AtemMini::with_address(&addr);
+    atem.connect()?;
+    assert!(update_until(&mut atem, |a| a.state().tally.by_index.len() == 4).await);
+
+    let start = Instant::now();
+    atem.set_program_input(0, 4)?;
+    assert!(
+        update_until(&mut atem, |a| a.state().mix_effects.mix_effects[&0].program
+            == Some(4))
+        .await,
+        "the resend arrives"
+    );
+    // the first transmission never left, so the answer waited for the resend
+    assert!(start.elapsed() >= Duration::from_millis(400));
+    Ok(())
+}
+
+#[tokio::test]
+async fn needs_a_mix_effect() {
+    let mut setup = SimulatorSetup::default();
+    setup.topology.me_count = 0;
+    assert!(Simulator::bind("127.0.0.1:0", setup).await.is_err());
+}